@@ -0,0 +1,101 @@
+//! Throughput of chat messages with a growing number of rooms
+//!
+//! Every sender does what the server does with a chat message: it locks its room, stores the message in the
+//! history, acknowledges it to the author and broadcasts it to the outboxes of the other members.
+//! The rooms have their own locks, so the throughput should grow with the number of rooms,
+//! up to the number of cores.
+//!
+//! Run with `cargo bench --bench rooms`
+
+use std::{sync::Arc, time::Instant};
+
+use rustic_reach::{
+    client::state::ClientState,
+    core::{
+        frame::Frame,
+        messages::{ChatMessage, ServerMessage},
+        outbox::{broadcast_frame, NoMetrics, OutboundConfig, Outbox},
+        room::room::ServerRooms,
+        user::user::User,
+    },
+    utils::traits::SendServerReply,
+};
+
+const MEMBERS_PER_ROOM: usize = 8;
+const MESSAGES_PER_MEMBER: usize = 2_000;
+
+/// Creates a user that has joined the given room, its messages are queued but not sent anywhere
+fn room_member(room_name: &str, member: usize) -> User {
+    let mut user = User::new(Outbox::new(&OutboundConfig::default(), &NoMetrics));
+    user.set_id(format!("{}-member-{}", room_name, member));
+    user.set_user_name(format!("member-{}", member));
+    user.join_room(room_name.to_string());
+    user
+}
+
+/// Sends all messages in every room, returns the amount of messages per second
+async fn run(room_count: usize) -> f64 {
+    let server_rooms = Arc::new(ServerRooms::with_max_room_count(room_count));
+    let mut senders = Vec::new();
+    for room in 0..room_count {
+        let room_name = format!("room-{}", room);
+        let members: Vec<User> = (0..MEMBERS_PER_ROOM)
+            .map(|member| room_member(&room_name, member))
+            .collect();
+        server_rooms
+            .create_public_room(room_name.clone(), MEMBERS_PER_ROOM, &members[0])
+            .unwrap();
+
+        let shared_room = server_rooms.get_room_with_name(&room_name).unwrap();
+        let mut shared_room = shared_room.lock().await;
+        for member in &members {
+            shared_room.add_user(member).unwrap();
+        }
+        senders.extend(members);
+    }
+
+    let start = Instant::now();
+    let mut tasks = Vec::new();
+    for user in senders {
+        let server_rooms = server_rooms.clone();
+        let state = ClientState::new(
+            String::new(),
+            user.get_user_name().to_string(),
+            user.get_latest_room().map(str::to_string),
+        );
+
+        tasks.push(tokio::spawn(async move {
+            for _ in 0..MESSAGES_PER_MEMBER {
+                let message = ChatMessage::create(&state, "Hello there".to_string()).unwrap();
+                let message = ChatMessage::from_user(&user, message).unwrap();
+
+                let room = server_rooms.get_room_with_name(&message.room).unwrap();
+                let mut room = room.lock().await;
+                let recorded = room.record_message(message);
+                let outboxes = room.outboxes_except(&user);
+
+                let ack = ServerMessage::ChatAck {
+                    room: room.name(),
+                    seq: recorded.seq(),
+                };
+                ack.send(user.get_outbox()).await;
+                broadcast_frame(&Frame::chat(&recorded), &outboxes);
+            }
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    let messages = room_count * MEMBERS_PER_ROOM * MESSAGES_PER_MEMBER;
+    messages as f64 / start.elapsed().as_secs_f64()
+}
+
+#[tokio::main]
+async fn main() {
+    println!("{:>6} {:>18}", "rooms", "messages");
+    for room_count in [1, 2, 4, 8, 16] {
+        let throughput = run(room_count).await;
+        println!("{:>6} {:>14.0} msg/s", room_count, throughput);
+    }
+}
@@ -10,6 +10,7 @@ use crate::{
     utils::{
         constants::{INFO_LOG, WARNING_LOG},
         hash::hash_str,
+        sanitize::sanitize_remote_text,
         terminal_ui::TerminalUI,
         traits::JsonSerializing,
     },
@@ -79,7 +80,8 @@ pub async fn auth_user(
                                 if let Ok(mut ui) = terminal_ui.lock() {
                                     ui.add_message(format!(
                                         "{} Ignored unrecognized message: {}",
-                                        *WARNING_LOG, valid_str
+                                        *WARNING_LOG,
+                                        sanitize_remote_text(&valid_str)
                                     ));
                                 }
                                 continue;
@@ -0,0 +1,239 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{core::messages::ChatMessage, utils::time::get_date_time_string};
+
+/// Messages shown before and after every match of a search
+const CONTEXT_MESSAGES: usize = 2;
+
+/// Matches shown by a search, the latest ones are kept
+pub const MAX_SEARCH_MATCHES: usize = 20;
+
+/// One line of the chat log
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct LoggedMessage {
+    /// Local date and time the message was logged, the time stamp of a message has no date
+    pub logged_at: String,
+
+    #[serde(flatten)]
+    pub message: ChatMessage,
+}
+
+/// What the user is searching for with /search
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchQuery {
+    pub text: String,
+    pub room: Option<String>,
+    pub from: Option<String>,
+}
+
+impl SearchQuery {
+    /// Checks if the message is found by the search, the text and the sender are matched without case
+    fn matches(&self, message: &ChatMessage) -> bool {
+        let text_found = message
+            .get_content()
+            .to_lowercase()
+            .contains(&self.text.to_lowercase());
+        let in_room = self.room.as_ref().is_none_or(|room| *room == message.room);
+        let from_sender = self
+            .from
+            .as_ref()
+            .is_none_or(|from| from.to_lowercase() == message.sender.to_lowercase());
+        text_found && in_room && from_sender
+    }
+}
+
+/// Message found by a search, with the messages of the room around it
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchMatch {
+    pub before: Vec<LoggedMessage>,
+    pub found: LoggedMessage,
+    pub after: Vec<LoggedMessage>,
+}
+
+/**
+ * Chat messages of one server, kept by the client in a directory next to its config
+ *
+ * Every room has its own file with a message as JSON per line, only ever appended to.
+ * The log outlives the history of the server, which is lost when the server restarts.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChatLog {
+    dir: PathBuf,
+}
+
+impl ChatLog {
+    /// Log of the server with the given address, in the given chat log directory
+    pub fn new(chat_log_dir: &str, server_address: &str) -> Self {
+        Self {
+            dir: PathBuf::from(chat_log_dir).join(file_name(server_address)),
+        }
+    }
+
+    fn room_file(&self, room: &str) -> PathBuf {
+        self.dir.join(format!("{}.jsonl", file_name(room)))
+    }
+
+    /// Adds the message at the end of the log of its room
+    pub fn append(&self, message: &ChatMessage) -> io::Result<()> {
+        let logged = LoggedMessage {
+            logged_at: get_date_time_string(),
+            message: message.clone(),
+        };
+        let line = serde_json::to_string(&logged)?;
+
+        fs::create_dir_all(&self.dir)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.room_file(&message.room))?;
+        writeln!(file, "{}", line)
+    }
+
+    /// Messages of the room in the order they were logged, lines that can not be read are skipped
+    fn read_room(&self, path: &PathBuf) -> io::Result<Vec<LoggedMessage>> {
+        let content = fs::read_to_string(path)?;
+        Ok(content
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+
+    /// Finds the messages of the query in every room, or in the room of the query
+    ///
+    /// The rooms are searched in the order of their names, at most `MAX_SEARCH_MATCHES` of the latest matches
+    /// are returned. A server without a log has no matches.
+    pub fn search(&self, query: &SearchQuery) -> io::Result<Vec<SearchMatch>> {
+        let mut room_files = match &query.room {
+            Some(room) => vec![self.room_file(room)],
+            None => match fs::read_dir(&self.dir) {
+                Ok(entries) => entries
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|path| path.extension().is_some_and(|ext| ext == "jsonl"))
+                    .collect(),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+                Err(err) => return Err(err),
+            },
+        };
+        room_files.sort();
+
+        let mut matches = Vec::new();
+        for path in room_files {
+            let messages = match self.read_room(&path) {
+                Ok(messages) => messages,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            };
+            for (index, logged) in messages.iter().enumerate() {
+                if !query.matches(&logged.message) {
+                    continue;
+                }
+                let start = index.saturating_sub(CONTEXT_MESSAGES);
+                let end = (index + 1 + CONTEXT_MESSAGES).min(messages.len());
+                matches.push(SearchMatch {
+                    before: messages[start..index].to_vec(),
+                    found: logged.clone(),
+                    after: messages[index + 1..end].to_vec(),
+                });
+            }
+        }
+
+        let skipped = matches.len().saturating_sub(MAX_SEARCH_MATCHES);
+        matches.drain(..skipped);
+        Ok(matches)
+    }
+}
+
+/// Name of a file or directory for the given room or server, characters that are not allowed everywhere are replaced
+fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::state::ClientState;
+
+    fn message(sender: &str, room: &str, content: &str) -> ChatMessage {
+        let state = ClientState::new(String::new(), sender.to_string(), Some(room.to_string()));
+        ChatMessage::create(&state, content.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_search_the_log() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let log = ChatLog::new(dir.path().to_str().unwrap(), "127.0.0.1:8080");
+        for (sender, room, content) in [
+            ("alice", "lobby", "good morning"),
+            ("bob", "lobby", "hi alice"),
+            ("alice", "lobby", "Did the deploy work?"),
+            ("bob", "lobby", "yes"),
+            ("carol", "lobby", "nice"),
+            ("bob", "lobby", "bye"),
+            ("alice", "work/ops", "deploy is done"),
+        ] {
+            log.append(&message(sender, room, content)).unwrap();
+        }
+
+        let query = |text: &str, room: Option<&str>, from: Option<&str>| SearchQuery {
+            text: text.to_string(),
+            room: room.map(str::to_string),
+            from: from.map(str::to_string),
+        };
+
+        // Every room is searched, and the text is matched without case
+        let matches = log.search(&query("DEPLOY", None, None)).unwrap();
+        assert_eq!(matches.len(), 2);
+        assert_eq!(
+            matches[0].found.message.get_content(),
+            "Did the deploy work?"
+        );
+        assert_eq!(matches[1].found.message.room, "work/ops");
+
+        // The messages around the match are shown with it
+        let contents = |messages: &[LoggedMessage]| -> Vec<String> {
+            messages
+                .iter()
+                .map(|logged| logged.message.get_content().to_string())
+                .collect()
+        };
+        assert_eq!(contents(&matches[0].before), ["good morning", "hi alice"]);
+        assert_eq!(contents(&matches[0].after), ["yes", "nice"]);
+        assert!(matches[1].before.is_empty());
+
+        let matches = log
+            .search(&query("deploy", Some("work/ops"), None))
+            .unwrap();
+        assert_eq!(matches.len(), 1);
+        let matches = log.search(&query("", Some("lobby"), Some("Bob"))).unwrap();
+        assert_eq!(matches.len(), 3);
+        assert!(log
+            .search(&query("deploy", Some("dev"), None))
+            .unwrap()
+            .is_empty());
+
+        dir.close().unwrap();
+
+        // Nothing has been logged for the server yet
+        assert!(log.search(&query("deploy", None, None)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_file_names() {
+        assert_eq!(file_name("127.0.0.1:8080"), "127.0.0.1_8080");
+        assert_eq!(file_name("work/../ops"), "work_.._ops");
+    }
+}
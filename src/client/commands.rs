@@ -0,0 +1,197 @@
+use crate::{
+    client::chat_log::SearchQuery,
+    core::messages::Command,
+    utils::args::{parse_server_address, ServerAddress},
+};
+
+/// Commands handled by the client itself, they are never sent to the server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LocalCommand {
+    /// Connect to the given server, or to the default server of the config
+    Connect(Option<ServerAddress>),
+    Disconnect,
+    /// List the servers of the config
+    Servers,
+    /// Show the joined room with the given name or number
+    Switch(String),
+    /// Search the chat log of the server
+    Search(SearchQuery),
+    Exit,
+}
+
+impl LocalCommand {
+    /// Shown together with the commands of the server when typing /help
+    pub const INPUT_COMMANDS: [Self; 6] = [
+        Self::Connect(None),
+        Self::Disconnect,
+        Self::Servers,
+        Self::Switch(String::new()),
+        Self::Search(SearchQuery {
+            text: String::new(),
+            room: None,
+            from: None,
+        }),
+        Self::Exit,
+    ];
+
+    /// Parses the input of the user, None if the input is not a client command
+    ///
+    /// The error tells how the command is used, when the arguments are wrong
+    #[allow(clippy::should_implement_trait)]
+    pub fn from_str(input: &str) -> Option<Result<Self, String>> {
+        let parts: Vec<&str> = input.split_ascii_whitespace().collect();
+        let command = match parts.first() {
+            Some(&"/connect") => match parts[1..] {
+                [] => Ok(LocalCommand::Connect(None)),
+                [address] => parse_server_address(address)
+                    .map(|address| LocalCommand::Connect(Some(address))),
+                _ => Err(format!("Usage: {}", LocalCommand::Connect(None).usage())),
+            },
+            Some(&"/disconnect") => Ok(LocalCommand::Disconnect),
+            Some(&"/servers") => Ok(LocalCommand::Servers),
+            Some(&"/switch") => match parts[1..] {
+                [room] => Ok(LocalCommand::Switch(room.to_owned())),
+                _ => Err(format!(
+                    "Usage: {}",
+                    LocalCommand::Switch(String::new()).usage()
+                )),
+            },
+            Some(&"/search") => parse_search(&parts[1..]).map(LocalCommand::Search),
+            Some(&"/exit") => Ok(LocalCommand::Exit),
+            _ => return None,
+        };
+        Some(command)
+    }
+
+    pub fn usage(&self) -> String {
+        match self {
+            LocalCommand::Connect(_) => "/connect [<server>|host[:port]]".to_owned(),
+            LocalCommand::Disconnect => "/disconnect".to_owned(),
+            LocalCommand::Servers => "/servers".to_owned(),
+            LocalCommand::Switch(_) => "/switch <room_name|number>".to_owned(),
+            LocalCommand::Search(_) => {
+                "/search <text> [--room <room_name>] [--from <user>]".to_owned()
+            }
+            LocalCommand::Exit => "/exit".to_owned(),
+        }
+    }
+
+    pub fn description(&self) -> String {
+        match self {
+            LocalCommand::Connect(_) => {
+                "Connects to a server of the config by name, to the given address, or to the default server".to_owned()
+            }
+            LocalCommand::Disconnect => "Closes the connection to the server".to_owned(),
+            LocalCommand::Servers => "Lists the servers of the config".to_owned(),
+            LocalCommand::Switch(_) => {
+                "Shows a joined room, the rooms are numbered in the order they were joined (also Alt+number)".to_owned()
+            }
+            LocalCommand::Search(_) => {
+                "Searches the messages in the chat log of the server, when chat_log is enabled in the config".to_owned()
+            }
+            LocalCommand::Exit => "Exits the client".to_owned(),
+        }
+    }
+}
+
+/// Parses the arguments of /search, the words that are not options are the text searched for
+fn parse_search(args: &[&str]) -> Result<SearchQuery, String> {
+    let usage = || {
+        format!(
+            "Usage: {}",
+            LocalCommand::Search(SearchQuery::default()).usage()
+        )
+    };
+    let mut query = SearchQuery::default();
+    let mut words = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match *arg {
+            "--room" => query.room = Some(args.next().ok_or_else(usage)?.to_string()),
+            "--from" => query.from = Some(args.next().ok_or_else(usage)?.to_string()),
+            word => words.push(word),
+        }
+    }
+    if words.is_empty() {
+        return Err(usage());
+    }
+    query.text = words.join(" ");
+    Ok(query)
+}
+
+/// Names of the commands of the server and of the client, completed with Tab
+pub fn command_names() -> Vec<String> {
+    let server_usages = Command::INPUT_COMMANDS.map(|command| command.usage());
+    let client_usages = LocalCommand::INPUT_COMMANDS.map(|command| command.usage());
+    server_usages
+        .into_iter()
+        .chain(client_usages)
+        .filter_map(|usage| usage.split_whitespace().next().map(str::to_string))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_local_commands() {
+        assert_eq!(
+            LocalCommand::from_str("/connect"),
+            Some(Ok(LocalCommand::Connect(None)))
+        );
+        assert_eq!(
+            LocalCommand::from_str("/connect chat.example.com:9000"),
+            Some(Ok(LocalCommand::Connect(Some(ServerAddress {
+                host: "chat.example.com".to_string(),
+                port: Some(9000),
+            }))))
+        );
+        assert!(matches!(
+            LocalCommand::from_str("/connect a b"),
+            Some(Err(_))
+        ));
+        assert!(matches!(
+            LocalCommand::from_str("/connect host:port"),
+            Some(Err(_))
+        ));
+        assert_eq!(
+            LocalCommand::from_str("/disconnect"),
+            Some(Ok(LocalCommand::Disconnect))
+        );
+        assert_eq!(
+            LocalCommand::from_str("/servers"),
+            Some(Ok(LocalCommand::Servers))
+        );
+        assert_eq!(
+            LocalCommand::from_str("/switch 2"),
+            Some(Ok(LocalCommand::Switch("2".to_string())))
+        );
+        assert!(matches!(LocalCommand::from_str("/switch"), Some(Err(_))));
+        assert_eq!(
+            LocalCommand::from_str("/search the deploy --from bob --room work"),
+            Some(Ok(LocalCommand::Search(SearchQuery {
+                text: "the deploy".to_string(),
+                room: Some("work".to_string()),
+                from: Some("bob".to_string()),
+            })))
+        );
+        assert!(matches!(
+            LocalCommand::from_str("/search --room work"),
+            Some(Err(_))
+        ));
+        assert!(matches!(
+            LocalCommand::from_str("/search deploy --from"),
+            Some(Err(_))
+        ));
+
+        // Tab completes the commands of the server and of the client
+        let names = command_names();
+        assert!(names.contains(&"/join".to_string()));
+        assert!(names.contains(&"/switch".to_string()));
+
+        // Commands of the server and chat messages are not client commands
+        assert_eq!(LocalCommand::from_str("/join lobby"), None);
+        assert_eq!(LocalCommand::from_str("hello"), None);
+    }
+}
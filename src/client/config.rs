@@ -1,9 +1,54 @@
-use std::{collections::HashMap, fs};
+use std::{collections::HashMap, fs, path::Path};
 
 use serde::{Deserialize, Serialize};
 use serde_yaml::Value;
 
-#[derive(Debug, Deserialize, Serialize)]
+use super::tls::client_tls_config;
+use crate::utils::logging::{LogFormat, LoggingConfig};
+
+/// File the client logs to when no file is given, the terminal is used by the chat
+const DEFAULT_LOG_FILE: &str = "rustic_reach_client.log";
+
+/// Directory of the chat log when no directory is given, next to the config file
+const DEFAULT_CHAT_LOG_DIR: &str = "chat_logs";
+
+fn default_server_port() -> u16 {
+    return 8080;
+}
+
+fn default_logging() -> LoggingConfig {
+    return LoggingConfig {
+        format: LogFormat::Text,
+        file: Some(DEFAULT_LOG_FILE.to_string()),
+        ..LoggingConfig::default()
+    };
+}
+
+fn default_chat_log_dir() -> String {
+    return DEFAULT_CHAT_LOG_DIR.to_string();
+}
+
+/// Local log of the chat messages, searched with /search
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ChatLogConfig {
+    #[serde(default)]
+    enabled: bool,
+
+    /// Directory with a log per server and room, relative to the directory of the config file
+    #[serde(default = "default_chat_log_dir")]
+    dir: String,
+}
+
+impl Default for ChatLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: default_chat_log_dir(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ClientConfig {
     user_name: String,
     hash_pass: String,
@@ -15,8 +60,18 @@ pub struct ClientConfig {
     #[serde(default)]
     default_server: Option<DefaultServer>,
 
+    /// Servers the user can connect to by name
+    #[serde(default)]
+    servers: Vec<ServerProfile>,
+
     #[serde(default)]
     room_aliases: HashMap<String, String>,
+
+    #[serde(default = "default_logging")]
+    logging: LoggingConfig,
+
+    #[serde(default)]
+    chat_log: ChatLogConfig,
 }
 
 impl ClientConfig {
@@ -24,7 +79,7 @@ impl ClientConfig {
         // Check if there is any aliases defined in the config that we could use
         if let Some(given_room_name) = room_name {
             if let Some(name) = self.room_aliases.get(given_room_name) {
-                return &name;
+                return name;
             }
         }
 
@@ -32,6 +87,11 @@ impl ClientConfig {
         &self.user_name
     }
 
+    /// Names used in single rooms instead of the user name, by room
+    pub fn get_room_aliases(&self) -> &HashMap<String, String> {
+        &self.room_aliases
+    }
+
     pub fn get_token(&self) -> &str {
         &self.user_token
     }
@@ -43,42 +103,323 @@ impl ClientConfig {
     pub fn get_validate_server(&self) -> bool {
         self.validate_server_repo
     }
+
+    pub fn get_servers(&self) -> &[ServerProfile] {
+        &self.servers
+    }
+
+    /// Server of the profile with the given name
+    pub fn get_server(&self, name: &str) -> Option<&DefaultServer> {
+        self.servers
+            .iter()
+            .find(|profile| profile.name == name)
+            .map(|profile| &profile.server)
+    }
+
+    /// Server of the profile with the given name, or the server at the given address
+    ///
+    /// A server given by address uses the TLS settings of the default server.
+    pub fn resolve_server(&self, host: &str, port: Option<u16>) -> DefaultServer {
+        if port.is_none() {
+            if let Some(server) = self.get_server(host) {
+                return server.clone();
+            }
+        }
+        DefaultServer::with_address(host.to_string(), port, self.get_default_server())
+    }
+
+    /// Config used while connected to the given server, with the token, the name and the room aliases of the server
+    pub fn with_server(&self, server: &DefaultServer) -> ClientConfig {
+        let mut config = self.clone();
+        if let Some(user_token) = &server.user_token {
+            config.user_token = user_token.clone();
+        }
+        if let Some(user_name) = &server.user_name {
+            config.user_name = user_name.clone();
+        }
+        config.room_aliases.extend(server.room_aliases.clone());
+        config
+    }
+
+    /// Checks for values the client can not run with
+    pub fn validate(&self) -> Result<(), String> {
+        if self.user_name.trim().is_empty() {
+            return Err("user_name can not be empty".to_string());
+        }
+        if let Some(server) = &self.default_server {
+            server.validate("default_server")?;
+        }
+        for (index, profile) in self.servers.iter().enumerate() {
+            if profile.name.is_empty() || profile.name.contains(char::is_whitespace) {
+                return Err(format!(
+                    "servers: '{}' is not a valid name, it is used with /connect <name>",
+                    profile.name
+                ));
+            }
+            if self.servers[..index]
+                .iter()
+                .any(|other| other.name == profile.name)
+            {
+                return Err(format!("servers: {} is defined twice", profile.name));
+            }
+            profile
+                .server
+                .validate(&format!("servers.{}", profile.name))?;
+        }
+        return Ok(());
+    }
+
+    /// Uses the given name instead of the user name of the config, on every server
+    pub fn set_user_name(&mut self, user_name: String) {
+        self.user_name = user_name;
+        let servers = self
+            .default_server
+            .iter_mut()
+            .chain(self.servers.iter_mut().map(|profile| &mut profile.server));
+        for server in servers {
+            server.user_name = None;
+        }
+    }
+
+    /// Uses the given server instead of the default server of the config
+    pub fn set_default_server(&mut self, server: DefaultServer) {
+        self.default_server = Some(server);
+    }
+
+    /// Logs to the given file instead of the file of the config
+    pub fn set_log_file(&mut self, file: String) {
+        self.logging.file = Some(file);
+    }
+
+    /// Logging config of the client, which always logs to a file so the terminal UI stays clean
+    pub fn get_logging(&self) -> LoggingConfig {
+        let mut logging = self.logging.clone();
+        logging
+            .file
+            .get_or_insert_with(|| DEFAULT_LOG_FILE.to_string());
+        logging
+    }
+
+    /// Directory of the chat log, None if the chat log is not enabled
+    pub fn get_chat_log_dir(&self) -> Option<&str> {
+        match self.chat_log.enabled {
+            true => Some(&self.chat_log.dir),
+            false => None,
+        }
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// Websocket scheme used for connecting to a server, `wss` connects over TLS
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scheme {
+    #[default]
+    Ws,
+    Wss,
+}
+
+impl Scheme {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Scheme::Ws => "ws",
+            Scheme::Wss => "wss",
+        }
+    }
+}
+
+/// Server the client connects to, the default server or the server of a profile
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct DefaultServer {
+    #[serde(alias = "host")]
     server_ip: String,
 
+    #[serde(default = "default_server_port", alias = "port")]
+    server_port: u16,
+
     #[serde(default)]
     auto_connect: bool,
+
+    /// Room joined once connected
+    #[serde(default)]
+    auto_join: Option<String>,
+
+    #[serde(default)]
+    scheme: Scheme,
+
+    /// Connects over TLS, the same as `scheme: wss`
+    #[serde(default)]
+    tls: bool,
+
+    /// PEM file with the CA certificates to trust, instead of the public CAs
+    #[serde(default)]
+    ca_file: Option<String>,
+
+    /// SHA-256 fingerprint of the only server certificate to trust, for self signed certificates
+    #[serde(default)]
+    pinned_cert_sha256: Option<String>,
+
+    /// Token used on this server instead of the token of the client
+    #[serde(default)]
+    user_token: Option<String>,
+
+    /// Name used on this server instead of the name of the client
+    #[serde(default)]
+    user_name: Option<String>,
+
+    /// Names used in the rooms of this server, before the room aliases of the client
+    #[serde(default)]
+    room_aliases: HashMap<String, String>,
+}
+
+/// Server the user connects to by name, with `/connect <name>` or `--server <name>`
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ServerProfile {
+    name: String,
+
+    #[serde(flatten)]
+    server: DefaultServer,
+}
+
+impl ServerProfile {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_server(&self) -> &DefaultServer {
+        &self.server
+    }
 }
 
 impl DefaultServer {
+    /// Server at the given address, with the TLS settings of the given server when there is one
+    ///
+    /// The token and the names of the given server are not used, they belong to the given server only.
+    pub fn with_address(host: String, port: Option<u16>, base: Option<&DefaultServer>) -> Self {
+        let mut server = base.cloned().unwrap_or(DefaultServer {
+            server_ip: String::new(),
+            server_port: default_server_port(),
+            auto_connect: true,
+            auto_join: None,
+            scheme: Scheme::default(),
+            tls: false,
+            ca_file: None,
+            pinned_cert_sha256: None,
+            user_token: None,
+            user_name: None,
+            room_aliases: HashMap::new(),
+        });
+        server.server_ip = host;
+        if let Some(port) = port {
+            server.server_port = port;
+        }
+        server.user_token = None;
+        server.user_name = None;
+        server.room_aliases.clear();
+        server
+    }
+
+    /// Checks the server settings, the section is the place of the server in the config
+    fn validate(&self, section: &str) -> Result<(), String> {
+        if self.server_ip.is_empty() {
+            return Err(format!("{}.server_ip can not be empty", section));
+        }
+        if self
+            .user_token
+            .as_ref()
+            .is_some_and(|token| token.is_empty())
+        {
+            return Err(format!("{}.user_token can not be empty", section));
+        }
+        if let Some(user_name) = &self.user_name {
+            if user_name.trim().is_empty() {
+                return Err(format!("{}.user_name can not be empty", section));
+            }
+        }
+        self.tls_config()
+            .map_err(|err| format!("{}: {}", section, err))?;
+        return Ok(());
+    }
+
     pub fn ip(&self) -> String {
         self.server_ip.clone()
     }
+    pub fn port(&self) -> u16 {
+        self.server_port
+    }
+    /// Host and port of the server, shown to the user
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.server_ip, self.server_port)
+    }
     pub fn should_auto_connect(&self) -> bool {
         self.auto_connect
     }
+    pub fn get_auto_join(&self) -> Option<&str> {
+        self.auto_join.as_deref()
+    }
+
+    /// Joins the given room once connected, instead of the room of the config
+    pub fn set_auto_join(&mut self, room: String) {
+        self.auto_join = Some(room);
+    }
+
+    pub fn scheme(&self) -> Scheme {
+        if self.tls {
+            return Scheme::Wss;
+        }
+        self.scheme
+    }
+
+    /// Websocket url of the server
+    pub fn ws_url(&self) -> String {
+        format!(
+            "{}://{}:{}/ws",
+            self.scheme().as_str(),
+            self.server_ip,
+            self.server_port
+        )
+    }
+
+    /// Creates the TLS config for the server, None if the server is not using TLS
+    pub fn tls_config(&self) -> Result<Option<rustls::ClientConfig>, String> {
+        if self.scheme() != Scheme::Wss {
+            return Ok(None);
+        }
+        client_tls_config(self.ca_file.as_deref(), self.pinned_cert_sha256.as_deref()).map(Some)
+    }
 }
 
 pub fn parse_client_config(file_path: &str) -> Option<ClientConfig> {
-    // Try to read to yaml data, if not return none
-    let yaml_data = fs::read_to_string(file_path).ok()?;
-    return parse_client_config_yml(yaml_data);
+    return load_client_config(file_path).ok();
+}
+
+/// Reads and parses the client config file, the error tells what is wrong with the file
+pub fn load_client_config(file_path: &str) -> Result<ClientConfig, String> {
+    let yaml_data = fs::read_to_string(file_path)
+        .map_err(|err| format!("Client config {} could not be read: {}", file_path, err))?;
+    let mut config = load_client_config_yml(&yaml_data)?;
+
+    // The chat log is kept next to the config file, wherever the client is started from
+    if let Some(config_dir) = Path::new(file_path).parent() {
+        config.chat_log.dir = config_dir
+            .join(&config.chat_log.dir)
+            .to_string_lossy()
+            .into_owned();
+    }
+    return Ok(config);
 }
 
+#[cfg(test)]
 fn parse_client_config_yml(yaml_data: String) -> Option<ClientConfig> {
+    return load_client_config_yml(&yaml_data).ok();
+}
+
+fn load_client_config_yml(yaml_data: &str) -> Result<ClientConfig, String> {
     // Parse the client
-    let parsed_yaml: Value = serde_yaml::from_str(&yaml_data).unwrap();
-    if let Some(client) = parsed_yaml.get("client") {
-        let client_data = serde_yaml::from_value(client.clone());
-        // If error, return none
-        return Some(client_data.ok()?);
+    let parsed_yaml: Value = serde_yaml::from_str(yaml_data).map_err(|err| err.to_string())?;
+    match parsed_yaml.get("client") {
+        Some(client) => serde_yaml::from_value(client.clone()).map_err(|err| err.to_string()),
+        None => Err("The config has no client section".to_string()),
     }
-
-    // Was not able to parse the config
-    None
 }
 
 // Unit test for Client config check
@@ -116,15 +457,67 @@ mod tests {
 
         // Checking the default server is present
         assert!(config.default_server.is_some());
-        if let Some(server) = config.default_server {
+        if let Some(server) = &config.default_server {
             assert_eq!(server.server_ip, "127.0.0.1");
-            assert_eq!(server.auto_connect, true);
+            assert!(server.auto_connect);
+            assert_eq!(server.scheme, Scheme::Ws);
         }
 
         assert_eq!(
             config.room_aliases.get("friends"),
             Some(&"elephant321".to_string())
         );
+
+        // Without a logging section, the client logs to the default file
+        let logging = config.get_logging();
+        assert_eq!(logging.file.as_deref(), Some(DEFAULT_LOG_FILE));
+        assert_eq!(logging.format, LogFormat::Text);
+    }
+
+    #[test]
+    fn test_parse_logging_without_file() {
+        let yaml = r#"
+        client:
+            user_name: "zebra123"
+            hash_pass: "asfdgfhgdQESHZDJXK"
+            user_token: "12345678756432134567"
+            logging:
+                level: debug
+        "#;
+
+        // The client still logs to a file, the terminal is used by the chat
+        let config = parse_client_config_yml(yaml.to_string()).unwrap();
+        let logging = config.get_logging();
+        assert_eq!(logging.level, "debug");
+        assert_eq!(logging.file.as_deref(), Some(DEFAULT_LOG_FILE));
+
+        // The chat log is only kept when it is enabled
+        assert_eq!(config.get_chat_log_dir(), None);
+    }
+
+    #[test]
+    fn test_chat_log_next_to_the_config() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file_path = dir.path().join("config.yml");
+        fs::write(
+            &file_path,
+            r#"
+            client:
+              user_name: "zebra123"
+              hash_pass: "asfdgfhgdQESHZDJXK"
+              user_token: "12345678756432134567"
+              chat_log:
+                enabled: true
+            "#,
+        )
+        .unwrap();
+        let config = load_client_config(file_path.to_str().unwrap()).unwrap();
+
+        let expected = dir.path().join(DEFAULT_CHAT_LOG_DIR);
+        assert_eq!(
+            config.get_chat_log_dir(),
+            Some(expected.to_string_lossy().as_ref())
+        );
     }
 
     #[test]
@@ -212,14 +605,14 @@ mod tests {
         let config = result.unwrap();
 
         // Auto validate server should be false
-        assert_eq!(config.validate_server_repo, false);
+        assert!(!config.validate_server_repo);
 
         // Check the valid fields are there
         // The default server is still some, but auto connect can not be provided and be false
         assert!(config.default_server.is_some());
         if let Some(server) = config.default_server {
             assert!(!server.server_ip.is_empty());
-            assert_eq!(server.auto_connect, false);
+            assert!(!server.auto_connect);
         }
 
         // Missing room_aliases should be an empty HashMap
@@ -238,6 +631,112 @@ mod tests {
         assert!(result.is_none()); // Parsing should fail because of missing required fields
     }
 
+    #[test]
+    fn test_parse_tls_server() {
+        let yaml = r#"
+        client:
+          user_name: "zebra123"
+          hash_pass: "asfdgfhgdQESHZDJXK"
+          user_token: "12345678756432134567"
+          default_server:
+            server_ip: chat.example.com
+            scheme: wss
+            pinned_cert_sha256: "ab:cd"
+        "#;
+
+        let config = parse_client_config_yml(yaml.to_string()).unwrap();
+        let server = config.default_server.unwrap();
+        assert_eq!(server.scheme, Scheme::Wss);
+        assert!(server.ca_file.is_none());
+        assert_eq!(server.pinned_cert_sha256.as_deref(), Some("ab:cd"));
+
+        // The fingerprint is too short to be a SHA-256 fingerprint
+        assert!(server.tls_config().is_err());
+    }
+
+    #[test]
+    fn test_parse_server_profiles() {
+        let yaml = r#"
+        client:
+          user_name: "zebra123"
+          hash_pass: "asfdgfhgdQESHZDJXK"
+          user_token: "12345678756432134567"
+          room_aliases:
+            friends: "elephant321"
+            work: "anon"
+          default_server:
+            server_ip: 127.0.0.1
+          servers:
+            - name: staging
+              host: staging.example.com
+              port: 9000
+              user_name: "tester"
+              room_aliases:
+                work: "qa"
+            - name: production
+              host: chat.example.com
+              tls: true
+              user_token: "98765432123456789876"
+        "#;
+
+        let config = parse_client_config_yml(yaml.to_string()).unwrap();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.get_servers().len(), 2);
+
+        // Profiles are found by name, anything else is an address
+        let staging = config.resolve_server("staging", None);
+        assert_eq!(staging.address(), "staging.example.com:9000");
+        assert_eq!(staging.scheme(), Scheme::Ws);
+        let production = config.resolve_server("production", None);
+        assert_eq!(production.ws_url(), "wss://chat.example.com:8080/ws");
+        let other = config.resolve_server("staging", Some(7000));
+        assert_eq!(other.address(), "staging:7000");
+        assert!(other.user_name.is_none());
+
+        // The name, the token and the room aliases of the server come first
+        let staging_config = config.with_server(&staging);
+        assert_eq!(staging_config.get_user_name(&None), "tester");
+        assert_eq!(staging_config.get_token(), "12345678756432134567");
+        assert_eq!(
+            staging_config.get_user_name(&Some("work".to_string())),
+            "qa"
+        );
+        assert_eq!(
+            staging_config.get_user_name(&Some("friends".to_string())),
+            "elephant321"
+        );
+        let production_config = config.with_server(&production);
+        assert_eq!(production_config.get_user_name(&None), "zebra123");
+        assert_eq!(production_config.get_token(), "98765432123456789876");
+
+        // A name given on the command line is used on every server
+        let mut renamed = config.clone();
+        renamed.set_user_name("bob".to_string());
+        let staging = renamed.resolve_server("staging", None);
+        assert_eq!(renamed.with_server(&staging).get_user_name(&None), "bob");
+    }
+
+    #[test]
+    fn test_validate_server_profiles() {
+        let yaml = r#"
+        client:
+          user_name: "zebra123"
+          hash_pass: "asfdgfhgdQESHZDJXK"
+          user_token: "12345678756432134567"
+          servers:
+            - name: staging
+              host: staging.example.com
+            - name: staging
+              host: chat.example.com
+        "#;
+
+        let config = parse_client_config_yml(yaml.to_string()).unwrap();
+        assert_eq!(
+            config.validate(),
+            Err("servers: staging is defined twice".to_string())
+        );
+    }
+
     #[test]
     fn test_parse_empty_yaml() {
         let yaml = r#"
@@ -1,9 +1,10 @@
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
 use rustic_reach::{
-    client::{config::ClientConfig, runtime::connect},
+    client::{config::ClientConfig, runtime::run_client},
     utils::{
-        args::validate_args,
-        constants::{DEFAULT_SERVER_PORT, INFO_LOG, WARNING_LOG},
+        args::{parse_client_args, ClientCommand},
+        constants::{ERROR_LOG, INFO_LOG, WARNING_LOG},
+        logging::init_logging,
         terminal_ui::TerminalUI,
     },
 };
@@ -12,18 +13,76 @@ use std::{
     sync::{Arc, Mutex},
 };
 
+/// Prints the settings the client would run with, for `client check-config`
+fn print_config(file_path: &str, config: &ClientConfig) {
+    println!("{} Client config {} is valid", *INFO_LOG, file_path);
+    println!("  user name: {}", config.get_user_name(&None));
+    match config.get_default_server() {
+        Some(server) => {
+            println!("  server: {}", server.ws_url());
+            println!("  auto connect: {}", server.should_auto_connect());
+            if let Some(room) = server.get_auto_join() {
+                println!("  auto join: {}", room);
+            }
+        }
+        None => println!("  server: none, use --server or /connect"),
+    }
+    for profile in config.get_servers() {
+        println!(
+            "  server {}: {}",
+            profile.get_name(),
+            profile.get_server().ws_url()
+        );
+    }
+    if let Some(log_file) = config.get_logging().file {
+        println!("  log file: {}", log_file);
+    }
+    if let Some(chat_log_dir) = config.get_chat_log_dir() {
+        println!("  chat log: {}", chat_log_dir);
+    }
+}
+
 #[tokio::main]
 async fn main() {
-    let client_config: ClientConfig = validate_args().unwrap_or_else(|message| {
-        println!("{}", message);
+    let client_args = parse_client_args();
+    let options = client_args.get_options();
+
+    // Values given on the command line override the config file
+    let client_config = options
+        .load_config()
+        .and_then(|config| {
+            config
+                .validate()
+                .map_err(|message| format!("{} Invalid client config: {}", *ERROR_LOG, message))?;
+            Ok(config)
+        })
+        .unwrap_or_else(|message| {
+            println!("{}", message);
+            exit(1);
+        });
+
+    if let Some(ClientCommand::CheckConfig(_)) = client_args.command {
+        print_config(&options.config, &client_config);
+        return;
+    }
+
+    // Logs go to a file, anything printed to the terminal would corrupt the UI
+    init_logging(&client_config.get_logging()).unwrap_or_else(|message| {
+        println!("{} {}", *ERROR_LOG, message);
         exit(1);
     });
+    tracing::info!("Client started");
 
-    // Initialize terminal UI
-    enable_raw_mode().unwrap();
+    // Initialize terminal UI, or print plain lines for scripts
+    let terminal_ui = if options.no_tui {
+        TerminalUI::plain()
+    } else {
+        enable_raw_mode().unwrap();
+        TerminalUI::new().unwrap()
+    };
 
     // Create a terminal ui behind mutex
-    let terminal_ui = Arc::new(Mutex::new(TerminalUI::new().unwrap()));
+    let terminal_ui = Arc::new(Mutex::new(terminal_ui));
     if let Ok(mut ui) = terminal_ui.lock() {
         ui.add_message(format!("{} Client config parsed", *INFO_LOG));
     }
@@ -39,24 +98,35 @@ async fn main() {
         }
     }
 
-    // If a default server and auto connect is set to true, then do connection
-    if let Some(server_options) = client_config.get_default_server() {
-        if server_options.should_auto_connect() {
-            println!("{} Auto connecting to default server...", *INFO_LOG);
-            connect(
-                server_options.ip(),
-                DEFAULT_SERVER_PORT.to_string(),
-                client_config.into(),
-                terminal_ui,
-            )
-            .await;
+    // Connect to the server given on the command line, or to the default server when auto connect is set
+    let should_connect = client_args.should_connect();
+    let connect_to = match client_config.get_default_server() {
+        Some(server_options) if should_connect || server_options.should_auto_connect() => {
+            Some(server_options.clone())
         }
-        println!(
-            "{} Default server detected. Use /connect (without args) to connect to the server.",
-            *INFO_LOG
-        );
-    }
+        Some(_) => {
+            if let Ok(mut ui) = terminal_ui.lock() {
+                ui.add_message(format!(
+                    "{} Default server detected. Use /connect (without args) to connect to the server.",
+                    *INFO_LOG
+                ));
+            }
+            None
+        }
+        None => {
+            if let Ok(mut ui) = terminal_ui.lock() {
+                ui.add_message(format!(
+                    "{} No default server, use /connect <server> or /connect <host[:port]> to connect to a server",
+                    *INFO_LOG
+                ));
+            }
+            None
+        }
+    };
+    run_client(connect_to, client_config.into(), terminal_ui).await;
 
     // End of program
-    disable_raw_mode().unwrap();
+    if !options.no_tui {
+        disable_raw_mode().unwrap();
+    }
 }
@@ -1,4 +1,8 @@
 pub mod auth;
+pub mod chat_log;
+pub mod commands;
 pub mod config;
+pub mod reconnect;
 pub mod runtime;
 pub mod state;
+pub mod tls;
@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+/// Exponential backoff with jitter, used between reconnect attempts
+///
+/// The delay doubles for every failed attempt until it reaches the max delay.
+/// Half of the delay is random, so that many clients do not reconnect at the same time after a server restart.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    attempt: u32,
+    base: Duration,
+    max: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(500), Duration::from_secs(30))
+    }
+}
+
+impl Backoff {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self {
+            attempt: 0,
+            base,
+            max,
+        }
+    }
+
+    /// Amount of attempts done since the last reset
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    /// Returns the delay before the next attempt, and counts the attempt
+    pub fn next_delay(&mut self) -> Duration {
+        let exponential = self
+            .base
+            .saturating_mul(2u32.saturating_pow(self.attempt))
+            .min(self.max);
+        self.attempt = self.attempt.saturating_add(1);
+
+        // Equal jitter: keep half of the delay, and randomize the other half
+        let half = exponential / 2;
+        let jitter = rand::rng().random_range(0..=half.as_millis() as u64);
+        half + Duration::from_millis(jitter)
+    }
+
+    /// Start over from the base delay, used after a successful connection
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay_grows_until_max() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_secs(2));
+
+        for attempt in 0..10 {
+            let expected =
+                (Duration::from_millis(100) * 2u32.pow(attempt)).min(Duration::from_secs(2));
+            let delay = backoff.next_delay();
+            assert!(delay >= expected / 2, "delay {:?} too short", delay);
+            assert!(delay <= expected, "delay {:?} too long", delay);
+        }
+        assert_eq!(backoff.attempt(), 10);
+    }
+
+    #[test]
+    fn test_reset_starts_over() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_secs(2));
+        for _ in 0..5 {
+            backoff.next_delay();
+        }
+
+        backoff.reset();
+        assert_eq!(backoff.attempt(), 0);
+        assert!(backoff.next_delay() <= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_many_attempts_does_not_overflow() {
+        let mut backoff = Backoff::default();
+        for _ in 0..100 {
+            assert!(backoff.next_delay() <= Duration::from_secs(30));
+        }
+    }
+}
@@ -5,7 +5,7 @@ use std::{
 
 use actix_codec::Framed;
 use actix_web::web::Bytes;
-use awc::{ws, BoxedSocket};
+use awc::{error::WsProtocolError, ws, BoxedSocket};
 use colored::Colorize;
 use crossterm::{style::Stylize, terminal::disable_raw_mode};
 use futures_util::{
@@ -14,31 +14,215 @@ use futures_util::{
 };
 use tokio::{
     select,
-    sync::mpsc::{self, UnboundedSender},
+    sync::mpsc::{self, UnboundedReceiver, UnboundedSender},
     task::LocalSet,
+    time::{interval_at, sleep, Instant},
 };
 use tokio_stream::wrappers::UnboundedReceiverStream;
+use tracing::{error, info, warn};
 
 use crate::{
-    client::state::ClientState,
+    client::{
+        chat_log::{ChatLog, LoggedMessage, SearchMatch, SearchQuery},
+        commands::{command_names, LocalCommand},
+        state::{ClientState, ConnectionStatus},
+    },
     core::messages::{ChatMessage, ClientMessage, Command, ServerMessage},
     utils::{
-        constants::{server_message, ERROR_LOG, INFO_LOG, MESSAGE_COMMAND_SYMBOL, SERVER_INFO},
-        terminal_ui::TerminalUI,
+        constants::{
+            server_message, CONNECTION_TIMEOUT, ERROR_LOG, HEARTBEAT_INTERVAL, INFO_LOG,
+            MESSAGE_COMMAND_SYMBOL, SERVER_INFO, WARNING_LOG,
+        },
+        sanitize::sanitize_remote_text,
+        terminal_ui::{TerminalUI, UiMessage},
     },
 };
 
-use super::{auth::auth_user, config::ClientConfig};
+use super::{
+    auth::auth_user,
+    config::{ClientConfig, DefaultServer},
+    reconnect::Backoff,
+};
 
 pub type WsFramedSink = SplitSink<Framed<BoxedSocket, ws::Codec>, ws::Message>;
 pub type WsFramedStream = SplitStream<Framed<BoxedSocket, ws::Codec>>;
 
+/// Asks the connection to connect to a server, or to close
+#[derive(Debug)]
+pub enum ConnectionControl {
+    Connect(DefaultServer),
+    Disconnect,
+}
+
+fn handle_local_command(
+    command: LocalCommand,
+    terminal_ui: &mut TerminalUI,
+    client_state: &mut ClientState,
+    client_config: &ClientConfig,
+    control_tx: &UnboundedSender<ConnectionControl>,
+) {
+    match command {
+        LocalCommand::Connect(address) => {
+            // Connect to the server of a profile, or to the given address with the settings of the default server
+            let server = match address {
+                Some(address) => Some(client_config.resolve_server(&address.host, address.port)),
+                None => client_config.get_default_server().cloned(),
+            };
+            match server {
+                Some(server) => {
+                    control_tx
+                        .send(ConnectionControl::Connect(server))
+                        .unwrap_or_else(|err| {
+                            terminal_ui.add_message(format!(
+                                "{} Unbounded channel error: {}",
+                                *ERROR_LOG, err
+                            ));
+                        });
+                }
+                None => {
+                    terminal_ui.add_message(format!(
+                        "{} No default server in the config, use {}",
+                        *ERROR_LOG,
+                        Colorize::bold("/connect <host[:port]>")
+                    ));
+                }
+            }
+        }
+        LocalCommand::Disconnect => {
+            if client_state.is_online() {
+                control_tx
+                    .send(ConnectionControl::Disconnect)
+                    .unwrap_or_else(|err| {
+                        terminal_ui.add_message(format!(
+                            "{} Unbounded channel error: {}",
+                            *ERROR_LOG, err
+                        ));
+                    });
+            } else {
+                terminal_ui.add_message(format!("{} Not connected to a server", *ERROR_LOG));
+            }
+        }
+        LocalCommand::Servers => {
+            let connected_to = match client_state.get_connection() {
+                ConnectionStatus::Connecting(address) | ConnectionStatus::Connected(address) => {
+                    Some(address.as_str())
+                }
+                ConnectionStatus::Offline => None,
+            };
+            let default_server = client_config
+                .get_default_server()
+                .map(|server| ("(default)", server));
+            let profiles = client_config
+                .get_servers()
+                .iter()
+                .map(|profile| (profile.get_name(), profile.get_server()));
+
+            let mut servers_string = format!("{} Servers:\n", *INFO_LOG);
+            for (name, server) in default_server.into_iter().chain(profiles) {
+                let current = if connected_to == Some(server.address().as_str()) {
+                    " (connected)"
+                } else {
+                    ""
+                };
+                servers_string.push_str(&format!(
+                    "\r  {} {}{}\n",
+                    Colorize::bold(name),
+                    server.ws_url(),
+                    current
+                ));
+            }
+            if client_config.get_servers().is_empty() {
+                servers_string.push_str("\r  No servers in the config, add them under `servers`\n");
+            }
+            terminal_ui.add_message(servers_string);
+        }
+        LocalCommand::Switch(room) => {
+            // The room is shown once the panes are updated
+            if let Err(err) = client_state.switch_room(&room) {
+                terminal_ui.add_message(format!("{} {}", *ERROR_LOG, err));
+            }
+        }
+        LocalCommand::Search(query) => {
+            // Before connecting, the log of the default server is searched
+            let Some(chat_log_dir) = client_config.get_chat_log_dir() else {
+                terminal_ui.add_message(format!(
+                    "{} The chat log is not enabled, set {} in the config",
+                    *ERROR_LOG,
+                    Colorize::bold("chat_log.enabled")
+                ));
+                return;
+            };
+            let chat_log = client_state.get_chat_log().cloned().or_else(|| {
+                client_config
+                    .get_default_server()
+                    .map(|server| ChatLog::new(chat_log_dir, &server.address()))
+            });
+            match chat_log.map(|chat_log| chat_log.search(&query)) {
+                Some(Ok(matches)) => {
+                    terminal_ui.add_message(format_search_matches(&query, &matches));
+                }
+                Some(Err(err)) => {
+                    terminal_ui.add_message(format!(
+                        "{} Could not read the chat log: {}",
+                        *ERROR_LOG, err
+                    ));
+                }
+                None => {
+                    terminal_ui.add_message(format!(
+                        "{} The chat log is kept per server, use {} first",
+                        *ERROR_LOG,
+                        Colorize::bold("/connect")
+                    ));
+                }
+            }
+        }
+        LocalCommand::Exit => {
+            terminal_ui.add_message(format!("{} Exiting the program...", *INFO_LOG));
+
+            disable_raw_mode().unwrap();
+            exit(0);
+        }
+    }
+}
+
 fn handle_client_stdin(
     input: String,
     terminal_ui: &mut TerminalUI,
     client_state: &mut ClientState,
+    client_config: &ClientConfig,
     message_tx: &mpsc::UnboundedSender<ClientMessage>,
+    control_tx: &UnboundedSender<ConnectionControl>,
 ) {
+    // Commands of the client are handled here, whether connected or not
+    match LocalCommand::from_str(&input) {
+        Some(Ok(command)) => {
+            handle_local_command(
+                command,
+                terminal_ui,
+                client_state,
+                client_config,
+                control_tx,
+            );
+            return;
+        }
+        Some(Err(usage)) => {
+            terminal_ui.add_message(format!("{} {}", *ERROR_LOG, usage));
+            return;
+        }
+        None => (),
+    }
+
+    // Messages are queued while reconnecting, but nothing can be sent while offline
+    let is_help = input.split_ascii_whitespace().next() == Some("/help");
+    if !client_state.is_online() && !is_help {
+        terminal_ui.add_message(format!(
+            "{} Not connected, use {} first",
+            *ERROR_LOG,
+            Colorize::bold("/connect")
+        ));
+        return;
+    }
+
     // Message command only if the command starts with the command symbol
     // This allows users to execute commands when they are messaging
     if input.starts_with(MESSAGE_COMMAND_SYMBOL) {
@@ -59,9 +243,7 @@ fn handle_client_stdin(
                     });
                 }
                 Command::JoinPublicRoom(room_name) => {
-                    client_state.room = Some(room_name.clone());
-
-                    // Send join room to server
+                    // Send join room to server, the room is shown once the server has let the user in
                     let join_message = ClientMessage::Command(Command::JoinPublicRoom(room_name));
                     message_tx.send(join_message).unwrap_or_else(|err| {
                         terminal_ui.add_message(format!(
@@ -70,18 +252,24 @@ fn handle_client_stdin(
                         ));
                     });
                 }
-                Command::LeaveRoom => {
-                    // Leaving room
-                    client_state.room = None;
-
-                    // Send leave room message
-                    let leave_message = ClientMessage::Command(Command::LeaveRoom);
-                    message_tx.send(leave_message).unwrap_or_else(|err| {
-                        terminal_ui.add_message(format!(
-                            "{} Unbounded channel error: {}",
-                            *ERROR_LOG, err
-                        ));
-                    });
+                Command::LeaveRoom(room_name) => {
+                    // Leaving the given room, or the room shown
+                    match room_name.or_else(|| client_state.room.clone()) {
+                        Some(room_name) => {
+                            let leave_message =
+                                ClientMessage::Command(Command::LeaveRoom(Some(room_name)));
+                            message_tx.send(leave_message).unwrap_or_else(|err| {
+                                terminal_ui.add_message(format!(
+                                    "{} Unbounded channel error: {}",
+                                    *ERROR_LOG, err
+                                ));
+                            });
+                        }
+                        None => {
+                            terminal_ui
+                                .add_message(format!("{} You are not in any room", *ERROR_LOG));
+                        }
+                    }
                 }
                 Command::RoomInfo => {
                     if client_state.room.is_some() {
@@ -95,7 +283,8 @@ fn handle_client_stdin(
                         });
                     } else {
                         // Tell the user to join a room first
-                        let room_command_colored = format!("/room")
+                        let room_command_colored = "/room"
+                            .to_string()
                             .yellow()
                             .underline(crossterm::style::Color::Yellow)
                             .to_string();
@@ -108,6 +297,10 @@ fn handle_client_stdin(
                 Command::AuthUser(_) => {
                     unimplemented!("Auth from command line")
                 }
+                Command::RejoinRoom { .. } | Command::SetNickname { .. } => {
+                    // Sent by the client itself after a reconnect or a join, never typed by the user
+                    terminal_ui.add_message(format!("{} Not a user command", *ERROR_LOG));
+                }
                 Command::CreatePublicRoom(room_name) => {
                     let public_room_request: ClientMessage =
                         ClientMessage::Command(Command::CreatePublicRoom(room_name));
@@ -121,7 +314,13 @@ fn handle_client_stdin(
                 Command::Help => {
                     let mut commands_string = String::new();
                     for command in Command::INPUT_COMMANDS {
-                        commands_string.push_str("\r");
+                        commands_string.push('\r');
+                        commands_string.push_str(&format!("{}\n", command.usage().bold()));
+                        commands_string.push_str("\r        ");
+                        commands_string.push_str(&format!("{}\n\n", command.description()));
+                    }
+                    for command in LocalCommand::INPUT_COMMANDS {
+                        commands_string.push('\r');
                         commands_string.push_str(&format!("{}\n", command.usage().bold()));
                         commands_string.push_str("\r        ");
                         commands_string.push_str(&format!("{}\n\n", command.description()));
@@ -131,21 +330,11 @@ fn handle_client_stdin(
                 }
             }
         } else {
-            // Exit command is the client only command
-            if input.starts_with("/exit") {
-                terminal_ui.add_message(format!("{} Exiting the program...", *INFO_LOG));
-
-                disable_raw_mode().unwrap();
-                exit(0);
-            } else {
-                // Letting the user know what commands they used that was not valid
-                let error_command =
-                    Colorize::red(input.split_ascii_whitespace().collect::<Vec<&str>>()[0])
-                        .underline();
+            // Letting the user know what commands they used that was not valid
+            let error_command =
+                Colorize::red(input.split_ascii_whitespace().collect::<Vec<&str>>()[0]).underline();
 
-                terminal_ui
-                    .add_message(format!("{} Unknown command: {}", *ERROR_LOG, error_command));
-            }
+            terminal_ui.add_message(format!("{} Unknown command: {}", *ERROR_LOG, error_command));
         }
     } else {
         if client_state.room.is_none() {
@@ -160,38 +349,109 @@ fn handle_client_stdin(
                     message_tx
                         .send(ClientMessage::Chat(message.clone()))
                         .unwrap_or_else(|err| {
-                            println!("{} Unbounded channel error: {}", *ERROR_LOG, err);
+                            error!(error = %err, "Chat message could not be queued for sending");
                         });
 
                     // Print the chat message from the users perspective
-                    terminal_ui.add_message(message.format_self());
+                    terminal_ui.add_room_message(&message.room, message.format_self());
+                    client_state.log_message(&message);
                 }
                 Err(_) => {
-                    println!(
+                    terminal_ui.add_message(format!(
                         "{} Could not create chat message. Please join a room!",
                         *ERROR_LOG
-                    );
+                    ));
                 }
             }
         }
     }
 }
 
+/// Formats the messages found by /search, every match with the messages of its room around it
+fn format_search_matches(query: &SearchQuery, matches: &[SearchMatch]) -> String {
+    if matches.is_empty() {
+        return format!("{} No messages found for '{}'", *INFO_LOG, query.text);
+    }
+
+    let format_logged = |logged: &LoggedMessage| {
+        format!(
+            "{} <{}> {}",
+            logged.logged_at,
+            sanitize_remote_text(&logged.message.sender),
+            sanitize_remote_text(logged.message.get_content())
+        )
+    };
+    let mut matches_string = format!(
+        "{} {} message(s) found for '{}':\n",
+        *INFO_LOG,
+        matches.len(),
+        query.text
+    );
+    for search_match in matches {
+        let room = format!(
+            "#{}",
+            sanitize_remote_text(&search_match.found.message.room)
+        );
+        matches_string.push_str(&format!("\r  {}\n", Colorize::yellow(room.as_str())));
+        for logged in &search_match.before {
+            matches_string.push_str(&format!("\r    {}\n", format_logged(logged)));
+        }
+        let found = format_logged(&search_match.found);
+        matches_string.push_str(&format!("\r  > {}\n", Colorize::bold(found.as_str())));
+        for logged in &search_match.after {
+            matches_string.push_str(&format!("\r    {}\n", format_logged(logged)));
+        }
+    }
+    matches_string
+}
+
+/// Formats a chat message from the server, as the users own message if it was written by the user
+fn format_chat_message(chat_message: &ChatMessage, client_state: &ClientState) -> String {
+    if chat_message.sender == client_state.get_name_in(&chat_message.room) {
+        chat_message.format_self()
+    } else {
+        chat_message.format()
+    }
+}
+
+/// Handles the messages of one websocket connection
+///
+/// Returns when the connection is lost: the stream ends, the server closes the connection,
+/// or nothing has been received from the server within the connection timeout.
+/// Returns the control when the user asked to disconnect, or to connect to another server.
 async fn handle_incoming_messages(
     stream: &mut WsFramedStream,
     sink: &mut WsFramedSink,
-    terminal_ui_sender: &UnboundedSender<String>,
+    terminal_ui_sender: &UnboundedSender<UiMessage>,
     message_rx: &mut UnboundedReceiverStream<ClientMessage>,
-) {
+    client_state: &Arc<Mutex<ClientState>>,
+    terminal_ui: &Arc<Mutex<TerminalUI>>,
+    control_rx: &mut UnboundedReceiver<ConnectionControl>,
+) -> Option<ConnectionControl> {
+    // Ping the server regularly, so that a dead connection is noticed (e.g. after the computer has been sleeping)
+    // The first ping is sent right away, for the latency shown in the status bar
+    let mut heartbeat = interval_at(Instant::now(), HEARTBEAT_INTERVAL);
+    let mut last_received = Instant::now();
+    let mut ping_sent_at: Option<Instant> = None;
+
     loop {
         select! {
-            Some(msg) = stream.next() => match msg {
-                Ok(ws::Frame::Text(bytes)) => {
+            msg = stream.next() => {
+                let msg = match msg {
+                    Some(Ok(msg)) => msg,
+                    // Stream ended or failed, the connection is lost
+                    _ => return None,
+                };
+                last_received = Instant::now();
+
+                match msg {
+                ws::Frame::Text(bytes) => {
                     match String::from_utf8(bytes.to_vec()) {
                         Ok(valid_str) => {
                             // Parse server message, or ignore the message
-                            let server_msg: ServerMessage = match serde_json::from_str(&valid_str) {
-                                Ok(msg) => msg,
+                            // All remote text is sanitized here, before it can reach the terminal
+                            let server_msg: ServerMessage = match serde_json::from_str::<ServerMessage>(&valid_str) {
+                                Ok(msg) => msg.sanitized(),
                                 Err(_) => {
                                     continue;
                                 }
@@ -201,40 +461,177 @@ async fn handle_incoming_messages(
                             match server_msg {
                                 ServerMessage::CommandResult { success: _, message } => {
                                     let server_message = server_message(&message);
-                                    terminal_ui_sender.send(server_message).expect("Could not send message over terminal channel")
+                                    terminal_ui_sender.send(UiMessage::Info(server_message)).expect("Could not send message over terminal channel")
                                 },
                                 ServerMessage::StateUpdate { username: _, current_room: _, message } => {
                                     let server_message = server_message(&message);
-                                    terminal_ui_sender.send(server_message).expect("Could not send message over terminal channel")
+                                    terminal_ui_sender.send(UiMessage::Info(server_message)).expect("Could not send message over terminal channel")
                                 },
                                 ServerMessage::Chat(chat_message) => {
-                                    // Add message to the terminal ui
-                                    terminal_ui_sender.send(chat_message.format()).expect("Could not send chat message over terminal channel");
+                                    let mut unread = false;
+                                    if let Ok(mut state) = client_state.lock() {
+                                        if let Some(seq) = chat_message.seq {
+                                            state.seen_seq(&chat_message.room, seq);
+                                        }
+                                        unread = state.received_message(&chat_message.room);
+                                        state.log_message(&chat_message);
+                                    }
+
+                                    // Add message to the messages of its room
+                                    let room_msg = UiMessage::Room(chat_message.room.clone(), chat_message.format());
+                                    terminal_ui_sender.send(room_msg).expect("Could not send chat message over terminal channel");
+                                    if unread {
+                                        show_panes(terminal_ui, client_state);
+                                    }
                                 },
                                 ServerMessage::Authenticated => {
                                     // User is successfully authenticated
                                     let aut_msg = format!("{} Authenticated on the server!", *SERVER_INFO);
-                                    terminal_ui_sender.send(aut_msg).expect("Could not send authenticate message over terminal channel");
+                                    terminal_ui_sender.send(UiMessage::Info(aut_msg)).expect("Could not send authenticate message over terminal channel");
                                 },
                                 ServerMessage::RoomActionError(msg) => {
-                                    terminal_ui_sender.send(msg).expect("Could not send room action error message over terminal channel");
+                                    terminal_ui_sender.send(UiMessage::Info(server_message(&msg))).expect("Could not send room action error message over terminal channel");
+                                },
+                                ServerMessage::ChatRejected(reason) => {
+                                    let rejected_msg = format!("{} Message not sent: {}", *ERROR_LOG, reason);
+                                    terminal_ui_sender.send(UiMessage::Info(rejected_msg)).expect("Could not send rejected message over terminal channel");
                                 },
                                 ServerMessage::CreatedRoom(room_name) => {
                                     let colored_room_name = room_name.yellow().underline(crossterm::style::Color::Yellow);
                                     let server_room_create_msg = format!("{} '{}' was created as a public room", *INFO_LOG, colored_room_name);
-                                    terminal_ui_sender.send(server_room_create_msg).expect("Could not send room action error message over terminal channel");
+                                    terminal_ui_sender.send(UiMessage::Info(server_room_create_msg)).expect("Could not send room action error message over terminal channel");
+
+                                }
+                                ServerMessage::JoinedRoom { room, last_seq } => {
+                                    let mut nickname = None;
+                                    if let Ok(mut state) = client_state.lock() {
+                                        state.seen_seq(&room, last_seq);
+                                        state.joined_room(&room);
+                                        nickname = state.get_nickname(&room).map(str::to_string);
+                                    }
 
+                                    // The alias of the room in the config is used as the name in the room
+                                    if let Some(nickname) = nickname {
+                                        let set_nickname = ClientMessage::Command(Command::SetNickname { room: room.clone(), name: nickname });
+                                        if let Ok(json) = serde_json::to_string(&set_nickname) {
+                                            if sink.send(ws::Message::Text(json.into())).await.is_err() {
+                                                return None;
+                                            }
+                                        }
+                                    }
+                                    let joined_msg = server_message(&format!("Joined room {}!", room));
+                                    terminal_ui_sender.send(UiMessage::Room(room, joined_msg)).expect("Could not send joined room message over terminal channel");
+                                    show_panes(terminal_ui, client_state);
+                                }
+                                ServerMessage::LeftRoom(room) => {
+                                    if let Ok(mut state) = client_state.lock() {
+                                        state.left_room(&room);
+                                    }
+                                    show_panes(terminal_ui, client_state);
+                                    let left_msg = server_message(&format!("Left room {}", room));
+                                    terminal_ui_sender.send(UiMessage::Info(left_msg)).expect("Could not send left room message over terminal channel");
+                                }
+                                ServerMessage::RateLimited(reason) => {
+                                    let rate_limited_msg = format!("{} {}", *WARNING_LOG, reason);
+                                    terminal_ui_sender.send(UiMessage::Info(rate_limited_msg)).expect("Could not send rate limit message over terminal channel");
                                 }
+                                ServerMessage::ChatAck { room, seq } => {
+                                    if let Ok(mut state) = client_state.lock() {
+                                        state.seen_seq(&room, seq);
+                                    }
+                                }
+                                ServerMessage::History { room, messages } => {
+                                    let rejoined_msg = server_message(&format!(
+                                        "Rejoined room {}, {} missed message(s)",
+                                        room,
+                                        messages.len()
+                                    ));
+                                    terminal_ui_sender.send(UiMessage::Room(room.clone(), rejoined_msg)).expect("Could not send history message over terminal channel");
 
+                                    // Show the missed messages in the order they were sent, in the messages of the room
+                                    let mut unread = false;
+                                    if let Ok(mut state) = client_state.lock() {
+                                        for chat_message in messages {
+                                            if let Some(seq) = chat_message.seq {
+                                                state.seen_seq(&room, seq);
+                                            }
+                                            unread |= state.received_message(&room);
+                                            state.log_message(&chat_message);
+                                            let history_msg = UiMessage::Room(room.clone(), format_chat_message(&chat_message, &state));
+                                            terminal_ui_sender.send(history_msg).expect("Could not send history message over terminal channel");
+                                        }
+                                    }
+                                    if unread {
+                                        show_panes(terminal_ui, client_state);
+                                    }
+                                }
+                                ServerMessage::ServerShutdown { reason, reconnect_after } => {
+                                    if let Ok(mut state) = client_state.lock() {
+                                        state.server_shutdown(reconnect_after);
+                                    }
+                                    let shutdown_msg = match reconnect_after {
+                                        Some(seconds) => format!("Server is shutting down: {}, back in {}s", reason, seconds),
+                                        None => format!("Server is shutting down: {}", reason),
+                                    };
+                                    terminal_ui_sender.send(UiMessage::Info(server_message(&shutdown_msg))).expect("Could not send shutdown message over terminal channel");
+                                }
+                                ServerMessage::Announcement(announcement) => {
+                                    let announcement_msg = server_message(&format!("Announcement: {}", announcement));
+                                    terminal_ui_sender.send(UiMessage::Info(announcement_msg)).expect("Could not send announcement over terminal channel");
+                                }
+                                ServerMessage::Roster { room, members } => {
+                                    if let Ok(mut state) = client_state.lock() {
+                                        state.set_members(room, members);
+                                    }
+                                    show_panes(terminal_ui, client_state);
+                                }
+                                ServerMessage::RoomList(rooms) => {
+                                    if let Ok(mut state) = client_state.lock() {
+                                        state.set_rooms(rooms);
+                                    }
+                                    show_panes(terminal_ui, client_state);
+                                }
                             }
                         },
-                        Err(err) => println!("{} Failed to parse text frame: {}", *ERROR_LOG, err),
+                        Err(err) => error!(error = %err, "Failed to parse text frame"),
                     }
                 },
-                Ok(ws::Frame::Ping(_)) => {
-                    sink.send(ws::Message::Pong(Bytes::new())).await.unwrap();
+                ws::Frame::Ping(_) => {
+                    let pong = sink.send(ws::Message::Pong(Bytes::new())).await;
+                    if pong.is_err() {
+                        return None;
+                    }
+                },
+                ws::Frame::Pong(_) => {
+                    if let Some(sent_at) = ping_sent_at.take() {
+                        if let Ok(mut state) = client_state.lock() {
+                            state.set_latency(sent_at.elapsed());
+                        }
+                        show_panes(terminal_ui, client_state);
+                    }
+                },
+                ws::Frame::Close(reason) => {
+                    // Let the user know why the server closed the connection
+                    if let Some(description) = reason.and_then(|reason| reason.description) {
+                        let close_msg = format!("{} Disconnected by the server: {}", *WARNING_LOG, sanitize_remote_text(&description));
+                        terminal_ui_sender.send(UiMessage::Info(close_msg)).expect("Could not send close message over terminal channel");
+                    }
+                    return None;
                 },
                 _ => {}
+                }
+            },
+            _ = heartbeat.tick() => {
+                if last_received.elapsed() > CONNECTION_TIMEOUT {
+                    return None;
+                }
+                if sink.send(ws::Message::Ping(Bytes::new())).await.is_err() {
+                    return None;
+                }
+                ping_sent_at = Some(Instant::now());
+            },
+            control = control_rx.recv() => {
+                return Some(control.unwrap_or(ConnectionControl::Disconnect));
             },
             Some(message) = message_rx.next() => {
                 // Received a chat message from the input thread
@@ -242,21 +639,252 @@ async fn handle_incoming_messages(
                 match serde_json::to_string(&message) {
                     Ok(json) => {
                         // Send the serialized message over the WebSocket
-                        sink.send(ws::Message::Text(json.into())).await.unwrap();
+                        if sink.send(ws::Message::Text(json.into())).await.is_err() {
+                            return None;
+                        }
                     }
                     Err(err) => {
-                        println!("{} Failed to serialize ChatMessage: {}", *ERROR_LOG, err);
+                        error!(error = %err, "Failed to serialize ChatMessage");
                     }
                 }
             }
-            else => break,
         }
     }
 }
 
-pub async fn connect(
-    server_ip: String,
-    server_port: String,
+/// Starts the session of the user on the server, after connecting or reconnecting
+///
+/// Sets the user name, and joins the rooms the user is in. After a reconnect the rooms are rejoined with the
+/// nicknames of the rooms, and the server replies with the messages of every room that were missed while disconnected.
+async fn start_session(
+    sink: &mut WsFramedSink,
+    client_state: &Arc<Mutex<ClientState>>,
+    reconnected: bool,
+) -> Result<(), WsProtocolError> {
+    let mut session_messages = Vec::new();
+    if let Ok(state) = client_state.lock() {
+        session_messages.push(Command::SetName(state.user_name.clone()));
+        for room in state.get_joined_rooms() {
+            if !reconnected {
+                session_messages.push(Command::JoinPublicRoom(room.clone()));
+                continue;
+            }
+            session_messages.push(Command::RejoinRoom {
+                room: room.clone(),
+                after: state.last_seq(room),
+            });
+            if let Some(nickname) = state.get_nickname(room) {
+                session_messages.push(Command::SetNickname {
+                    room: room.clone(),
+                    name: nickname.to_owned(),
+                });
+            }
+        }
+    }
+
+    for command in session_messages {
+        if let Ok(json) = serde_json::to_string(&ClientMessage::Command(command)) {
+            sink.send(ws::Message::Text(json.into())).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates the websocket client, connecting over TLS when a TLS config is given
+fn ws_client(tls_config: &Option<Arc<rustls::ClientConfig>>) -> awc::Client {
+    match tls_config {
+        Some(tls_config) => awc::Client::builder()
+            .connector(awc::Connector::new().rustls_0_23(tls_config.clone()))
+            .finish(),
+        None => awc::Client::new(),
+    }
+}
+
+/// Shows the rooms, the members and the status of the client around the messages
+///
+/// The state is unlocked before the UI is locked, the input thread locks them the other way around.
+fn show_panes(terminal_ui: &Arc<Mutex<TerminalUI>>, client_state: &Arc<Mutex<ClientState>>) {
+    let panes = match client_state.lock() {
+        Ok(state) => state.panes(),
+        Err(_) => return,
+    };
+    if let Ok(mut ui) = terminal_ui.lock() {
+        ui.set_panes(panes);
+    }
+}
+
+fn set_connection(
+    client_state: &Arc<Mutex<ClientState>>,
+    terminal_ui: &Arc<Mutex<TerminalUI>>,
+    connection: ConnectionStatus,
+) {
+    if let Ok(mut state) = client_state.lock() {
+        state.set_connection(connection);
+    }
+    show_panes(terminal_ui, client_state);
+}
+
+/// Connects to the server, and reconnects with backoff every time the connection is lost
+///
+/// Returns the control that ended the connection: the user disconnected, or connects to another server.
+async fn keep_connected(
+    server: &DefaultServer,
+    client_config: &ClientConfig,
+    client_state: &Arc<Mutex<ClientState>>,
+    terminal_ui: &Arc<Mutex<TerminalUI>>,
+    terminal_ui_sender: &UnboundedSender<UiMessage>,
+    message_rx: &mut UnboundedReceiverStream<ClientMessage>,
+    control_rx: &mut UnboundedReceiver<ConnectionControl>,
+) -> ConnectionControl {
+    let server_address = server.address();
+    let tls_config = match server.tls_config() {
+        Ok(tls_config) => tls_config.map(Arc::new),
+        Err(err) => {
+            if let Ok(mut ui) = terminal_ui.lock() {
+                ui.add_message(format!("{} {}", *ERROR_LOG, err));
+            }
+            return ConnectionControl::Disconnect;
+        }
+    };
+
+    // Formatting the websocket connection string
+    let ws_url = server.ws_url();
+
+    let mut backoff = Backoff::default();
+    let mut has_connected = false;
+    loop {
+        set_connection(
+            client_state,
+            terminal_ui,
+            ConnectionStatus::Connecting(server_address.clone()),
+        );
+        if let Ok(mut ui) = terminal_ui.lock() {
+            ui.add_message(format!(
+                "{} Connecting to {} ...",
+                *INFO_LOG, server_address
+            ));
+        }
+
+        // Connecting to the given server, the user can give up or pick another server meanwhile
+        let connected = select! {
+            connected = ws_client(&tls_config).ws(ws_url.as_str()).connect() => connected,
+            control = control_rx.recv() => return control.unwrap_or(ConnectionControl::Disconnect),
+        };
+        match connected {
+            Ok((_, ws)) => {
+                // Successful connection
+                if let Ok(mut ui) = terminal_ui.lock() {
+                    ui.add_message(format!("{} Connected to {}!", *INFO_LOG, server_address));
+                }
+                info!(server = %server_address, "Connected");
+
+                // Creating a sink and stream from the websocket
+                // - sink: messages sent to the server
+                // - stream: messages received from the server
+                let (mut sink, mut stream): (WsFramedSink, WsFramedStream) = ws.split();
+
+                // Before we do anything, we authenticate the user
+                let authenticated = select! {
+                    authenticated = auth_user(&mut sink, &mut stream, client_config, terminal_ui.clone()) => authenticated,
+                    control = control_rx.recv() => return control.unwrap_or(ConnectionControl::Disconnect),
+                };
+                match authenticated {
+                    Ok(_) => {
+                        backoff.reset();
+                        set_connection(
+                            client_state,
+                            terminal_ui,
+                            ConnectionStatus::Connected(server_address.clone()),
+                        );
+
+                        // Set the name and join the room, after a reconnect get back to where the user was
+                        let started = start_session(&mut sink, client_state, has_connected)
+                            .await
+                            .is_ok();
+                        has_connected = true;
+
+                        // Handle incoming messages on streams and unbounded channel until the connection is lost
+                        if started {
+                            if let Some(control) = handle_incoming_messages(
+                                &mut stream,
+                                &mut sink,
+                                terminal_ui_sender,
+                                message_rx,
+                                client_state,
+                                terminal_ui,
+                                control_rx,
+                            )
+                            .await
+                            {
+                                // Let the server know the user left on purpose
+                                let _ = sink
+                                    .send(ws::Message::Close(Some(ws::CloseCode::Normal.into())))
+                                    .await;
+                                info!(server = %server_address, "Disconnected");
+                                return control;
+                            }
+                        }
+
+                        warn!(server = %server_address, "Lost connection");
+                        if let Ok(mut ui) = terminal_ui.lock() {
+                            ui.add_message(format!(
+                                "{} Lost connection to {}",
+                                *WARNING_LOG, server_address
+                            ));
+                        }
+                    }
+                    Err(err) => {
+                        error!(server = %server_address, error = %err, "Authentication failed");
+                        if let Ok(mut ui) = terminal_ui.lock() {
+                            ui.add_message(format!(
+                                "{} Authentication failed: {}",
+                                *ERROR_LOG, err
+                            ));
+                        }
+                    }
+                }
+            }
+            Err(err) => {
+                warn!(server = %server_address, error = %err, "Failed to connect");
+                if let Ok(mut ui) = terminal_ui.lock() {
+                    ui.add_message(format!(
+                        "{} Failed to connect to {}, {}",
+                        *ERROR_LOG, server_address, err
+                    ));
+                }
+            }
+        }
+
+        // Wait before trying again, a server that shut down tells how long it will take to be back
+        let mut delay = backoff.next_delay();
+        if let Some(reconnect_after) = client_state
+            .lock()
+            .ok()
+            .and_then(|mut state| state.take_reconnect_after())
+        {
+            delay += reconnect_after;
+        }
+        if let Ok(mut ui) = terminal_ui.lock() {
+            ui.add_message(format!(
+                "{} Reconnecting in {:.1}s (attempt {})...",
+                *WARNING_LOG,
+                delay.as_secs_f32(),
+                backoff.attempt()
+            ));
+        }
+        select! {
+            _ = sleep(delay) => (),
+            control = control_rx.recv() => return control.unwrap_or(ConnectionControl::Disconnect),
+        }
+    }
+}
+
+/// Runs the client until the user exits, connected to at most one server at a time
+///
+/// The client stays offline until the user types /connect, unless a server to connect to is given.
+pub async fn run_client(
+    connect_to: Option<DefaultServer>,
     client_config: Arc<ClientConfig>,
     terminal_ui: Arc<Mutex<TerminalUI>>,
 ) {
@@ -264,86 +892,58 @@ pub async fn connect(
 
     local.spawn_local(async move {
         // Creating another unbounded channel for sending message
+        // The channel outlives each connection, so messages written while reconnecting are sent afterwards
         let (client_message_sender, client_message_receiver) = mpsc::unbounded_channel();
         let mut client_message_receiver: UnboundedReceiverStream<ClientMessage> =
             UnboundedReceiverStream::new(client_message_receiver);
 
-        // Create a channel for sending terminal messages to be added to the UI 
+        // Create a channel for sending terminal messages to be added to the UI
         let (terminal_ui_sender, terminal_ui_receiver) = mpsc::unbounded_channel();
-        let mut terminal_ui_receiver: UnboundedReceiverStream<String> =
+        let mut terminal_ui_receiver: UnboundedReceiverStream<UiMessage> =
             UnboundedReceiverStream::new(terminal_ui_receiver);
 
-        // Create a terminal ui behind mutex
-        if let Ok(mut ui) = terminal_ui.lock() {
-            ui.add_message(format!("{} Connecting to {} ...", *INFO_LOG, server_ip));
-        }
-
-        // Formatting the websocket connection string
-        let ws_url = format!("ws://{server_ip}:{server_port}/ws");
-
-        // Connecting to the given server
-        let (_, ws) = awc::Client::new()
-            .ws(ws_url)
-            .connect()
-            .await
-            .unwrap_or_else(|err| {
-                println!("{} Failed to connect to {}, {}", *ERROR_LOG, server_ip, err);
-                exit(1)
-            });
+        // Channel for /connect and /disconnect, from the input thread to the connection
+        let (control_sender, mut control_receiver) = mpsc::unbounded_channel();
 
-        // Successful connection
+        // Client state to be shared between the input thread and the connection, kept across reconnects
+        let client_state = Arc::new(Mutex::new(ClientState::new(
+            client_config.get_token().to_owned(),
+            client_config.get_user_name(&None).to_owned(),
+            None,
+        )));
         if let Ok(mut ui) = terminal_ui.lock() {
-            ui.add_message(format!("{} Connected to {}!", *INFO_LOG, server_ip));
-        }
-
-        // Creating a sink and stream from the websocket
-        // - sink:
-        // - stream:
-        let (mut sink, mut stream): (WsFramedSink, WsFramedStream) = ws.split();
-
-        // Sent to the server the client token, and confirm 
-        //TODO: let server know about the client and its token 
-
-        // Client state to be shared between users
-        let mut client_state =
-            ClientState::new(client_config.get_token().to_owned(), client_config.get_user_name(&None).to_owned(), None);
-
-
-        // Before we do anything, we authenticate the user
-        match auth_user(&mut sink, &mut stream, &client_config, terminal_ui.clone()).await {
-            Ok(_) => (),
-            Err(_) => {
-                // Exit procedure, auth not valid
-                disable_raw_mode().unwrap();
-                exit(1);
-            },
+            ui.set_commands(command_names());
         }
+        show_panes(&terminal_ui, &client_state);
 
         // Creating two threads:
         // - input thread: handle input from the user
         // - message thread: handle incoming messages
         // Spawn asynchronous tasks for handling input and messages
         // Spawn blocking thread for user input
-        let input_thread = tokio::spawn(async move {
+        let input_ui = terminal_ui.clone();
+        let input_state = client_state.clone();
+        let input_config = client_config.clone();
+        let _input_thread = tokio::spawn(async move {
             loop {
                 select! {
                     // Handle messages from the terminal_ui_receiver channel
                     Some(received_message) = terminal_ui_receiver.next() => {
-                        println!("MSG from channel: {}", received_message);
-                        if let Ok(mut ui) = terminal_ui.lock() {
-                            ui.add_message(received_message);
+                        if let Ok(mut ui) = input_ui.lock() {
+                            ui.show_message(received_message);
                         }
                     },
                     // Handle direct user input from terminal_ui.handle_input()
                     Ok(input) = async {
                         // Lock the terminal UI and process handle_input()
-                        terminal_ui.lock()
-                            .map(|mut ui| ui.handle_input())
-                            .and_then(|res| Ok(res))
+                        // Waiting for input blocks the thread, the other tasks are moved off it meanwhile,
+                        // otherwise the connection makes no progress on a runtime with a single worker
+                        tokio::task::block_in_place(|| input_ui.lock().map(|mut ui| ui.handle_input()))
                     } => {
                         if let Ok(Some(input)) = input {
-                            if let Ok(mut ui) = terminal_ui.lock() {
-                                handle_client_stdin(input, &mut ui, &mut client_state, &client_message_sender);
+                            if let (Ok(mut ui), Ok(mut state)) = (input_ui.lock(), input_state.lock()) {
+                                handle_client_stdin(input, &mut ui, &mut state, &input_config, &client_message_sender, &control_sender);
+                                ui.set_panes(state.panes());
                             }
                         }
                     }
@@ -351,12 +951,47 @@ pub async fn connect(
             }
         });
 
-        // Handle incoming messages on streams and unbounded channel 
-        handle_incoming_messages(&mut stream, &mut sink, &terminal_ui_sender, &mut client_message_receiver).await;
+        let mut next_control = connect_to.map(ConnectionControl::Connect);
+        loop {
+            let control = match next_control.take() {
+                Some(control) => control,
+                None => match control_receiver.recv().await {
+                    Some(control) => control,
+                    None => break,
+                },
+            };
+
+            next_control = match control {
+                ConnectionControl::Connect(server) => {
+                    // The name, the room and the messages seen belong to the previous server
+                    let server_config = client_config.with_server(&server);
+                    if let Ok(mut state) = client_state.lock() {
+                        state.user_name = server_config.get_user_name(&None).to_owned();
+                        state.set_room_aliases(server_config.get_room_aliases().clone());
+                        state.start_session(server.get_auto_join().map(str::to_string));
+                        state.set_chat_log(client_config.get_chat_log_dir().map(|dir| ChatLog::new(dir, &server.address())));
+                    }
+                    show_panes(&terminal_ui, &client_state);
+
+                    let control = keep_connected(&server, &server_config, &client_state, &terminal_ui, &terminal_ui_sender, &mut client_message_receiver, &mut control_receiver).await;
+
+                    // Messages that were not sent are dropped, they were written for the closed connection
+                    set_connection(&client_state, &terminal_ui, ConnectionStatus::Offline);
+                    while client_message_receiver.as_mut().try_recv().is_ok() {}
 
-        // Wait for the input thread to finish
-        if let Err(err) = input_thread.await {
-            eprintln!("Input thread panicked: {:?}", err);
+                    match control {
+                        ConnectionControl::Disconnect => {
+                            if let Ok(mut ui) = terminal_ui.lock() {
+                                ui.add_message(format!("{} Disconnected from {}", *INFO_LOG, server.address()));
+                            }
+                            None
+                        }
+                        connect => Some(connect),
+                    }
+                }
+                // The connection was closed right before, nothing left to disconnect
+                ConnectionControl::Disconnect => None,
+            };
         }
     });
 
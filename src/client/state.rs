@@ -1,9 +1,62 @@
+use std::{collections::HashMap, time::Duration};
+
+use tracing::warn;
+
+use crate::{
+    client::chat_log::ChatLog,
+    core::messages::ChatMessage,
+    utils::terminal_ui::panes::{Panes, RoomEntry},
+};
+
+/// Connection of the client to a server, with the address of the server
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    /// Not connected, messages can not be sent until /connect is used
+    #[default]
+    Offline,
+
+    /// Connecting or reconnecting, messages are sent once connected
+    Connecting(String),
+
+    /// Connected and authenticated
+    Connected(String),
+}
+
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
 pub struct ClientState {
     id: String,
     pub user_name: String,
+
+    /// Room that is shown, chat messages are sent to this room
     pub room: Option<String>,
+
+    // Rooms the user is in, in the order they were joined
+    joined: Vec<String>,
+
+    // Messages received in each joined room while another room was shown
+    unread: HashMap<String, usize>,
+
+    // Names used in single rooms instead of the user name, the room aliases of the config
+    room_aliases: HashMap<String, String>,
+
+    // Sequence number of the latest message seen in each room, used to fetch missed messages after a reconnect
+    last_seq: HashMap<String, u64>,
+
+    // Time the server asked to wait before reconnecting, when it was shut down
+    reconnect_after: Option<Duration>,
+
+    connection: ConnectionStatus,
+
+    // Rooms of the server that can be joined, and the members of the rooms the user is in
+    rooms: Vec<String>,
+    members: HashMap<String, Vec<String>>,
+
+    // Round trip time of the latest ping to the server
+    latency: Option<Duration>,
+
+    // Log of the messages of the server, kept after disconnecting so it can still be searched
+    chat_log: Option<ChatLog>,
 }
 
 impl ClientState {
@@ -12,6 +65,260 @@ impl ClientState {
             id,
             user_name: user_name.clone(),
             room: room.clone(),
+            joined: room.into_iter().collect(),
+            unread: HashMap::new(),
+            room_aliases: HashMap::new(),
+            last_seq: HashMap::new(),
+            reconnect_after: None,
+            connection: ConnectionStatus::Offline,
+            rooms: Vec::new(),
+            members: HashMap::new(),
+            latency: None,
+            chat_log: None,
+        }
+    }
+
+    pub fn get_connection(&self) -> &ConnectionStatus {
+        &self.connection
+    }
+
+    /// Changes the connection status, what is known about the server is forgotten once offline
+    pub fn set_connection(&mut self, connection: ConnectionStatus) {
+        if connection == ConnectionStatus::Offline {
+            self.rooms.clear();
+            self.members.clear();
+            self.latency = None;
+        }
+        self.connection = connection;
+    }
+
+    /// Checks if messages can be sent, right away or once the connection is back
+    pub fn is_online(&self) -> bool {
+        self.connection != ConnectionStatus::Offline
+    }
+
+    /// Starts over for a new server, the rooms and the messages seen belong to the previous server
+    pub fn start_session(&mut self, room: Option<String>) {
+        self.joined = room.iter().cloned().collect();
+        self.room = room;
+        self.unread.clear();
+        self.last_seq.clear();
+        self.reconnect_after = None;
+        self.rooms.clear();
+        self.members.clear();
+        self.latency = None;
+    }
+
+    /// Rooms the user is in, in the order they were joined
+    pub fn get_joined_rooms(&self) -> &[String] {
+        &self.joined
+    }
+
+    /// Register that the user joined the room, the room is shown
+    pub fn joined_room(&mut self, room: &str) {
+        if !self.joined.iter().any(|joined| joined == room) {
+            self.joined.push(room.to_owned());
+        }
+        self.room = Some(room.to_owned());
+        self.unread.remove(room);
+    }
+
+    /// Register that the user left the room, the room joined last is shown if the room was shown
+    pub fn left_room(&mut self, room: &str) {
+        self.joined.retain(|joined| joined != room);
+        self.unread.remove(room);
+        self.members.remove(room);
+        if self.room.as_deref() == Some(room) {
+            self.room = self.joined.last().cloned();
+        }
+    }
+
+    /// Uses the given names in their rooms instead of the user name, set when connecting to a server
+    pub fn set_room_aliases(&mut self, room_aliases: HashMap<String, String>) {
+        self.room_aliases = room_aliases;
+    }
+
+    /// Nickname of the user in the room, None if the user name is used
+    pub fn get_nickname(&self, room: &str) -> Option<&str> {
+        self.room_aliases.get(room).map(String::as_str)
+    }
+
+    /// Name of the user in the room, the nickname of the room or else the user name
+    pub fn get_name_in(&self, room: &str) -> &str {
+        self.get_nickname(room).unwrap_or(&self.user_name)
+    }
+
+    /// Shows the joined room with the given name, or with the given number in the order the rooms were joined
+    pub fn switch_room(&mut self, room: &str) -> Result<(), String> {
+        let target = match self.joined.iter().find(|joined| *joined == room) {
+            Some(joined) => joined.clone(),
+            None => room
+                .parse::<usize>()
+                .ok()
+                .and_then(|number| self.joined.get(number.wrapping_sub(1)))
+                .cloned()
+                .ok_or_else(|| format!("You are not in the room '{}'", room))?,
+        };
+        self.unread.remove(&target);
+        self.room = Some(target);
+        Ok(())
+    }
+
+    /// Counts a message received in the room, returns true if the room is not shown
+    pub fn received_message(&mut self, room: &str) -> bool {
+        if self.room.as_deref() == Some(room) || !self.joined.iter().any(|joined| joined == room) {
+            return false;
         }
+        *self.unread.entry(room.to_owned()).or_insert(0) += 1;
+        true
+    }
+
+    pub fn set_rooms(&mut self, rooms: Vec<String>) {
+        self.rooms = rooms;
+    }
+
+    pub fn set_members(&mut self, room: String, members: Vec<String>) {
+        self.members.insert(room, members);
+    }
+
+    pub fn set_latency(&mut self, latency: Duration) {
+        self.latency = Some(latency);
+    }
+
+    /// Logs the messages to the given chat log, None when the chat log is not enabled
+    pub fn set_chat_log(&mut self, chat_log: Option<ChatLog>) {
+        self.chat_log = chat_log;
+    }
+
+    pub fn get_chat_log(&self) -> Option<&ChatLog> {
+        self.chat_log.as_ref()
+    }
+
+    /// Appends the message to the chat log of the server, when the chat log is enabled
+    pub fn log_message(&self, message: &ChatMessage) {
+        if let Some(chat_log) = &self.chat_log {
+            if let Err(err) = chat_log.append(message) {
+                warn!(error = %err, room = %message.room, "Chat message could not be logged");
+            }
+        }
+    }
+
+    /// What is shown around the messages in the terminal
+    ///
+    /// The joined rooms are listed first, also rooms with a password that the server does not list.
+    pub fn panes(&self) -> Panes {
+        let joined = self.joined.iter().map(|name| RoomEntry {
+            name: name.clone(),
+            joined: true,
+            unread: self.unread.get(name).copied().unwrap_or(0),
+        });
+        let others = self
+            .rooms
+            .iter()
+            .filter(|name| !self.joined.contains(name))
+            .map(|name| RoomEntry {
+                name: name.clone(),
+                joined: false,
+                unread: 0,
+            });
+        let rooms = joined.chain(others).collect();
+
+        let connection = match &self.connection {
+            ConnectionStatus::Offline => "offline".to_string(),
+            ConnectionStatus::Connecting(address) => format!("connecting to {}", address),
+            ConnectionStatus::Connected(address) => format!("connected to {}", address),
+        };
+
+        Panes {
+            rooms,
+            members: self
+                .room
+                .as_ref()
+                .and_then(|room| self.members.get(room))
+                .cloned()
+                .unwrap_or_default(),
+            connection,
+            latency: self.latency,
+            room: self.room.clone(),
+            user_name: match &self.room {
+                Some(room) => self.get_name_in(room).to_owned(),
+                None => self.user_name.clone(),
+            },
+        }
+    }
+
+    /// Register that the message with the given sequence number in the room has been seen
+    pub fn seen_seq(&mut self, room: &str, seq: u64) {
+        let last_seq = self.last_seq.entry(room.to_owned()).or_insert(0);
+        if seq > *last_seq {
+            *last_seq = seq;
+        }
+    }
+
+    /// Register that the server is shutting down, and will be back after the given amount of seconds
+    pub fn server_shutdown(&mut self, reconnect_after: Option<u64>) {
+        self.reconnect_after = reconnect_after.map(Duration::from_secs);
+    }
+
+    /// Time to wait before reconnecting, asked by the server when it shut down
+    pub fn take_reconnect_after(&mut self) -> Option<Duration> {
+        self.reconnect_after.take()
+    }
+
+    /// Sequence number of the latest message seen in the given room
+    pub fn last_seq(&self, room: &str) -> u64 {
+        self.last_seq.get(room).copied().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_joined_rooms() {
+        let mut state = ClientState::new(String::new(), "zebra123".to_string(), None);
+        state.joined_room("lobby");
+        state.joined_room("dev");
+        assert_eq!(state.get_joined_rooms(), ["lobby", "dev"]);
+        assert_eq!(state.room.as_deref(), Some("dev"));
+
+        // Messages of the rooms that are not shown are counted until the room is shown
+        assert!(state.received_message("lobby"));
+        assert!(state.received_message("lobby"));
+        assert!(!state.received_message("dev"));
+        assert!(!state.received_message("unknown"));
+        assert_eq!(state.panes().rooms[0].unread, 2);
+
+        // Rooms can be shown by name or by number
+        assert!(state.switch_room("1").is_ok());
+        assert_eq!(state.room.as_deref(), Some("lobby"));
+        assert_eq!(state.panes().rooms[0].unread, 0);
+        assert!(state.switch_room("dev").is_ok());
+        assert!(state.switch_room("3").is_err());
+        assert!(state.switch_room("work").is_err());
+
+        // Leaving the room shown shows the room joined last
+        state.left_room("dev");
+        assert_eq!(state.room.as_deref(), Some("lobby"));
+        state.left_room("lobby");
+        assert_eq!(state.room, None);
+    }
+
+    #[test]
+    fn test_name_in_room() {
+        let mut state = ClientState::new(String::new(), "zebra123".to_string(), None);
+        state.set_room_aliases(HashMap::from([(
+            "friends".to_string(),
+            "elephant321".to_string(),
+        )]));
+        state.joined_room("lobby");
+        state.joined_room("friends");
+
+        // The status bar shows the name in the room shown
+        assert_eq!(state.panes().user_name, "elephant321");
+        assert!(state.switch_room("lobby").is_ok());
+        assert_eq!(state.panes().user_name, "zebra123");
+        assert_eq!(state.get_nickname("lobby"), None);
     }
 }
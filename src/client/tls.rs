@@ -0,0 +1,154 @@
+use std::sync::Arc;
+
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+    pki_types::{pem::PemObject, CertificateDer, ServerName, UnixTime},
+    DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+use sha2::{Digest, Sha256};
+
+/// Creates the TLS config used for connecting to a server over wss
+///
+/// - With a pinned fingerprint, only the server certificate with the given SHA-256 fingerprint is trusted
+/// - With a CA file, only certificates signed by the CAs in the file are trusted
+/// - Otherwise the well known public CAs are trusted
+pub fn client_tls_config(
+    ca_file: Option<&str>,
+    pinned_cert_sha256: Option<&str>,
+) -> Result<rustls::ClientConfig, String> {
+    let builder = rustls::ClientConfig::builder();
+
+    if let Some(fingerprint) = pinned_cert_sha256 {
+        let verifier = PinnedCertVerifier::new(fingerprint)?;
+        return Ok(builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth());
+    }
+
+    let mut roots = RootCertStore::empty();
+    match ca_file {
+        Some(ca_file) => {
+            let certs = CertificateDer::pem_file_iter(ca_file)
+                .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+                .map_err(|err| format!("Could not read CA file {}: {}", ca_file, err))?;
+            let (added, _) = roots.add_parsable_certificates(certs);
+            if added == 0 {
+                return Err(format!("No valid certificate found in {}", ca_file));
+            }
+        }
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+
+    Ok(builder.with_root_certificates(roots).with_no_client_auth())
+}
+
+/// Parses a SHA-256 fingerprint in hex, with or without colons between the bytes
+fn parse_fingerprint(fingerprint: &str) -> Result<Vec<u8>, String> {
+    let hex_fingerprint: String = fingerprint.chars().filter(|c| *c != ':').collect();
+    match hex::decode(hex_fingerprint) {
+        Ok(bytes) if bytes.len() == 32 => Ok(bytes),
+        _ => Err(format!("Invalid SHA-256 fingerprint: {}", fingerprint)),
+    }
+}
+
+/// Trusts exactly one server certificate, given by its fingerprint
+///
+/// The certificate chain and the host name are not checked, so self signed certificates can be used.
+/// The handshake signatures are still verified, so the server must have the private key of the certificate.
+#[derive(Debug)]
+struct PinnedCertVerifier {
+    fingerprint: Vec<u8>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl PinnedCertVerifier {
+    fn new(fingerprint: &str) -> Result<Self, String> {
+        let provider = CryptoProvider::get_default()
+            .cloned()
+            .unwrap_or_else(|| Arc::new(rustls::crypto::ring::default_provider()));
+
+        Ok(Self {
+            fingerprint: parse_fingerprint(fingerprint)?,
+            provider,
+        })
+    }
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let fingerprint = Sha256::digest(end_entity.as_ref());
+        if fingerprint.as_slice() == self.fingerprint.as_slice() {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(
+                "Server certificate does not match the pinned fingerprint".to_string(),
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fingerprint() {
+        let fingerprint = "ab".repeat(32);
+        assert_eq!(parse_fingerprint(&fingerprint).unwrap(), vec![0xab; 32]);
+
+        // Colons between the bytes, as printed by openssl
+        let with_colons = vec!["AB"; 32].join(":");
+        assert_eq!(parse_fingerprint(&with_colons).unwrap(), vec![0xab; 32]);
+
+        assert!(parse_fingerprint("abcd").is_err());
+        assert!(parse_fingerprint(&"zz".repeat(32)).is_err());
+    }
+
+    #[test]
+    fn test_missing_ca_file() {
+        assert!(client_tls_config(Some("/does/not/exist.pem"), None).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+use std::sync::Arc;
+
+use bytestring::ByteString;
+use serde::Serialize;
+use serde_json::value::RawValue;
+
+use super::messages::ChatMessage;
+
+/// A message serialized once, that can be sent to many sessions
+///
+/// Cloning a frame shares the serialized text instead of copying it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame(ByteString);
+
+impl Frame {
+    /// Serializes the value to a JSON frame
+    pub fn json<T: Serialize + ?Sized>(value: &T) -> Option<Self> {
+        serde_json::to_string(value).ok().map(Self::text)
+    }
+
+    /// Creates a frame from text that is sent as is
+    pub fn text(text: String) -> Self {
+        Self(ByteString::from(text))
+    }
+
+    /// Frame of `ServerMessage::Chat` with the recorded message
+    pub fn chat(message: &RecordedMessage) -> Self {
+        Self::json(&WireMessage::Chat(&message.json))
+            .expect("Recorded chat message could not be serialized")
+    }
+
+    /// Frame of `ServerMessage::History` with the recorded messages
+    pub fn history(room: &str, messages: &[RecordedMessage]) -> Self {
+        let messages = messages.iter().map(|message| &*message.json).collect();
+        Self::json(&WireMessage::History { room, messages })
+            .expect("Recorded chat messages could not be serialized")
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<Frame> for ByteString {
+    fn from(frame: Frame) -> Self {
+        frame.0
+    }
+}
+
+/// Variants of `ServerMessage` with chat messages that have already been serialized
+///
+/// Must serialize exactly like the matching `ServerMessage` variants.
+#[derive(Serialize)]
+enum WireMessage<'a> {
+    Chat(&'a RawValue),
+    History {
+        room: &'a str,
+        messages: Vec<&'a RawValue>,
+    },
+}
+
+/// A chat message stored in a room, serialized once when it was recorded
+///
+/// The same JSON is used for the broadcast and for every history sent after a reconnect.
+#[derive(Debug, Clone)]
+pub struct RecordedMessage {
+    seq: u64,
+    json: Arc<RawValue>,
+}
+
+impl RecordedMessage {
+    /// Serializes the message, which must have its sequence number set
+    pub fn new(message: &ChatMessage) -> Self {
+        let json =
+            serde_json::value::to_raw_value(message).expect("Chat message could not be serialized");
+        Self {
+            seq: message.seq.unwrap_or_default(),
+            json: Arc::from(json),
+        }
+    }
+
+    /// Position of the message in the room history
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        core::{messages::ServerMessage, user::user::User},
+        utils::traits::JsonSerializing,
+    };
+
+    fn chat_message(seq: u64) -> ChatMessage {
+        let mut user = User::detached();
+        user.set_id("id".to_string());
+        user.set_user_name("zebra".to_string());
+        user.join_room("room".to_string());
+
+        let received: ChatMessage = serde_json::from_str(
+            r#"{"sender":"x","content":"Hello \"there\"","room":"room","time_stamp":"12:00"}"#,
+        )
+        .unwrap();
+        let mut message = ChatMessage::from_user(&user, received).unwrap();
+        message.seq = Some(seq);
+        message
+    }
+
+    #[test]
+    fn test_chat_frame_matches_server_message() {
+        let message = chat_message(1);
+        let recorded = RecordedMessage::new(&message);
+
+        let expected = ServerMessage::Chat(message).frame().unwrap();
+        assert_eq!(Frame::chat(&recorded), expected);
+        assert_eq!(recorded.seq(), 1);
+    }
+
+    #[test]
+    fn test_history_frame_matches_server_message() {
+        let messages = vec![chat_message(1), chat_message(2)];
+        let recorded: Vec<RecordedMessage> = messages.iter().map(RecordedMessage::new).collect();
+
+        let expected = ServerMessage::History {
+            room: "room".to_string(),
+            messages,
+        }
+        .frame()
+        .unwrap();
+        assert_eq!(Frame::history("room", &recorded), expected);
+
+        // An empty history is still a valid message
+        let empty = ServerMessage::History {
+            room: "room".to_string(),
+            messages: Vec::new(),
+        };
+        assert_eq!(Frame::history("room", &[]), empty.frame().unwrap());
+    }
+}
@@ -1,21 +1,24 @@
 use colored::Colorize;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use crate::{
     client::state::ClientState,
     utils::{
         constants::{MESSAGE_COMMAND_SYMBOL, SELF_USER},
+        sanitize::sanitize_remote_text,
         time::get_time_string,
         traits::{JsonSerializing, SendServerReply},
     },
 };
 
 use super::{
-    room::room::{Room, RoomError},
+    outbox::{broadcast_frame, Outbox},
+    room::room::RoomError,
     user::user::User,
 };
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, JsonSchema)]
 pub enum ClientMessage {
     Command(Command),
     Chat(ChatMessage),
@@ -24,15 +27,33 @@ pub enum ClientMessage {
 impl JsonSerializing for ClientMessage {}
 impl SendServerReply for ClientMessage {}
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Clone, JsonSchema)]
 pub enum Command {
     SetName(String),
     JoinPublicRoom(String),
-    LeaveRoom,
+
+    /// Leaves the given room, or the room joined last when no room is given
+    LeaveRoom(Option<String>),
     Help,
     RoomInfo,
     AuthUser(String),
     CreatePublicRoom(String),
+
+    /// Sent by the client after a reconnect to get back into the room it was in
+    ///
+    /// The server replies with every message in the room history with a sequence number after `after`
+    RejoinRoom {
+        room: String,
+        after: u64,
+    },
+
+    /// Sets the name of the user in a joined room, the other rooms keep the user name
+    ///
+    /// Sent by the client after joining a room that has an alias in its config
+    SetNickname {
+        room: String,
+        name: String,
+    },
 }
 
 impl Command {
@@ -41,11 +62,12 @@ impl Command {
     pub const INPUT_COMMANDS: [Self; 5] = [
         Self::JoinPublicRoom(String::new()),
         Self::SetName(String::new()),
-        Self::LeaveRoom,
+        Self::LeaveRoom(None),
         Self::CreatePublicRoom(String::new()),
         Self::Help,
     ];
 
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(input: &str) -> Option<Self> {
         if input.is_empty() || !input.starts_with(MESSAGE_COMMAND_SYMBOL) {
             return None;
@@ -62,11 +84,11 @@ impl Command {
                     return Some(Command::JoinPublicRoom(room_name.to_owned()));
                 }
             }
-            "/leave" => {
-                if parts.len() == 1 {
-                    return Some(Command::LeaveRoom);
-                }
-            }
+            "/leave" => match parts.len() {
+                1 => return Some(Command::LeaveRoom(None)),
+                2 => return Some(Command::LeaveRoom(Some(parts[1].to_owned()))),
+                _ => (),
+            },
             "/name" => {
                 if parts.len() == 2 {
                     let new_name = parts[1];
@@ -95,46 +117,137 @@ impl Command {
             Command::Help => "/help".to_owned(),
             Command::SetName(_) => "/name <new_name>".to_owned(),
             Command::JoinPublicRoom(_) => "/join <public_room_name>".to_owned(),
-            Command::LeaveRoom => "/leave".to_owned(),
+            Command::LeaveRoom(_) => "/leave [<room_name>]".to_owned(),
             Command::CreatePublicRoom(_) => "/create -p <public_room_name>".to_owned(),
             Command::RoomInfo => "/room (NOT IMPLEMENTED)".to_owned(),
             Command::AuthUser(_) => "".to_owned(),
+            Command::RejoinRoom { .. } => "".to_owned(),
+            Command::SetNickname { .. } => "".to_owned(),
         }
     }
     pub fn description(&self) -> String {
         match self {
             Command::SetName(_) => "Sets a new username".to_owned(),
             Command::JoinPublicRoom(_) => "Allows the user to join a public room".to_owned(),
-            Command::LeaveRoom => "Leaves the current room".to_owned(),
+            Command::LeaveRoom(_) => "Leaves the current room, or the given room".to_owned(),
             Command::CreatePublicRoom(_) => "Create a public room".to_owned(),
             Command::RoomInfo => "Get room information (NOT IMPLEMENTED)".to_owned(),
             Command::Help => "List all commands and their usage".to_owned(),
             Command::AuthUser(_) => "".to_owned(),
+            Command::RejoinRoom { .. } => "".to_owned(),
+            Command::SetNickname { .. } => "".to_owned(),
+        }
+    }
+
+    /// Name of the command, without its arguments
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::SetName(_) => "SetName",
+            Command::JoinPublicRoom(_) => "JoinPublicRoom",
+            Command::LeaveRoom(_) => "LeaveRoom",
+            Command::Help => "Help",
+            Command::RoomInfo => "RoomInfo",
+            Command::AuthUser(_) => "AuthUser",
+            Command::CreatePublicRoom(_) => "CreatePublicRoom",
+            Command::RejoinRoom { .. } => "RejoinRoom",
+            Command::SetNickname { .. } => "SetNickname",
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct ChatMessage {
     pub sender: String,
     content: String,
     pub room: String,
     time_stamp: String,
+
+    /// Id of the author, set by the server from the authenticated user
+    #[serde(default)]
+    pub sender_id: Option<String>,
+
+    /// Position of the message in the room history, set by the server when the message is stored
+    #[serde(default)]
+    pub seq: Option<u64>,
 }
 
 impl ChatMessage {
+    #[allow(clippy::result_unit_err)]
     pub fn create(client_state: &ClientState, message_content: String) -> Result<Self, ()> {
         if client_state.room.is_none() {
             return Err(());
         }
+        let room = client_state.room.clone().unwrap();
         Ok(Self {
-            sender: client_state.user_name.clone(),
+            sender: client_state.get_name_in(&room).to_owned(),
             content: message_content,
-            room: client_state.room.clone().unwrap(),
+            room,
             time_stamp: get_time_string(),
+            sender_id: None,
+            seq: None,
         })
     }
 
+    /// Builds the message that is sent to other users from the state of the author on the server
+    ///
+    /// Only the content and the room are taken from the received message. The sender is taken from the
+    /// authenticated user, the time stamp from the clock of the server, and the message is rejected if the
+    /// user has not joined the room.
+    pub fn from_user(user: &User, received: ChatMessage) -> Result<Self, MessageError> {
+        let sender_id = user.get_id().ok_or(MessageError::NotAuthenticated)?;
+        if !user.has_joined_room() {
+            return Err(MessageError::NotInRoom);
+        }
+        if !user.is_in_room(&received.room) {
+            return Err(MessageError::WrongRoom(received.room));
+        }
+
+        Ok(Self {
+            sender: user.get_name_in(&received.room).to_owned(),
+            content: received.content,
+            room: received.room,
+            time_stamp: get_time_string(),
+            sender_id: Some(sender_id.to_owned()),
+            seq: None,
+        })
+    }
+
+    /// Checks that the content of the message can be sent to other users
+    pub fn validate(&self, max_length: usize) -> Result<(), MessageError> {
+        let length = self.content.chars().count();
+        if length == 0 {
+            return Err(MessageError::Empty);
+        }
+        if length > max_length {
+            return Err(MessageError::TooLong(max_length));
+        }
+        if self.content.chars().any(char::is_control) {
+            return Err(MessageError::ControlCharacter);
+        }
+        Ok(())
+    }
+
+    /// Text of the message, as written by the sender
+    pub fn get_content(&self) -> &str {
+        &self.content
+    }
+
+    /// Time the message was received by the server
+    pub fn get_time_stamp(&self) -> &str {
+        &self.time_stamp
+    }
+
+    /// Returns the message with its text made safe to print in the terminal
+    pub fn sanitized(self) -> Self {
+        Self {
+            sender: sanitize_remote_text(&self.sender),
+            content: sanitize_remote_text(&self.content),
+            room: sanitize_remote_text(&self.room),
+            time_stamp: sanitize_remote_text(&self.time_stamp),
+            ..self
+        }
+    }
+
     pub fn format(&self) -> String {
         let user_name_color: (u8, u8, u8) = (255, 0, 140);
 
@@ -160,6 +273,43 @@ impl ChatMessage {
     }
 }
 
+/// Reasons for the server to not accept a chat message
+#[derive(Debug, PartialEq, Eq)]
+pub enum MessageError {
+    Empty,
+
+    /// The message is longer than the given max amount of characters
+    TooLong(usize),
+
+    /// The message contains control characters, like terminal escape sequences
+    ControlCharacter,
+
+    /// The author has not been authenticated by the server
+    NotAuthenticated,
+
+    /// The author is not in any room
+    NotInRoom,
+
+    /// The message was written for a room the author has not joined
+    WrongRoom(String),
+}
+
+impl MessageError {
+    /// Returns a formatted message from the message error
+    pub fn message(&self) -> String {
+        match self {
+            MessageError::Empty => "Message is empty".to_string(),
+            MessageError::TooLong(max_length) => {
+                format!("Message is longer than {} characters", max_length)
+            }
+            MessageError::ControlCharacter => "Message contains control characters".to_string(),
+            MessageError::NotAuthenticated => "You are not authenticated".to_string(),
+            MessageError::NotInRoom => "You are not in a room".to_string(),
+            MessageError::WrongRoom(room) => format!("You are not in the room '{}'", room),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct CommandResult {
     success: bool,
@@ -169,7 +319,7 @@ pub struct CommandResult {
 impl JsonSerializing for CommandResult {}
 impl SendServerReply for CommandResult {}
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 pub enum ServerMessage {
     CommandResult {
         success: bool,
@@ -192,6 +342,51 @@ pub enum ServerMessage {
 
     /// Room message
     CreatedRoom(String),
+
+    /// Sent when the user has joined a room, with the sequence number of the latest message in the room
+    JoinedRoom {
+        room: String,
+        last_seq: u64,
+    },
+
+    /// Sent back to the author of a chat message once it has been stored in the room history
+    ChatAck {
+        room: String,
+        seq: u64,
+    },
+
+    /// Sent when a message was dropped because the user is sending too fast
+    RateLimited(String),
+
+    /// Sent when a chat message was not accepted, with the reason
+    ChatRejected(String),
+
+    /// Messages from the room history, sent when rejoining a room after a reconnect
+    History {
+        room: String,
+        messages: Vec<ChatMessage>,
+    },
+
+    /// Announcement of the server admin, sent to every connected user
+    Announcement(String),
+
+    /// Sent to every user before the server shuts down, with the seconds after which the server should be back
+    ServerShutdown {
+        reason: String,
+        reconnect_after: Option<u64>,
+    },
+
+    /// Names of the users in the room, sent to every member when someone joins, leaves or changes name
+    Roster {
+        room: String,
+        members: Vec<String>,
+    },
+
+    /// Rooms that can be joined without a password, sent after authentication and when rooms are created or deleted
+    RoomList(Vec<String>),
+
+    /// Sent when the user has left a room, or the room was deleted
+    LeftRoom(String),
 }
 
 impl ServerMessage {
@@ -209,6 +404,64 @@ impl ServerMessage {
         };
     }
 
+    /**
+     * Returns the message with all of its text made safe to print in the terminal
+     *
+     * Every message received from the server is sanitized once, before it is handled by the client.
+     */
+    pub fn sanitized(self) -> Self {
+        let text = |text: String| sanitize_remote_text(&text);
+        let texts = |texts: Vec<String>| texts.into_iter().map(text).collect();
+
+        return match self {
+            Self::CommandResult { success, message } => Self::CommandResult {
+                success,
+                message: text(message),
+            },
+            Self::StateUpdate {
+                username,
+                current_room,
+                message,
+            } => Self::StateUpdate {
+                username: username.map(text),
+                current_room: current_room.map(text),
+                message: text(message),
+            },
+            Self::Chat(message) => Self::Chat(message.sanitized()),
+            Self::RoomActionError(message) => Self::RoomActionError(text(message)),
+            Self::Authenticated => Self::Authenticated,
+            Self::CreatedRoom(room) => Self::CreatedRoom(text(room)),
+            Self::JoinedRoom { room, last_seq } => Self::JoinedRoom {
+                room: text(room),
+                last_seq,
+            },
+            Self::ChatAck { room, seq } => Self::ChatAck {
+                room: text(room),
+                seq,
+            },
+            Self::RateLimited(reason) => Self::RateLimited(text(reason)),
+            Self::ChatRejected(reason) => Self::ChatRejected(text(reason)),
+            Self::History { room, messages } => Self::History {
+                room: text(room),
+                messages: messages.into_iter().map(ChatMessage::sanitized).collect(),
+            },
+            Self::Announcement(announcement) => Self::Announcement(text(announcement)),
+            Self::ServerShutdown {
+                reason,
+                reconnect_after,
+            } => Self::ServerShutdown {
+                reason: text(reason),
+                reconnect_after,
+            },
+            Self::Roster { room, members } => Self::Roster {
+                room: text(room),
+                members: texts(members),
+            },
+            Self::RoomList(rooms) => Self::RoomList(texts(rooms)),
+            Self::LeftRoom(room) => Self::LeftRoom(text(room)),
+        };
+    }
+
     pub fn from_chat_msg(chat_message: ChatMessage) -> Self {
         return Self::Chat(chat_message);
     }
@@ -216,7 +469,7 @@ impl ServerMessage {
     pub fn state_update(user: &User, message: &str) -> Self {
         Self::StateUpdate {
             username: Some(user.get_user_name().to_owned()),
-            current_room: user.get_room_name(),
+            current_room: user.get_latest_room().map(str::to_string),
             message: message.to_string(),
         }
     }
@@ -233,17 +486,143 @@ impl ServerMessage {
         Self::CreatedRoom(room_message)
     }
 
-    /// Broadcasts the message to the given room
+    /// Broadcasts the message to the given sessions
     ///
-    /// Does not send the message to the current user, but to everyone in the given room
-    pub async fn broadcast_msg(&self, room: &Room, current_user: &User) {
-        for user in room.iter_users() {
-            if user.get_id() != current_user.get_id() {
-                self.send(&mut user.get_session()).await;
-            }
+    /// The outboxes are collected from the room beforehand with `Room::outboxes_except`.
+    /// The message is serialized once, and queueing never waits, so a slow session does not hold up the others.
+    pub fn broadcast_msg(&self, outboxes: &[Outbox]) {
+        if let Some(frame) = self.frame() {
+            broadcast_frame(&frame, outboxes);
         }
     }
 }
 
 impl JsonSerializing for ServerMessage {}
 impl SendServerReply for ServerMessage {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chat_message(content: &str) -> ChatMessage {
+        ChatMessage {
+            sender: "zebra123".to_string(),
+            content: content.to_string(),
+            room: "friends".to_string(),
+            time_stamp: "12:00:00".to_string(),
+            sender_id: None,
+            seq: None,
+        }
+    }
+
+    #[test]
+    fn test_from_user_uses_server_state() {
+        let mut user = User::detached();
+        user.set_id("id-of-zebra".to_string());
+        user.set_user_name("zebra123".to_string());
+        user.join_room("work".to_string());
+        user.join_room("friends".to_string());
+
+        // The client claims to be someone else
+        let mut received = chat_message("hi");
+        received.sender = "admin".to_string();
+        received.sender_id = Some("id-of-admin".to_string());
+        // The time stamp is forged, the server has no brackets in its time stamps
+        received.time_stamp = "[00:00:00]".to_string();
+
+        let message = ChatMessage::from_user(&user, received).unwrap();
+        assert_ne!(message.time_stamp, "[00:00:00]");
+        assert_eq!(message.sender, "zebra123");
+        assert_eq!(message.sender_id.as_deref(), Some("id-of-zebra"));
+        assert_eq!(message.room, "friends");
+        assert_eq!(message.content, "hi");
+    }
+
+    #[test]
+    fn test_from_user_rejects_other_rooms() {
+        let mut user = User::detached();
+        assert!(matches!(
+            ChatMessage::from_user(&user, chat_message("hi")),
+            Err(MessageError::NotAuthenticated)
+        ));
+
+        user.set_id("id-of-zebra".to_string());
+        assert!(matches!(
+            ChatMessage::from_user(&user, chat_message("hi")),
+            Err(MessageError::NotInRoom)
+        ));
+
+        user.join_room("work".to_string());
+        assert!(matches!(
+            ChatMessage::from_user(&user, chat_message("hi")),
+            Err(MessageError::WrongRoom(room)) if room == "friends"
+        ));
+    }
+
+    #[test]
+    fn test_validate_message_length() {
+        assert_eq!(chat_message("hello").validate(5), Ok(()));
+        assert_eq!(chat_message("æøå🦀!").validate(5), Ok(()));
+        assert_eq!(
+            chat_message("hello!").validate(5),
+            Err(MessageError::TooLong(5))
+        );
+        assert_eq!(chat_message("").validate(5), Err(MessageError::Empty));
+    }
+
+    #[test]
+    fn test_validate_rejects_control_characters() {
+        assert_eq!(
+            chat_message("\u{1b}[2J").validate(100),
+            Err(MessageError::ControlCharacter)
+        );
+        assert_eq!(
+            chat_message("hi\n[SERVER] hi").validate(100),
+            Err(MessageError::ControlCharacter)
+        );
+    }
+
+    #[test]
+    fn test_format_strips_escape_sequences() {
+        let mut message = chat_message("\u{1b}]0;pwned\u{7}hi");
+        message.sender = "\u{1b}[2Jevil".to_string();
+
+        let formatted = message.sanitized().format();
+        assert!(!formatted.contains("pwned"));
+        assert!(!formatted.contains("\u{1b}[2J"));
+        assert!(formatted.contains("<evil>"));
+        assert!(formatted.ends_with("hi"));
+    }
+
+    #[test]
+    fn test_sanitize_server_messages() {
+        let spoof = "\u{1b}[2J\r\n[SERVER] Send your token";
+        let sanitized = "\u{FFFD}\u{FFFD}[SERVER] Send your token";
+
+        let message = ServerMessage::failed_command(spoof).sanitized();
+        assert!(
+            matches!(message, ServerMessage::CommandResult { message, .. } if message == sanitized)
+        );
+
+        let message = ServerMessage::ServerShutdown {
+            reason: spoof.to_string(),
+            reconnect_after: Some(5),
+        };
+        assert!(matches!(
+            message.sanitized(),
+            ServerMessage::ServerShutdown { reason, reconnect_after: Some(5) } if reason == sanitized
+        ));
+
+        let message = ServerMessage::JoinedRoom {
+            room: spoof.to_string(),
+            last_seq: 3,
+        };
+        assert!(matches!(
+            message.sanitized(),
+            ServerMessage::JoinedRoom { room, last_seq: 3 } if room == sanitized
+        ));
+
+        let message = ServerMessage::Announcement(spoof.to_string()).sanitized();
+        assert!(matches!(message, ServerMessage::Announcement(text) if text == sanitized));
+    }
+}
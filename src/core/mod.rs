@@ -1,3 +1,6 @@
+pub mod frame;
 pub mod messages;
+pub mod outbox;
+pub mod protocol;
 pub mod room;
 pub mod user;
@@ -0,0 +1,345 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use actix_ws::{CloseCode, CloseReason, Session};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tracing::warn;
+
+use crate::{core::frame::Frame, utils::lock};
+
+fn default_queue_size() -> usize {
+    return 256;
+}
+
+/// What happens when a client reads slower than messages are sent to it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// The oldest queued messages are dropped to make room for new ones
+    #[default]
+    DropOldest,
+
+    /// The client is disconnected, it can reconnect and catch up with the room history
+    Disconnect,
+}
+
+/**
+ * Outbound config defined in the server config file
+ *
+ * Every session has its own queue of messages waiting to be sent, so a slow client never holds up the others.
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct OutboundConfig {
+    /// Max amount of messages waiting to be sent to a single client
+    #[serde(default = "default_queue_size")]
+    pub queue_size: usize,
+
+    #[serde(default)]
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for OutboundConfig {
+    fn default() -> Self {
+        Self {
+            queue_size: default_queue_size(),
+            overflow: OverflowPolicy::default(),
+        }
+    }
+}
+
+/// Told about the queues of the outboxes, the server reports them as metrics
+pub trait OutboxMetrics: Send + Sync {
+    /// The amount of queued messages changed by the given amount
+    fn queued(&self, change: i64);
+
+    /// A message was dropped because the queue was full
+    fn dropped(&self);
+
+    /// A session was disconnected because its queue was full
+    fn disconnected(&self);
+}
+
+/// Outbox metrics that are not reported anywhere
+pub struct NoMetrics;
+
+impl OutboxMetrics for NoMetrics {
+    fn queued(&self, _: i64) {}
+    fn dropped(&self) {}
+    fn disconnected(&self) {}
+}
+
+/// The next thing the writer of a session should do
+#[derive(Debug, PartialEq)]
+enum Outgoing {
+    Text(Frame),
+    Close(Option<CloseReason>),
+}
+
+#[derive(Debug, Default)]
+struct OutboxState {
+    queue: VecDeque<Frame>,
+    closing: bool,
+    close_reason: Option<CloseReason>,
+    dropped: u64,
+}
+
+struct OutboxInner {
+    // Never held across an `.await`
+    state: Mutex<OutboxState>,
+    notify: Notify,
+    config: OutboundConfig,
+    metrics: &'static dyn OutboxMetrics,
+}
+
+impl std::fmt::Debug for OutboxInner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OutboxInner")
+            .field("state", &self.state)
+            .field("notify", &self.notify)
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Bounded queue of messages waiting to be sent to a single session
+///
+/// Pushing never waits, so messages can be sent to many sessions without a slow client holding up the rest.
+/// A writer task takes the messages from the queue and sends them over the websocket.
+#[derive(Debug, Clone)]
+pub struct Outbox {
+    inner: Arc<OutboxInner>,
+}
+
+impl Outbox {
+    /// Creates an outbox without a writer, the queued messages are not sent anywhere
+    ///
+    /// The queue is still bounded by the config, full queues are handled by the overflow policy.
+    pub fn new(config: &OutboundConfig, metrics: &'static dyn OutboxMetrics) -> Self {
+        Self {
+            inner: Arc::new(OutboxInner {
+                state: Mutex::new(OutboxState::default()),
+                notify: Notify::new(),
+                config: config.clone(),
+                metrics,
+            }),
+        }
+    }
+
+    /// Creates the outbox of a session, and spawns the task that writes its messages to the websocket
+    pub fn spawn(
+        session: Session,
+        config: &OutboundConfig,
+        metrics: &'static dyn OutboxMetrics,
+    ) -> Self {
+        let outbox = Self::new(config, metrics);
+        actix_web::rt::spawn(outbox.clone().write_to(session));
+        outbox
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, OutboxState> {
+        lock::mutex(&self.inner.state)
+    }
+
+    /// Queues a text message for the session
+    pub fn push_text(&self, text: String) {
+        self.push(Frame::text(text));
+    }
+
+    /// Queues a frame for the session, the frame is shared and not copied
+    ///
+    /// When the queue is full, the overflow policy of the config decides what happens
+    pub fn push(&self, frame: Frame) {
+        let mut state = self.state();
+        if state.closing {
+            return;
+        }
+
+        if state.queue.len() >= self.inner.config.queue_size {
+            match self.inner.config.overflow {
+                OverflowPolicy::DropOldest => {
+                    state.queue.pop_front();
+                    state.dropped += 1;
+                    self.inner.metrics.queued(-1);
+                    self.inner.metrics.dropped();
+                }
+                OverflowPolicy::Disconnect => {
+                    warn!(
+                        queue_size = self.inner.config.queue_size,
+                        "Disconnecting a session that could not keep up"
+                    );
+                    self.inner.metrics.queued(-(state.queue.len() as i64));
+                    self.inner.metrics.disconnected();
+                    state.queue.clear();
+                    state.closing = true;
+                    state.close_reason = Some(CloseReason {
+                        code: CloseCode::Policy,
+                        description: Some("Too slow, messages could not be delivered".to_string()),
+                    });
+                    drop(state);
+                    self.inner.notify.notify_one();
+                    return;
+                }
+            }
+        }
+
+        state.queue.push_back(frame);
+        self.inner.metrics.queued(1);
+        drop(state);
+        self.inner.notify.notify_one();
+    }
+
+    /// Closes the session with the given reason, once the queued messages have been sent
+    pub fn close(&self, reason: Option<CloseReason>) {
+        let mut state = self.state();
+        if !state.closing {
+            state.closing = true;
+            state.close_reason = reason;
+        }
+        drop(state);
+        self.inner.notify.notify_one();
+    }
+
+    /// Checks if the session is being closed, nothing more is sent to it
+    pub fn is_closing(&self) -> bool {
+        self.state().closing
+    }
+
+    /// Throws away the queued messages, used when they can not be sent anymore
+    fn discard(&self) {
+        let mut state = self.state();
+        self.inner.metrics.queued(-(state.queue.len() as i64));
+        state.queue.clear();
+    }
+
+    /// Amount of messages dropped because the queue was full
+    pub fn dropped(&self) -> u64 {
+        self.state().dropped
+    }
+
+    /// Waits for the next message to send, or for the session to be closed
+    async fn next(&self) -> Outgoing {
+        loop {
+            {
+                let mut state = self.state();
+                if let Some(frame) = state.queue.pop_front() {
+                    self.inner.metrics.queued(-1);
+                    return Outgoing::Text(frame);
+                }
+                if state.closing {
+                    return Outgoing::Close(state.close_reason.take());
+                }
+            }
+
+            // Notify keeps a permit when nobody is waiting, so a push between the check and here is not lost
+            self.inner.notify.notified().await;
+        }
+    }
+
+    /// Sends the queued messages over the websocket until the outbox or the websocket is closed
+    async fn write_to(self, mut session: Session) {
+        loop {
+            match self.next().await {
+                Outgoing::Text(frame) => {
+                    if session.text(frame).await.is_err() {
+                        // The websocket is closed, stop queueing messages for it
+                        self.close(None);
+                        self.discard();
+                        break;
+                    }
+                }
+                Outgoing::Close(reason) => {
+                    let _ = session.close(reason).await;
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// Queues the same frame for every session, the frame is serialized once and shared
+pub fn broadcast_frame(frame: &Frame, outboxes: &[Outbox]) {
+    for outbox in outboxes {
+        outbox.push(frame.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outbox(queue_size: usize, overflow: OverflowPolicy) -> Outbox {
+        Outbox::new(
+            &OutboundConfig {
+                queue_size,
+                overflow,
+            },
+            &NoMetrics,
+        )
+    }
+
+    fn text(value: &str) -> Outgoing {
+        Outgoing::Text(Frame::text(value.to_string()))
+    }
+
+    #[tokio::test]
+    async fn test_messages_are_sent_in_order() {
+        let outbox = outbox(4, OverflowPolicy::DropOldest);
+        outbox.push_text("a".to_string());
+        outbox.push_text("b".to_string());
+
+        assert_eq!(outbox.next().await, text("a"));
+        assert_eq!(outbox.next().await, text("b"));
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_drops_oldest() {
+        let outbox = outbox(2, OverflowPolicy::DropOldest);
+        for value in ["a", "b", "c", "d"] {
+            outbox.push_text(value.to_string());
+        }
+
+        assert_eq!(outbox.dropped(), 2);
+        assert_eq!(outbox.next().await, text("c"));
+        assert_eq!(outbox.next().await, text("d"));
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_disconnects() {
+        let outbox = outbox(2, OverflowPolicy::Disconnect);
+        for value in ["a", "b", "c"] {
+            outbox.push_text(value.to_string());
+        }
+
+        // The queued messages are thrown away, and nothing more is queued
+        outbox.push_text("d".to_string());
+        match outbox.next().await {
+            Outgoing::Close(Some(reason)) => assert_eq!(reason.code, CloseCode::Policy),
+            other => panic!("Expected a close, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_close_after_queued_messages() {
+        let outbox = outbox(4, OverflowPolicy::DropOldest);
+        outbox.push_text("a".to_string());
+        outbox.close(None);
+        outbox.push_text("b".to_string());
+
+        assert_eq!(outbox.next().await, text("a"));
+        assert_eq!(outbox.next().await, Outgoing::Close(None));
+    }
+
+    #[tokio::test]
+    async fn test_next_waits_for_push() {
+        let outbox = outbox(4, OverflowPolicy::DropOldest);
+        let writer = outbox.clone();
+        let next = tokio::spawn(async move { writer.next().await });
+
+        tokio::task::yield_now().await;
+        outbox.push_text("a".to_string());
+        assert_eq!(next.await.unwrap(), text("a"));
+    }
+}
@@ -0,0 +1,100 @@
+use schemars::gen::SchemaSettings;
+use serde_json::{json, Value};
+
+use super::messages::{ClientMessage, ServerMessage};
+
+/// Version of the wire protocol between the server and the clients
+///
+/// Must be bumped every time the schema of `ClientMessage` or `ServerMessage` changes.
+/// The schema of every version is published in the `protocol` directory as `schema-v<version>.json`.
+pub const PROTOCOL_VERSION: u32 = 9;
+
+/// Generates a JSON Schema of every message sent over the websocket
+///
+/// Messages sent by clients are described by `client_message`, and messages sent by the server by `server_message`.
+/// Both refer to the shared `definitions`.
+pub fn protocol_schema() -> Value {
+    let mut generator = SchemaSettings::draft07().into_generator();
+    let client_message = generator.subschema_for::<ClientMessage>();
+    let server_message = generator.subschema_for::<ServerMessage>();
+
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "RusticReach protocol",
+        "protocol_version": PROTOCOL_VERSION,
+        "client_message": client_message,
+        "server_message": server_message,
+        "definitions": generator.definitions(),
+    })
+}
+
+/// The protocol schema as pretty printed JSON, in the same format as the published schema files
+pub fn protocol_schema_string() -> String {
+    let mut schema = serde_json::to_string_pretty(&protocol_schema())
+        .expect("Protocol schema could not be serialized");
+    schema.push('\n');
+    schema
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use super::*;
+
+    fn schema_file(version: u32) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("protocol")
+            .join(format!("schema-v{}.json", version))
+    }
+
+    #[test]
+    fn test_schema_matches_published_version() {
+        // If this fails, the protocol has changed: bump PROTOCOL_VERSION and publish the new schema with
+        // cargo run --bin server -- --dump-schema protocol/schema-v<version>.json
+        let published = fs::read_to_string(schema_file(PROTOCOL_VERSION)).unwrap_or_else(|_| {
+            panic!(
+                "No published schema for protocol version {}",
+                PROTOCOL_VERSION
+            )
+        });
+        assert!(
+            published == protocol_schema_string(),
+            "The protocol schema changed without a protocol version bump"
+        );
+    }
+
+    #[test]
+    fn test_schema_differs_from_older_versions() {
+        // A version bump must come with an actual change of the schema
+        let protocol_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("protocol");
+        for entry in fs::read_dir(protocol_dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path == schema_file(PROTOCOL_VERSION) {
+                continue;
+            }
+
+            let published: Value =
+                serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+            let mut current = protocol_schema();
+            current["protocol_version"] = published["protocol_version"].clone();
+            assert_ne!(
+                published,
+                current,
+                "Protocol version {} has the same schema as {}",
+                PROTOCOL_VERSION,
+                path.display()
+            );
+        }
+    }
+
+    #[test]
+    fn test_schema_describes_all_messages() {
+        let schema = protocol_schema();
+        let definitions = schema["definitions"].as_object().unwrap();
+        for name in ["ClientMessage", "ServerMessage", "Command", "ChatMessage"] {
+            assert!(definitions.contains_key(name), "{} is missing", name);
+        }
+        assert_eq!(schema["protocol_version"], PROTOCOL_VERSION);
+    }
+}
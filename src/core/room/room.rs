@@ -3,13 +3,23 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use std::{
-    collections::{hash_map::Values, HashMap},
-    sync::{Arc, Mutex},
+    collections::{hash_map::Values, HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+    time::Instant,
 };
+use tokio::sync::Mutex as AsyncMutex;
 
 use crate::{
-    core::user::user::User,
-    utils::{constants::SERVER_INFO, hash::hash_str},
+    core::{frame::RecordedMessage, messages::ChatMessage, outbox::Outbox, user::user::User},
+    utils::{
+        constants::ROOM_HISTORY_SIZE,
+        hash::hash_str,
+        lock,
+        token_bucket::{BucketConfig, TokenBucket},
+    },
 };
 
 /// Represents any type of error that a user might have had interacting with a Room in some way
@@ -23,6 +33,9 @@ pub enum RoomError {
     // User is already in the room
     UserExists(String),
 
+    /// The user has not joined the room with the given name
+    NotJoined(String),
+
     /// Action was not available because of the given reason
     InvalidAction(String),
     PasswordRequired,
@@ -32,20 +45,23 @@ pub enum RoomError {
 
 impl RoomError {
     /// Returns a formatted message from the room error
+    ///
+    /// The message is plain text, the client decides how to show it
     pub fn message(&self) -> String {
         match self {
             RoomError::MaxRoomCount(count) => format!(
-                "{} {}/{} rooms created. No more available rooms available...",
-                *SERVER_INFO, count, count
+                "{}/{} rooms created. No more available rooms available...",
+                count, count
             ),
-            RoomError::MaxCapacityReached => format!("{} Room is full", *SERVER_INFO),
-            RoomError::NameOccupied => format!("{} Room already exists", *SERVER_INFO),
-            RoomError::InvalidAction(msg) => format!("{} {}", *SERVER_INFO, msg),
-            RoomError::PasswordRequired => format!("{} Room is password protected", *SERVER_INFO),
-            RoomError::RoomNotFound => format!("{} Room does not exists", *SERVER_INFO),
+            RoomError::MaxCapacityReached => "Room is full".to_string(),
+            RoomError::NameOccupied => "Room already exists".to_string(),
+            RoomError::InvalidAction(msg) => msg.to_string(),
+            RoomError::PasswordRequired => "Room is password protected".to_string(),
+            RoomError::RoomNotFound => "Room does not exists".to_string(),
+            RoomError::NotJoined(room_name) => format!("You are not in the room '{}'", room_name),
             RoomError::UserExists(user_name) => format!(
-                "{} User '{}' already exist in the room. Please change username",
-                *SERVER_INFO, user_name
+                "User '{}' already exist in the room. Please change username",
+                user_name
             ),
         }
     }
@@ -57,27 +73,47 @@ pub struct Room {
     owner_id: String,
     name: String,
     capacity: usize,
+
+    // Given when the room was created, instead of the capacity of the room config
+    own_capacity: bool,
     users: HashMap<String, User>,
     password_hash: Option<String>,
+
+    // The latest chat messages of the room, used by clients to catch up after a reconnect
+    history: VecDeque<RecordedMessage>,
+    last_seq: u64,
+
+    // Budget of chat messages shared by all users in the room
+    chat_bucket: TokenBucket,
 }
 
-/// Represent a application level data of the struct ServerRooms, protected by a mutex
-pub type WebRoom = web::Data<Arc<Mutex<ServerRooms>>>;
+/// Represent a application level data of the struct ServerRooms
+pub type WebRoom = web::Data<Arc<ServerRooms>>;
 
 impl Room {
     /// Create a new room with random id
     pub fn new(owner: &User, room_name: String, capacity: usize) -> Self {
+        let owner_id = owner
+            .get_id()
+            .expect("Owner of group had an ID that was not set")
+            .to_string();
+        Self::with_owner_id(owner_id, room_name, capacity)
+    }
+
+    /// Create a new room with random id, owned by the user with the given id
+    pub fn with_owner_id(owner_id: String, room_name: String, capacity: usize) -> Self {
         let room_id = Uuid::new_v4().to_string();
         Self {
             id: room_id,
-            owner_id: owner
-                .get_id()
-                .expect("Owner of group had an ID that was not set")
-                .to_string(),
+            owner_id,
             name: room_name,
             capacity,
+            own_capacity: false,
             users: HashMap::new(),
             password_hash: None,
+            history: VecDeque::with_capacity(ROOM_HISTORY_SIZE),
+            last_seq: 0,
+            chat_bucket: TokenBucket::new(Instant::now()),
         }
     }
 
@@ -88,6 +124,12 @@ impl Room {
         self
     }
 
+    /// Marks the capacity as given for this room, it is kept when the room config changes
+    pub fn own_capacity(mut self) -> Self {
+        self.own_capacity = true;
+        self
+    }
+
     /// Returns true if the room requires a password
     pub fn has_password(&self) -> bool {
         self.password_hash.is_some()
@@ -128,6 +170,11 @@ impl Room {
         false
     }
 
+    /// Gets the random id of the room
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
     /// Gets the name of the user
     pub fn name(&self) -> String {
         self.name.clone()
@@ -138,6 +185,11 @@ impl Room {
         self.capacity
     }
 
+    /// Changes the max amount of users, users already in the room are kept when there are more
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+    }
+
     /// Get the amount of users that are in the room
     pub fn joined_user_count(&self) -> usize {
         self.users.len()
@@ -152,32 +204,110 @@ impl Room {
         }
     }
 
+    /// Remove the user from the room, but only if the room still has the same session of the user
+    ///
+    /// Used when a connection closes. If the user already reconnected, the new session is kept.
+    pub fn remove_session(&mut self, user: &User) {
+        if let Some(user_id) = user.get_id() {
+            if let Some(joined_user) = self.users.get(user_id) {
+                if joined_user.get_session_id() == user.get_session_id() {
+                    self.users.remove(user_id);
+                }
+            }
+        }
+    }
+
     /// Add a user to the list of joined users
     ///
-    /// The method clones the user, changes the room of the user, and adds it to the room
+    /// The method clones the user and adds it to the room.
+    /// If the user is already in the room, the stored user is replaced, so that a reconnected user gets its new session.
+    /// New users are only added while the room has fewer users than its capacity.
     pub fn add_user(&mut self, user: &User) -> Result<(), RoomError> {
-        if self.capacity > 0 {
-            if let Some(user_id) = user.get_id() {
-                if !self.users.contains_key(user_id) {
-                    // Make the user mutable, change the room name, and then insert it to the room
-                    self.users.insert(user_id.to_owned(), user.clone());
-                }
-            } else {
-                // Should not be possible, panic
-                //TODO: handle unexpected behavior better
-                panic!("User did not have an ID")
+        if let Some(user_id) = user.get_id() {
+            if !self.users.contains_key(user_id) && self.users.len() >= self.capacity {
+                return Err(RoomError::MaxCapacityReached);
             }
+            self.users.insert(user_id.to_owned(), user.clone());
             Ok(())
         } else {
-            return Err(RoomError::MaxCapacityReached);
+            // Should not be possible, panic
+            //TODO: handle unexpected behavior better
+            panic!("User did not have an ID")
         }
     }
 
+    /// Names of the users in the room, their nickname in the room if they have one, sorted
+    pub fn member_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .users
+            .values()
+            .map(|user| user.get_name_in(&self.name).to_string())
+            .collect();
+        names.sort();
+        names
+    }
+
     /// Returns an iterator of all Users in the room
     pub fn iter_users(&self) -> Values<'_, String, User> {
         self.users.values()
     }
 
+    /// Returns the outboxes of every user in the room
+    pub fn outboxes(&self) -> Vec<Outbox> {
+        self.users
+            .values()
+            .map(|joined_user| joined_user.get_outbox().clone())
+            .collect()
+    }
+
+    /// Returns the outboxes of every user in the room, except the given user
+    pub fn outboxes_except(&self, user: &User) -> Vec<Outbox> {
+        self.users
+            .values()
+            .filter(|joined_user| joined_user.get_id() != user.get_id())
+            .map(|joined_user| joined_user.get_outbox().clone())
+            .collect()
+    }
+
+    /// Takes a chat message from the budget of the room, returns false if the room is flooded
+    pub fn try_take_chat(&mut self, config: &BucketConfig) -> bool {
+        self.chat_bucket.try_take(config, Instant::now())
+    }
+
+    /// Sequence number of the latest chat message sent in the room
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq
+    }
+
+    /// Stores the chat message in the room history
+    ///
+    /// The message gets its sequence number and is serialized once, the result is used for the broadcast
+    /// and for the history. Only the latest `ROOM_HISTORY_SIZE` messages are kept.
+    pub fn record_message(&mut self, mut message: ChatMessage) -> RecordedMessage {
+        self.last_seq += 1;
+        message.seq = Some(self.last_seq);
+        let recorded = RecordedMessage::new(&message);
+
+        if self.history.len() == ROOM_HISTORY_SIZE {
+            self.history.pop_front();
+        }
+        self.history.push_back(recorded.clone());
+
+        recorded
+    }
+
+    /// Returns all messages in the history with a sequence number after the given one
+    ///
+    /// If the given sequence number is ahead of the room (the room was created again), the whole history is returned
+    pub fn history_after(&self, seq: u64) -> Vec<RecordedMessage> {
+        let after = if seq > self.last_seq { 0 } else { seq };
+        self.history
+            .iter()
+            .filter(|message| message.seq() > after)
+            .cloned()
+            .collect()
+    }
+
     /// Returns a struct that represents the information about the current room
     pub fn info(&self) -> RoomInformation {
         unimplemented!()
@@ -193,127 +323,268 @@ pub struct RoomInformation {
     current_users: Vec<String>,
 }
 
+/// A room shared between the sessions of its members
+///
+/// The lock is async, so it can be held while sending to the members of the room.
+/// Every room has its own lock, so a busy room never blocks other rooms.
+pub type SharedRoom = Arc<AsyncMutex<Room>>;
+
 /// Collection of Rooms that the server currently has
+///
+/// The map of rooms is only locked for looking up, creating and deleting rooms, and never across an `.await`.
+/// Everything else happens on the lock of the single room.
 pub struct ServerRooms {
-    rooms: HashMap<String, Room>,
-    max_rooms_count: usize,
+    rooms: RwLock<HashMap<String, SharedRoom>>,
+    // Can be changed when the server config is reloaded
+    max_rooms_count: AtomicUsize,
 }
 
 impl ServerRooms {
     /// Create a new list of server rooms with a given max count for amount of rooms allowed on the server
     pub fn with_max_room_count(max: usize) -> Self {
         Self {
-            max_rooms_count: max,
-            rooms: HashMap::with_capacity(max),
+            max_rooms_count: AtomicUsize::new(max),
+            rooms: RwLock::new(HashMap::with_capacity(max)),
         }
     }
 
-    /// Checks if the given room is already exists on the server
-    pub fn is_room_name_taken(&self, room_name: String) -> bool {
-        for (_, room) in &self.rooms {
-            if room.name() == room_name {
-                return true;
-            }
-        }
-        false
+    /// Changes the max amount of rooms, existing rooms are kept when there are more
+    pub fn set_max_room_count(&self, max: usize) {
+        self.max_rooms_count.store(max, Ordering::Relaxed);
     }
 
-    /// Get the given room id
-    pub fn get_room_id(&self, room_name: String) -> Option<String> {
-        for (_, room) in &self.rooms {
-            if room.name() == room_name {
-                return Some(room.id.clone());
+    /// Changes the capacity of the rooms that use the capacity of the room config
+    ///
+    /// Rooms created with their own capacity keep it.
+    pub async fn set_default_room_capacity(&self, capacity: usize) {
+        for (_, room) in self.rooms() {
+            let mut room = room.lock().await;
+            if !room.own_capacity {
+                room.set_capacity(capacity);
             }
         }
-        None
     }
 
-    /// Returns a mutable reference to the room of the user
-    pub fn get_room_mut(&mut self, user: &User) -> Option<&mut Room> {
-        let room_name = user.get_room_name()?;
-        if let Some(room) = self.rooms.get_mut(&room_name) {
-            return Some(room);
-        }
+    fn read_rooms(&self) -> RwLockReadGuard<'_, HashMap<String, SharedRoom>> {
+        lock::read(&self.rooms)
+    }
 
-        // User is not in the room
-        None
+    fn write_rooms(&self) -> RwLockWriteGuard<'_, HashMap<String, SharedRoom>> {
+        lock::write(&self.rooms)
     }
 
-    /// Returns a reference to the room that the user is in
-    pub fn get_room(&self, user: &User) -> Option<&Room> {
-        let room_name = user.get_room_name()?;
-        if let Some(room) = self.rooms.get(&room_name) {
-            return Some(room);
-        }
+    /// Checks if the given room is already exists on the server
+    pub fn is_room_name_taken(&self, room_name: &str) -> bool {
+        self.read_rooms().contains_key(room_name)
+    }
+
+    /// Amount of rooms on the server
+    pub fn room_count(&self) -> usize {
+        self.read_rooms().len()
+    }
 
-        // User is not in the room
-        None
+    /// Returns every room on the server together with its name
+    pub fn rooms(&self) -> Vec<(String, SharedRoom)> {
+        self.read_rooms()
+            .iter()
+            .map(|(room_name, room)| (room_name.clone(), room.clone()))
+            .collect()
     }
 
-    pub fn get_room_mut_with_name(&mut self, room_name: String) -> Option<&mut Room> {
-        self.rooms.get_mut(&room_name)
+    /// Returns the rooms that the user is in, rooms deleted since the user joined them are left out
+    pub fn joined_rooms(&self, user: &User) -> Vec<SharedRoom> {
+        let rooms = self.read_rooms();
+        user.get_rooms()
+            .iter()
+            .filter_map(|room_name| rooms.get(room_name).cloned())
+            .collect()
     }
 
     /// Get the room with given name
-    pub fn get_room_with_name(&self, room_name: String) -> Option<&Room> {
-        self.rooms.get(&room_name)
+    pub fn get_room_with_name(&self, room_name: &str) -> Option<SharedRoom> {
+        self.read_rooms().get(room_name).cloned()
+    }
+
+    /// Inserts a new room, if the server has space for it and the name is free
+    fn insert_room(
+        &self,
+        room_name: String,
+        create: impl FnOnce() -> Room,
+    ) -> Result<(), RoomError> {
+        let mut rooms = self.write_rooms();
+
+        // Create room only if we are allowed to create more rooms
+        if rooms.len() >= self.max_rooms_count.load(Ordering::Relaxed) {
+            return Err(RoomError::MaxRoomCount(rooms.len()));
+        }
+
+        // The given room name is taken for this server
+        if rooms.contains_key(&room_name) {
+            return Err(RoomError::NameOccupied);
+        }
+
+        rooms.insert(room_name, Arc::new(AsyncMutex::new(create())));
+        Ok(())
     }
 
     /// Create a new password protected room
     ///
     /// Uses the room configuration to do the allowed operations
     pub fn create_private_room(
-        &mut self,
+        &self,
         room_name: String,
         room_capacity: usize,
         owner: &User,
         password: String,
     ) -> Result<(), RoomError> {
         // TODO: get from config the privileges of room creation
-
-        // Create room only if we are allowed to create more rooms
-        if self.rooms.len() < self.max_rooms_count {
-            // Create room
-            if !self.is_room_name_taken(room_name.clone()) {
-                // Create a new room with password and insert it to the list of rooms
-                let room = Room::new(owner, room_name.clone(), room_capacity).password(password);
-                self.rooms.insert(room_name, room);
-                return Ok(());
-            }
-            // The given room name is taken for this server
-            return Err(RoomError::NameOccupied);
-        }
-        return Err(RoomError::MaxRoomCount(self.rooms.len()));
+        self.insert_room(room_name.clone(), || {
+            Room::new(owner, room_name, room_capacity).password(password)
+        })
     }
 
     /// Create a public room without password
     pub fn create_public_room(
-        &mut self,
+        &self,
         room_name: String,
         room_capacity: usize,
         owner: &User,
     ) -> Result<(), RoomError> {
         // TODO: get from config the privileges of room creation
+        self.insert_room(room_name.clone(), || {
+            Room::new(owner, room_name, room_capacity)
+        })
+    }
 
-        // Create room only if we are allowed to create more rooms
-        if self.rooms.len() < self.max_rooms_count {
-            // Create room
-            if !self.is_room_name_taken(room_name.clone()) {
-                // Create a new room with password and insert it to the list of rooms
-                let room = Room::new(owner, room_name.clone(), room_capacity);
-                self.rooms.insert(room_name, room);
-                return Ok(());
+    /// Create a public room owned by the user with the given id
+    ///
+    /// Used for copies of rooms created on another node of the cluster, and for rooms created by the admin.
+    /// With `own_capacity` the capacity was given for this room, instead of taken from the room config.
+    pub fn create_room_with_owner_id(
+        &self,
+        room_name: String,
+        room_capacity: usize,
+        own_capacity: bool,
+        owner_id: String,
+    ) -> Result<(), RoomError> {
+        self.insert_room(room_name.clone(), || {
+            let room = Room::with_owner_id(owner_id, room_name, room_capacity);
+            match own_capacity {
+                true => room.own_capacity(),
+                false => room,
             }
-            // The given room name is taken for this server
-            return Err(RoomError::NameOccupied);
-        }
-        return Err(RoomError::MaxRoomCount(self.rooms.len()));
+        })
     }
 
-    /// Delete the given room name from the server
-    pub fn delete_room(&mut self, room_name: String) {
-        if let Some(room_id) = self.get_room_id(room_name) {
-            self.rooms.remove(&room_id);
-        }
+    /// Delete the given room name from the server, returns the deleted room
+    pub fn delete_room(&self, room_name: &str) -> Option<SharedRoom> {
+        self.write_rooms().remove(room_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn owner() -> User {
+        let mut user = User::detached();
+        user.set_id("owner".to_string());
+        user
+    }
+
+    #[test]
+    fn test_create_rooms() {
+        let server_rooms = ServerRooms::with_max_room_count(2);
+        assert!(server_rooms
+            .create_public_room("a".to_string(), 5, &owner())
+            .is_ok());
+        assert!(matches!(
+            server_rooms.create_public_room("a".to_string(), 5, &owner()),
+            Err(RoomError::NameOccupied)
+        ));
+        assert!(server_rooms
+            .create_private_room("b".to_string(), 5, &owner(), "secret".to_string())
+            .is_ok());
+        assert!(matches!(
+            server_rooms.create_public_room("c".to_string(), 5, &owner()),
+            Err(RoomError::MaxRoomCount(2))
+        ));
+
+        assert!(server_rooms.is_room_name_taken("a"));
+        assert_eq!(server_rooms.room_count(), 2);
+
+        assert!(server_rooms.delete_room("a").is_some());
+        assert!(server_rooms.delete_room("a").is_none());
+        assert!(!server_rooms.is_room_name_taken("a"));
+        assert!(server_rooms.get_room_with_name("a").is_none());
+
+        // The max can change while the server is running
+        server_rooms.set_max_room_count(3);
+        assert!(server_rooms
+            .create_public_room("c".to_string(), 5, &owner())
+            .is_ok());
+        assert!(server_rooms
+            .create_public_room("d".to_string(), 5, &owner())
+            .is_ok());
+        assert!(matches!(
+            server_rooms.create_public_room("e".to_string(), 5, &owner()),
+            Err(RoomError::MaxRoomCount(3))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_locked_room_does_not_block_other_rooms() {
+        let server_rooms = ServerRooms::with_max_room_count(2);
+        server_rooms
+            .create_public_room("a".to_string(), 5, &owner())
+            .unwrap();
+        server_rooms
+            .create_public_room("b".to_string(), 5, &owner())
+            .unwrap();
+
+        // While room a is busy, room b can still be used and new rooms can be looked up
+        let room_a = server_rooms.get_room_with_name("a").unwrap();
+        let _busy = room_a.lock().await;
+
+        let mut user = owner();
+        user.join_room("b".to_string());
+        let room_b = server_rooms.joined_rooms(&user).pop().unwrap();
+        let mut room_b = room_b.try_lock().expect("Room b was blocked by room a");
+        room_b.add_user(&user).unwrap();
+        assert!(room_b.contains_user(&user));
+        assert!(room_a.try_lock().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_room_capacity() {
+        let server_rooms = ServerRooms::with_max_room_count(1);
+        server_rooms
+            .create_public_room("a".to_string(), 2, &owner())
+            .unwrap();
+        let room = server_rooms.get_room_with_name("a").unwrap();
+
+        let user = |id: &str| {
+            let mut user = User::detached();
+            user.set_id(id.to_string());
+            user
+        };
+        room.lock().await.add_user(&user("alice")).unwrap();
+        room.lock().await.add_user(&user("bob")).unwrap();
+        assert!(matches!(
+            room.lock().await.add_user(&user("carol")),
+            Err(RoomError::MaxCapacityReached)
+        ));
+
+        // A user that is already in the full room can still replace its session
+        assert!(room.lock().await.add_user(&user("bob")).is_ok());
+
+        // The users are kept when the capacity goes down, new users wait for a free place
+        server_rooms.set_default_room_capacity(1).await;
+        assert_eq!(room.lock().await.joined_user_count(), 2);
+        room.lock().await.remove_user(&user("alice"));
+        assert!(room.lock().await.add_user(&user("carol")).is_err());
+
+        server_rooms.set_default_room_capacity(3).await;
+        assert!(room.lock().await.add_user(&user("carol")).is_ok());
     }
 }
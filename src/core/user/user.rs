@@ -1,9 +1,9 @@
-use std::fmt::Debug;
+use std::{collections::HashMap, fmt::Debug};
 
-use actix_ws::Session;
+use uuid::Uuid;
 
 use crate::{
-    core::{messages::ServerMessage, room::room::Room},
+    core::{messages::ServerMessage, outbox::Outbox, room::room::Room},
     utils::traits::SendServerReply,
 };
 
@@ -15,8 +15,16 @@ pub struct User {
     id: Option<String>,
     name: Option<String>,
     role: UserRole,
-    room_name: Option<String>,
-    session: Option<Session>,
+
+    // Rooms the user has joined, in the order they were joined
+    rooms: Vec<String>,
+
+    // Names used in single rooms instead of the user name, by room
+    nicknames: HashMap<String, String>,
+    outbox: Option<Outbox>,
+
+    // Unique for each websocket connection, so that a reconnected user can be told apart from its old session
+    session_id: String,
 }
 
 /// Implement debug where session is only showed as true if available
@@ -26,21 +34,39 @@ impl Debug for User {
             .field("id", &self.id)
             .field("name", &self.name)
             .field("role", &self.role)
-            .field("room_name", &self.room_name)
-            .field("session", &self.session.is_some())
+            .field("rooms", &self.rooms)
+            .field("nicknames", &self.nicknames)
+            .field("outbox", &self.outbox.is_some())
+            .field("session_id", &self.session_id)
             .finish()
     }
 }
 
 impl User {
-    /// Creates a new user from the given Session
-    pub fn new(session: Session) -> Self {
+    /// Creates a new user from the outbox of its websocket session
+    pub fn new(outbox: Outbox) -> Self {
+        Self {
+            id: None,
+            rooms: Vec::new(),
+            nicknames: HashMap::new(),
+            name: None,
+            role: UserRole::default(),
+            outbox: Some(outbox),
+            session_id: Uuid::new_v4().to_string(),
+        }
+    }
+
+    /// Creates a user without a websocket session, used in tests
+    #[cfg(test)]
+    pub fn detached() -> Self {
         Self {
             id: None,
-            room_name: None,
+            rooms: Vec::new(),
+            nicknames: HashMap::new(),
             name: None,
             role: UserRole::default(),
-            session: Some(session),
+            outbox: None,
+            session_id: Uuid::new_v4().to_string(),
         }
     }
 
@@ -49,21 +75,45 @@ impl User {
     }
 
     pub fn has_joined_room(&self) -> bool {
-        self.room_name.is_some()
+        !self.rooms.is_empty()
+    }
+
+    /// Get the queue of messages waiting to be sent to the user
+    pub fn get_outbox(&self) -> &Outbox {
+        self.outbox.as_ref().unwrap()
     }
 
-    pub fn get_session(&self) -> Session {
-        self.session.clone().unwrap()
+    /// Get the id of the websocket connection the user is using
+    pub fn get_session_id(&self) -> &str {
+        &self.session_id
     }
 
     pub fn get_user_name(&self) -> &str {
         if let Some(name) = &self.name {
-            return &name;
+            return name;
         }
 
         return "unknown";
     }
 
+    /// Name of the user in the given room, the nickname of the room or else the user name
+    pub fn get_name_in(&self, room_name: &str) -> &str {
+        match self.nicknames.get(room_name) {
+            Some(nickname) => nickname,
+            None => self.get_user_name(),
+        }
+    }
+
+    /// Names used in single rooms instead of the user name, by room
+    pub fn get_nicknames(&self) -> &HashMap<String, String> {
+        &self.nicknames
+    }
+
+    /// Uses the given name in the room instead of the user name, the other rooms are not affected
+    pub fn set_nickname(&mut self, room_name: String, nickname: String) {
+        self.nicknames.insert(room_name, nickname);
+    }
+
     /// Set the id of the user
     ///
     /// The id can only be set once. Once it is set, we cannot set it again
@@ -81,24 +131,40 @@ impl User {
         self.id.as_deref()
     }
 
-    pub fn get_room_name(&self) -> Option<String> {
-        //TODO: improve this, should not need to clone
-        self.room_name.clone()
+    /// Rooms the user is in, the room joined last at the end
+    pub fn get_rooms(&self) -> &[String] {
+        &self.rooms
+    }
+
+    /// Room the user joined last
+    pub fn get_latest_room(&self) -> Option<&str> {
+        self.rooms.last().map(String::as_str)
     }
 
-    pub fn take_room(&mut self) -> Option<String> {
-        self.room_name.take()
+    pub fn is_in_room(&self, room_name: &str) -> bool {
+        self.rooms.iter().any(|room| room == room_name)
+    }
+
+    /// Adds the room to the rooms of the user, a room is only added once
+    pub fn join_room(&mut self, room_name: String) {
+        if !self.is_in_room(&room_name) {
+            self.rooms.push(room_name);
+        }
     }
 
-    pub fn set_room(&mut self, room_name: String) {
-        self.room_name = Some(room_name);
+    /// Removes the room and the nickname of the room from the user, returns false if the user was not in the room
+    pub fn leave_room(&mut self, room_name: &str) -> bool {
+        self.nicknames.remove(room_name);
+        let count = self.rooms.len();
+        self.rooms.retain(|room| room != room_name);
+        self.rooms.len() != count
     }
 
     pub async fn broadcast_message(&self, message: &ServerMessage, room: &Room) {
-        // Client must have a room
+        // Client must be in the room
         assert!(
-            self.room_name.is_some(),
-            "Client tried to cast a message when room was none"
+            self.is_in_room(&room.name()),
+            "Client tried to cast a message in a room it has not joined"
         );
 
         // ID must be set before being able to talk in the group
@@ -114,7 +180,7 @@ impl User {
         );
 
         for user in room.iter_users() {
-            message.send(&mut user.get_session().clone()).await;
+            message.send(user.get_outbox()).await;
         }
     }
 }
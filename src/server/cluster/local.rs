@@ -0,0 +1,84 @@
+use std::sync::Arc;
+
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use super::{ClusterBus, ClusterMessage, RoomEvent, CLUSTER_EVENT_BUFFER};
+
+/// Cluster bus between nodes running in the same process
+///
+/// A single node on its own is not connected to anything, which is what a server without a cluster uses.
+/// More nodes are added to the same bus with `join`.
+#[derive(Debug, Clone)]
+pub struct LocalBus {
+    node_id: String,
+    events: Arc<broadcast::Sender<ClusterMessage>>,
+}
+
+impl LocalBus {
+    /// Creates a new bus with a single node
+    pub fn new() -> Self {
+        let (events, _) = broadcast::channel(CLUSTER_EVENT_BUFFER);
+        Self {
+            node_id: Uuid::new_v4().to_string(),
+            events: Arc::new(events),
+        }
+    }
+
+    /// Adds a new node to the same bus
+    pub fn join(&self) -> Self {
+        Self {
+            node_id: Uuid::new_v4().to_string(),
+            events: self.events.clone(),
+        }
+    }
+}
+
+impl Default for LocalBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClusterBus for LocalBus {
+    fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    fn publish(&self, event: RoomEvent) {
+        // Sending only fails when no node is subscribed
+        let _ = self.events.send(ClusterMessage {
+            origin: self.node_id.clone(),
+            event,
+        });
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<ClusterMessage> {
+        self.events.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_events_reach_other_nodes() {
+        let node_a = LocalBus::new();
+        let node_b = node_a.join();
+        let mut events_b = node_b.subscribe();
+
+        let event = RoomEvent::RoomCreated {
+            room: "lobby".to_string(),
+            owner_id: "owner".to_string(),
+            capacity: 5,
+            own_capacity: false,
+        };
+        node_a.publish(event.clone());
+
+        let received = events_b.recv().await.unwrap();
+        assert_eq!(received.origin, node_a.node_id());
+        assert_eq!(received.event, event);
+        assert_ne!(node_a.node_id(), node_b.node_id());
+    }
+}
@@ -0,0 +1,86 @@
+pub mod local;
+pub mod tcp;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+
+use crate::core::messages::ChatMessage;
+
+/// Amount of events a slow subscriber can fall behind before it starts missing events
+pub const CLUSTER_EVENT_BUFFER: usize = 1024;
+
+/// Something that happened in a room, that the other nodes of the cluster should know about
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RoomEvent {
+    /// A public room was created
+    RoomCreated {
+        room: String,
+        owner_id: String,
+        capacity: usize,
+
+        /// The capacity was given for this room, instead of taken from the room config
+        #[serde(default)]
+        own_capacity: bool,
+    },
+
+    /// A room was deleted by the admin
+    RoomDeleted { room: String },
+
+    /// A chat message was sent in a room, the sender and room are already checked by the node of the author
+    Chat(ChatMessage),
+}
+
+/// A room event together with the node it came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClusterMessage {
+    pub origin: String,
+    pub event: RoomEvent,
+}
+
+/**
+ * Pub/sub between the server processes of a cluster
+ *
+ * Every node keeps its own copy of the rooms and its own sessions. Room events are published to the other nodes,
+ * so members of the same room can be connected to different nodes.
+ */
+pub trait ClusterBus: Send + Sync {
+    /// Unique id of this node in the cluster
+    fn node_id(&self) -> &str;
+
+    /// Sends an event of this node to the other nodes, never waits
+    fn publish(&self, event: RoomEvent);
+
+    /// Receives the events of the cluster
+    ///
+    /// Events published by this node may be received too, they have the id of this node as origin.
+    fn subscribe(&self) -> broadcast::Receiver<ClusterMessage>;
+}
+
+fn default_listen() -> String {
+    return "127.0.0.1:9000".to_string();
+}
+
+/**
+ * Cluster config defined in the server config file
+ *
+ * A connection between two nodes carries events both ways, so every pair of nodes
+ * should be connected once: list the peer on only one of the two nodes.
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ClusterConfig {
+    /// Id of the node, a random id is used when not given
+    #[serde(default)]
+    pub node_id: Option<String>,
+
+    /// Address where other nodes connect to
+    #[serde(default = "default_listen")]
+    pub listen: String,
+
+    /// Addresses of the nodes to connect to
+    #[serde(default)]
+    pub peers: Vec<String>,
+
+    /// Shared by every node of the cluster, connections from nodes without it are closed
+    #[serde(skip_serializing)]
+    pub secret: String,
+}
@@ -0,0 +1,401 @@
+use std::{
+    io,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use futures_util::StreamExt;
+use tokio::{
+    io::AsyncWriteExt,
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpListener, TcpStream,
+    },
+    sync::broadcast::{self, error::RecvError},
+    time::timeout,
+};
+use tokio_util::codec::{FramedRead, LinesCodec, LinesCodecError};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::utils::{
+    constants::{CLUSTER_HANDSHAKE_TIMEOUT, CLUSTER_RETRY_INTERVAL},
+    hash::hash_str,
+};
+
+use super::{ClusterBus, ClusterConfig, ClusterMessage, RoomEvent, CLUSTER_EVENT_BUFFER};
+
+/// Max length of a line sent between the nodes, the connection is closed when a line is longer
+const MAX_LINE_LENGTH: usize = 64 * 1024;
+
+/// Cluster bus between server processes, over TCP connections between the nodes
+///
+/// Every event is sent as a line of JSON. A connection carries the events of both nodes,
+/// and connections to the configured peers are opened again when they are lost.
+/// Events are not forwarded, so every node must be connected to every other node.
+/// Before any event is sent, both nodes prove that they know the secret of the cluster.
+#[derive(Debug)]
+pub struct TcpBus {
+    node_id: String,
+    secret: String,
+    local_addr: SocketAddr,
+
+    // Events of this node, sent to every connected node
+    outgoing: broadcast::Sender<ClusterMessage>,
+
+    // Events received from the other nodes
+    incoming: broadcast::Sender<ClusterMessage>,
+
+    connected_peers: Arc<AtomicUsize>,
+}
+
+impl TcpBus {
+    /// Starts listening for other nodes, and connects to the peers in the config
+    ///
+    /// Must be called from within a tokio runtime
+    pub fn start(config: &ClusterConfig) -> io::Result<Self> {
+        let listener = std::net::TcpListener::bind(&config.listen)?;
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+
+        let (outgoing, _) = broadcast::channel(CLUSTER_EVENT_BUFFER);
+        let (incoming, _) = broadcast::channel(CLUSTER_EVENT_BUFFER);
+        let bus = Self {
+            node_id: config
+                .node_id
+                .clone()
+                .unwrap_or_else(|| Uuid::new_v4().to_string()),
+            secret: config.secret.clone(),
+            local_addr: listener.local_addr()?,
+            outgoing,
+            incoming,
+            connected_peers: Arc::new(AtomicUsize::new(0)),
+        };
+
+        // Accept connections from other nodes
+        let connection = bus.connection();
+        tokio::spawn(async move {
+            loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        tokio::spawn(connection.clone().run(stream, Side::Accepted));
+                    }
+                    Err(err) => warn!(error = %err, "Cluster accept failed"),
+                }
+            }
+        });
+
+        // Connect to the peers, and reconnect when the connection is lost
+        for peer in config.peers.clone() {
+            let connection = bus.connection();
+            tokio::spawn(async move {
+                loop {
+                    match TcpStream::connect(&peer).await {
+                        Ok(stream) => connection.clone().run(stream, Side::Connected).await,
+                        Err(err) => warn!(
+                            peer = %peer,
+                            error = %err,
+                            "Could not connect to cluster peer"
+                        ),
+                    }
+                    tokio::time::sleep(CLUSTER_RETRY_INTERVAL).await;
+                }
+            });
+        }
+
+        Ok(bus)
+    }
+
+    /// Address other nodes can connect to
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Amount of nodes this node is currently connected to
+    pub fn connected_peers(&self) -> usize {
+        self.connected_peers.load(Ordering::SeqCst)
+    }
+
+    fn connection(&self) -> Connection {
+        Connection {
+            node_id: self.node_id.clone(),
+            secret: self.secret.clone(),
+            outgoing: self.outgoing.clone(),
+            incoming: self.incoming.clone(),
+            connected_peers: self.connected_peers.clone(),
+        }
+    }
+}
+
+impl ClusterBus for TcpBus {
+    fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    fn publish(&self, event: RoomEvent) {
+        // Sending only fails when no node is connected
+        let _ = self.outgoing.send(ClusterMessage {
+            origin: self.node_id.clone(),
+            event,
+        });
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<ClusterMessage> {
+        self.incoming.subscribe()
+    }
+}
+
+/// How this node got its connection to the other node
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Accepted,
+    Connected,
+}
+
+impl Side {
+    /// The proof of the handshake is bound to the side, so a node can not answer with the proof of this node
+    fn proof(self, secret: &str, challenge: &str) -> String {
+        let side = match self {
+            Side::Accepted => "accepted",
+            Side::Connected => "connected",
+        };
+        hash_str(&format!("{}:{}:{}", side, challenge, secret))
+    }
+
+    fn other(self) -> Self {
+        match self {
+            Side::Accepted => Side::Connected,
+            Side::Connected => Side::Accepted,
+        }
+    }
+}
+
+type Lines = FramedRead<OwnedReadHalf, LinesCodec>;
+
+/// A connection to another node
+#[derive(Debug, Clone)]
+struct Connection {
+    node_id: String,
+    secret: String,
+    outgoing: broadcast::Sender<ClusterMessage>,
+    incoming: broadcast::Sender<ClusterMessage>,
+    connected_peers: Arc<AtomicUsize>,
+}
+
+impl Connection {
+    /// Sends the events of this node and receives the events of the other node, until the connection is lost
+    async fn run(self, stream: TcpStream, side: Side) {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = FramedRead::new(reader, LinesCodec::new_with_max_length(MAX_LINE_LENGTH));
+
+        // Events are only exchanged with nodes that know the secret of the cluster
+        match timeout(
+            CLUSTER_HANDSHAKE_TIMEOUT,
+            self.handshake(&mut lines, &mut writer, side),
+        )
+        .await
+        {
+            Ok(Ok(())) => (),
+            Ok(Err(err)) => {
+                warn!(error = %err, "Cluster handshake failed");
+                return;
+            }
+            Err(_) => {
+                warn!("Cluster handshake timed out");
+                return;
+            }
+        }
+
+        let mut outgoing = self.outgoing.subscribe();
+        self.connected_peers.fetch_add(1, Ordering::SeqCst);
+
+        loop {
+            tokio::select! {
+                line = lines.next() => {
+                    let line = match line {
+                        Some(Ok(line)) => line,
+                        // No more lines are read after an error, peers in the config connect again
+                        Some(Err(LinesCodecError::MaxLineLengthExceeded)) => {
+                            warn!(max_length = MAX_LINE_LENGTH, "Cluster event too long");
+                            break;
+                        }
+                        _ => break,
+                    };
+                    match serde_json::from_str::<ClusterMessage>(&line) {
+                        Ok(message) if message.origin != self.node_id => {
+                            let _ = self.incoming.send(message);
+                        }
+                        Ok(_) => (),
+                        Err(err) => warn!(error = %err, "Invalid cluster event"),
+                    }
+                }
+                message = outgoing.recv() => {
+                    let message = match message {
+                        Ok(message) => message,
+                        // The connection was too slow, the missed events are lost
+                        Err(RecvError::Lagged(missed)) => {
+                            warn!(missed, "Cluster peer missed events");
+                            continue;
+                        }
+                        Err(RecvError::Closed) => break,
+                    };
+                    let Ok(mut line) = serde_json::to_string(&message) else {
+                        continue;
+                    };
+                    line.push('\n');
+                    if writer.write_all(line.as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+
+        self.connected_peers.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Proves to the other node that this node knows the secret, and checks the proof of the other node
+    ///
+    /// Both nodes send a random challenge, and answer the challenge of the other node with a hash of the
+    /// secret and the challenge. The secret itself is never sent.
+    async fn handshake(
+        &self,
+        lines: &mut Lines,
+        writer: &mut OwnedWriteHalf,
+        side: Side,
+    ) -> io::Result<()> {
+        let challenge = Uuid::new_v4().to_string();
+        write_line(writer, &challenge).await?;
+        let peer_challenge = read_line(lines).await?;
+
+        write_line(writer, &side.proof(&self.secret, &peer_challenge)).await?;
+        let peer_proof = read_line(lines).await?;
+        if peer_proof != side.other().proof(&self.secret, &challenge) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "the other node does not know the cluster secret",
+            ));
+        }
+        Ok(())
+    }
+}
+
+async fn write_line(writer: &mut OwnedWriteHalf, line: &str) -> io::Result<()> {
+    writer.write_all(format!("{}\n", line).as_bytes()).await
+}
+
+async fn read_line(lines: &mut Lines) -> io::Result<String> {
+    match lines.next().await {
+        Some(Ok(line)) => Ok(line),
+        Some(Err(LinesCodecError::Io(err))) => Err(err),
+        Some(Err(err)) => Err(io::Error::new(io::ErrorKind::InvalidData, err)),
+        None => Err(io::ErrorKind::UnexpectedEof.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn config(peers: Vec<String>) -> ClusterConfig {
+        ClusterConfig {
+            node_id: None,
+            listen: "127.0.0.1:0".to_string(),
+            peers,
+            secret: "cluster-secret".to_string(),
+        }
+    }
+
+    /// Waits until the bus is connected to the given amount of nodes
+    async fn wait_for_peers(bus: &TcpBus, count: usize) {
+        for _ in 0..100 {
+            if bus.connected_peers() >= count {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("Cluster nodes did not connect");
+    }
+
+    #[tokio::test]
+    async fn test_events_flow_both_ways() {
+        let node_a = TcpBus::start(&config(Vec::new())).unwrap();
+        let node_b = TcpBus::start(&config(vec![node_a.local_addr().to_string()])).unwrap();
+        wait_for_peers(&node_a, 1).await;
+        wait_for_peers(&node_b, 1).await;
+
+        let mut events_a = node_a.subscribe();
+        let mut events_b = node_b.subscribe();
+
+        let event = RoomEvent::RoomCreated {
+            room: "lobby".to_string(),
+            owner_id: "owner".to_string(),
+            capacity: 5,
+            own_capacity: false,
+        };
+        node_a.publish(event.clone());
+        let received = events_b.recv().await.unwrap();
+        assert_eq!(received.origin, node_a.node_id());
+        assert_eq!(received.event, event);
+
+        node_b.publish(event.clone());
+        let received = events_a.recv().await.unwrap();
+        assert_eq!(received.origin, node_b.node_id());
+    }
+
+    #[tokio::test]
+    async fn test_nodes_without_the_secret_are_rejected() {
+        let node_a = TcpBus::start(&config(Vec::new())).unwrap();
+        let mut other_config = config(vec![node_a.local_addr().to_string()]);
+        other_config.secret = "wrong-secret".to_string();
+        let node_b = TcpBus::start(&other_config).unwrap();
+
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(node_a.connected_peers(), 0);
+        assert_eq!(node_b.connected_peers(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_long_lines_close_the_connection() {
+        let node_a = TcpBus::start(&config(Vec::new())).unwrap();
+        let mut events_a = node_a.subscribe();
+
+        // Connect as a node that knows the secret
+        let stream = TcpStream::connect(node_a.local_addr()).await.unwrap();
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = FramedRead::new(reader, LinesCodec::new());
+        let challenge = read_line(&mut lines).await.unwrap();
+        write_line(&mut writer, "challenge").await.unwrap();
+        let secret = config(Vec::new()).secret;
+        write_line(&mut writer, &Side::Connected.proof(&secret, &challenge))
+            .await
+            .unwrap();
+        assert_eq!(
+            read_line(&mut lines).await.unwrap(),
+            Side::Accepted.proof(&secret, "challenge")
+        );
+        wait_for_peers(&node_a, 1).await;
+
+        let message = ClusterMessage {
+            origin: "node-b".to_string(),
+            event: RoomEvent::RoomDeleted {
+                room: "lobby".to_string(),
+            },
+        };
+        write_line(&mut writer, &serde_json::to_string(&message).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(events_a.recv().await.unwrap(), message);
+
+        // The line is not buffered without a limit, the connection is closed instead
+        write_line(&mut writer, &"x".repeat(MAX_LINE_LENGTH + 1))
+            .await
+            .unwrap();
+        assert!(lines.next().await.is_none());
+        assert_eq!(node_a.connected_peers(), 0);
+    }
+}
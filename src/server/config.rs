@@ -1,4 +1,16 @@
+use std::fs;
+
 use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+
+use super::{
+    cluster::ClusterConfig, frontend::FrontendConfig, rate_limit::RateLimitConfig,
+    shutdown::ShutdownConfig,
+};
+use crate::{
+    core::outbox::OutboundConfig,
+    utils::{hash::hash_str, logging::LoggingConfig},
+};
 
 fn default_admin() -> String {
     return "admin".to_string();
@@ -19,6 +31,18 @@ fn default_max_users() -> usize {
     return 4;
 }
 
+fn default_max_message_length() -> usize {
+    return 1000;
+}
+
+fn default_host() -> String {
+    return "127.0.0.1".to_string();
+}
+
+fn default_port() -> u16 {
+    return 8080;
+}
+
 fn min_room_count() -> usize {
     return 3;
 }
@@ -27,18 +51,27 @@ fn max_user_count_per_room() -> usize {
     return 5;
 }
 
+fn default_room_name_max_length() -> usize {
+    return 50;
+}
+
 /**
  * Admin config defined in the server config file!
  */
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct AdminConfig {
     #[serde(default = "default_admin")]
     name: String,
+
+    #[serde(skip_serializing)]
     token: String,
+    // Not used by the server yet, the admin API uses the token
+    #[serde(skip_serializing)]
+    #[allow(dead_code)]
     password_hash: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct GeneralServerConfig {
     #[serde(default = "default_server_name")]
     server_name: String,
@@ -54,14 +87,18 @@ pub struct GeneralServerConfig {
 
     #[serde(default = "default_max_users")]
     max_user_count: usize,
+
+    /// Max amount of characters in a chat message
+    #[serde(default = "default_max_message_length")]
+    max_message_length: usize,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct RoomConfig {
     #[serde(default = "min_room_count")]
     max_room_count: usize,
 
-    #[serde(default)]
+    #[serde(default, alias = "room_password_required")]
     password_required: bool,
 
     #[serde(default)]
@@ -69,11 +106,505 @@ pub struct RoomConfig {
 
     #[serde(default = "max_user_count_per_room")]
     room_capacity: usize,
+
+    /// Max amount of characters in the name of a room
+    #[serde(default = "default_room_name_max_length")]
+    room_name_max_length: usize,
+}
+
+/// Certificate and private key used for serving over TLS (https and wss)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TlsConfig {
+    /// PEM file with the certificate chain
+    pub cert_file: String,
+
+    /// PEM file with the private key of the certificate
+    pub key_file: String,
+}
+
+/// Address the server listens on
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NetworkConfig {
+    #[serde(default = "default_host")]
+    pub host: String,
+
+    #[serde(default = "default_port")]
+    pub port: u16,
+
+    /// Serve over TLS when given
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            host: default_host(),
+            port: default_port(),
+            tls: None,
+        }
+    }
+}
+
+/// Config of the server, read from the server config file
+///
+/// Secrets are marked with `skip_serializing`, so they are never written out, e.g. when the admin API shows the config.
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct ServerConfig {
     admin: AdminConfig,
     general: GeneralServerConfig,
+
+    #[serde(rename = "room_config")]
     room: RoomConfig,
+
+    #[serde(default)]
+    rate_limit: RateLimitConfig,
+
+    #[serde(default)]
+    network: NetworkConfig,
+
+    #[serde(default)]
+    outbound: OutboundConfig,
+
+    /// Runs the server as a node of a cluster when given
+    #[serde(default)]
+    cluster: Option<ClusterConfig>,
+
+    #[serde(default)]
+    logging: LoggingConfig,
+
+    #[serde(default)]
+    shutdown: ShutdownConfig,
+
+    #[serde(default)]
+    frontend: FrontendConfig,
+
+    /// Ids of users that can not connect to the server, as listed by the admin API
+    #[serde(default)]
+    banned_users: Vec<String>,
+}
+
+/// Sections of the config that are only read when the server starts
+const RESTART_SECTIONS: [&str; 5] = ["network", "cluster", "logging", "shutdown", "frontend"];
+
+/// Adds the paths of the settings that differ between the two values, like `rate_limit.chat.capacity`
+fn changed_paths(
+    path: &str,
+    old: &serde_json::Value,
+    new: &serde_json::Value,
+    changed: &mut Vec<String>,
+) {
+    match (old, new) {
+        (serde_json::Value::Object(old), serde_json::Value::Object(new)) => {
+            let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let child_path = if path.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                let null = serde_json::Value::Null;
+                changed_paths(
+                    &child_path,
+                    old.get(key).unwrap_or(&null),
+                    new.get(key).unwrap_or(&null),
+                    changed,
+                );
+            }
+        }
+        _ if old != new => changed.push(path.to_string()),
+        _ => (),
+    }
+}
+
+impl ServerConfig {
+    pub fn get_network(&self) -> &NetworkConfig {
+        &self.network
+    }
+
+    pub fn get_outbound(&self) -> &OutboundConfig {
+        &self.outbound
+    }
+
+    pub fn get_cluster(&self) -> Option<&ClusterConfig> {
+        self.cluster.as_ref()
+    }
+
+    pub fn get_logging(&self) -> &LoggingConfig {
+        &self.logging
+    }
+
+    pub fn get_shutdown(&self) -> &ShutdownConfig {
+        &self.shutdown
+    }
+
+    pub fn get_frontend(&self) -> &FrontendConfig {
+        &self.frontend
+    }
+
+    pub fn get_general(&self) -> &GeneralServerConfig {
+        &self.general
+    }
+
+    pub fn get_admin_name(&self) -> &str {
+        &self.admin.name
+    }
+
+    /// Checks the token of the admin API, no token is accepted when the admin token is empty
+    ///
+    /// The hashes are compared, so the time taken does not tell how much of the token was right
+    pub fn is_admin_token(&self, token: &str) -> bool {
+        if self.admin.token.is_empty() {
+            return false;
+        }
+        return hash_str(token) == hash_str(&self.admin.token);
+    }
+
+    pub fn get_welcome_message(&self) -> Option<&str> {
+        self.general.welcome_message.as_deref()
+    }
+
+    pub fn get_max_room_count(&self) -> usize {
+        self.room.max_room_count
+    }
+
+    pub fn get_banned_users(&self) -> &[String] {
+        &self.banned_users
+    }
+
+    pub fn get_room_capacity(&self) -> usize {
+        self.room.room_capacity
+    }
+
+    pub fn get_room_name_max_length(&self) -> usize {
+        self.room.room_name_max_length
+    }
+
+    pub fn get_max_message_length(&self) -> usize {
+        self.general.max_message_length
+    }
+
+    pub fn get_rate_limit(&self) -> &RateLimitConfig {
+        &self.rate_limit
+    }
+
+    /// Checks for values the server can not run with
+    pub fn validate(&self) -> Result<(), String> {
+        if self.general.max_message_length == 0 {
+            return Err("general.max_message_length must be at least 1".to_string());
+        }
+        if self.room.max_room_count == 0 {
+            return Err("room_config.max_room_count must be at least 1".to_string());
+        }
+        if self.room.room_capacity == 0 {
+            return Err("room_config.room_capacity must be at least 1".to_string());
+        }
+        if self.room.room_name_max_length == 0 {
+            return Err("room_config.room_name_max_length must be at least 1".to_string());
+        }
+        if self.outbound.queue_size == 0 {
+            return Err("outbound.queue_size must be at least 1".to_string());
+        }
+        self.frontend.validate()?;
+        return self.rate_limit.validate();
+    }
+
+    /// Keeps the settings of the running config that can only be changed with a restart
+    ///
+    /// Returns the sections that were changed in the new config, and were not applied
+    pub fn keep_restart_settings(&mut self, running: &ServerConfig) -> Vec<&'static str> {
+        let new = serde_json::to_value(&*self).unwrap_or_default();
+        let old = serde_json::to_value(running).unwrap_or_default();
+        let mut ignored: Vec<&'static str> = RESTART_SECTIONS
+            .into_iter()
+            .filter(|section| new.get(section) != old.get(section))
+            .collect();
+
+        // The secret of the cluster is never written out, so it is compared on its own
+        let secret = |config: &ServerConfig| {
+            config
+                .cluster
+                .as_ref()
+                .map(|cluster| cluster.secret.clone())
+        };
+        if !ignored.contains(&"cluster") && secret(self) != secret(running) {
+            ignored.push("cluster");
+        }
+
+        self.network = running.network.clone();
+        self.cluster = running.cluster.clone();
+        self.logging = running.logging.clone();
+        self.shutdown = running.shutdown.clone();
+        self.frontend = running.frontend.clone();
+        ignored
+    }
+
+    /// Returns the paths of the settings that differ from the given config, secrets are only named
+    pub fn changed_settings(&self, old: &ServerConfig) -> Vec<String> {
+        let mut changed = Vec::new();
+        changed_paths(
+            "",
+            &serde_json::to_value(old).unwrap_or_default(),
+            &serde_json::to_value(self).unwrap_or_default(),
+            &mut changed,
+        );
+        if self.admin.token != old.admin.token {
+            changed.push("admin.token".to_string());
+        }
+        if self.admin.password_hash != old.admin.password_hash {
+            changed.push("admin.password_hash".to_string());
+        }
+        changed
+    }
+}
+
+impl Default for ServerConfig {
+    /// Config used when the server is started without a config file
+    ///
+    /// The admin token is empty, so nobody can authenticate as admin
+    fn default() -> Self {
+        Self {
+            admin: AdminConfig {
+                name: default_admin(),
+                token: String::new(),
+                password_hash: String::new(),
+            },
+            general: GeneralServerConfig {
+                server_name: default_server_name(),
+                description: default_server_description(),
+                server_version: default_server_version(),
+                welcome_message: None,
+                max_user_count: default_max_users(),
+                max_message_length: default_max_message_length(),
+            },
+            room: RoomConfig {
+                max_room_count: min_room_count(),
+                password_required: false,
+                allow_room_creation: false,
+                room_capacity: max_user_count_per_room(),
+                room_name_max_length: default_room_name_max_length(),
+            },
+            rate_limit: RateLimitConfig::default(),
+            network: NetworkConfig::default(),
+            outbound: OutboundConfig::default(),
+            cluster: None,
+            logging: LoggingConfig::default(),
+            shutdown: ShutdownConfig::default(),
+            frontend: FrontendConfig::default(),
+            banned_users: Vec::new(),
+        }
+    }
+}
+
+pub fn parse_server_config(file_path: &str) -> Option<ServerConfig> {
+    return load_server_config(file_path).ok();
+}
+
+/// Reads and parses the server config file, the error tells what is wrong with the file
+pub fn load_server_config(file_path: &str) -> Result<ServerConfig, String> {
+    let yaml_data = fs::read_to_string(file_path)
+        .map_err(|err| format!("Server config {} could not be read: {}", file_path, err))?;
+    return load_server_config_yml(&yaml_data);
+}
+
+#[cfg(test)]
+pub(crate) fn parse_server_config_yml(yaml_data: String) -> Option<ServerConfig> {
+    return load_server_config_yml(&yaml_data).ok();
+}
+
+fn load_server_config_yml(yaml_data: &str) -> Result<ServerConfig, String> {
+    // Parse the server
+    let parsed_yaml: Value = serde_yaml::from_str(yaml_data).map_err(|err| err.to_string())?;
+    match parsed_yaml.get("server") {
+        Some(server) => serde_yaml::from_value(server.clone()).map_err(|err| err.to_string()),
+        None => Err("The config has no server section".to_string()),
+    }
+}
+
+// Unit test for Server config check
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{core::outbox::OverflowPolicy, utils::logging::LogFormat};
+
+    #[test]
+    fn test_parse_valid_config() {
+        let yaml = r#"
+        server:
+          admin:
+            name: "admin"
+            token: "123dfgfaerw47ur6d"
+            password_hash: "awtsy5e4ha"
+          general:
+            server_name: "MyChatServer"
+            description: "A secure chat server for friends and colleagues."
+            server_version: "1.0.0"
+            welcome_message: "Welcome to MyChatServer!"
+            max_user_count: 1000
+            max_message_length: 500
+            server_uptime: 72
+          room_config:
+            room_password_required: true
+            max_room_count: 4
+            default_room_type: "public"
+            room_name_max_length: 50
+            allow_room_creation: true
+            room_capacity: 20
+          rate_limit:
+            chat:
+              capacity: 3
+              refill_per_sec: 0.5
+            warnings_before_mute: 1
+          network:
+            host: 0.0.0.0
+            port: 8443
+            tls:
+              cert_file: cert.pem
+              key_file: key.pem
+          outbound:
+            overflow: disconnect
+          logging:
+            level: "info,rustic_reach::server::cluster=debug"
+        "#;
+
+        let result = parse_server_config_yml(yaml.to_string());
+        assert!(result.is_some());
+        let config = result.unwrap();
+
+        assert_eq!(config.admin.token, "123dfgfaerw47ur6d");
+        assert!(config.is_admin_token("123dfgfaerw47ur6d"));
+        assert!(!config.is_admin_token("123dfgfaerw47ur6"));
+        assert_eq!(config.general.max_user_count, 1000);
+        assert_eq!(config.get_max_message_length(), 500);
+        assert!(config.room.password_required);
+        assert_eq!(config.room.max_room_count, 4);
+
+        // Rate limits that are not given use the defaults
+        let rate_limit = config.get_rate_limit();
+        assert_eq!(rate_limit.chat.capacity, 3.0);
+        assert_eq!(rate_limit.chat.refill_per_sec, 0.5);
+        assert_eq!(rate_limit.warnings_before_mute, 1);
+        assert_eq!(
+            rate_limit.commands.capacity,
+            RateLimitConfig::default().commands.capacity
+        );
+
+        let network = config.get_network();
+        assert_eq!(network.host, "0.0.0.0");
+        assert_eq!(network.port, 8443);
+        assert_eq!(network.tls.as_ref().unwrap().cert_file, "cert.pem");
+
+        let outbound = config.get_outbound();
+        assert_eq!(outbound.overflow, OverflowPolicy::Disconnect);
+        assert_eq!(outbound.queue_size, OutboundConfig::default().queue_size);
+
+        let logging = config.get_logging();
+        assert_eq!(logging.level, "info,rustic_reach::server::cluster=debug");
+        assert_eq!(logging.format, LogFormat::Json);
+    }
+
+    #[test]
+    fn test_parse_config_with_missing_optional_fields() {
+        let yaml = r#"
+        server:
+          admin:
+            token: "123dfgfaerw47ur6d"
+            password_hash: "awtsy5e4ha"
+          general: {}
+          room_config: {}
+        "#;
+
+        let result = parse_server_config_yml(yaml.to_string());
+        assert!(result.is_some());
+        let config = result.unwrap();
+
+        assert_eq!(config.admin.name, "admin");
+        assert_eq!(config.general.server_name, "MyHostedServer");
+        assert_eq!(config.get_max_message_length(), 1000);
+        assert!(config.general.welcome_message.is_none());
+        assert_eq!(config.room.room_capacity, 5);
+
+        // Without a network section, the server listens on localhost without TLS
+        assert_eq!(config.get_network().host, "127.0.0.1");
+        assert_eq!(config.get_network().port, 8080);
+        assert!(config.get_network().tls.is_none());
+    }
+
+    #[test]
+    fn test_parse_config_with_missing_required_fields() {
+        let yaml = r#"
+        server:
+          general: {}
+          room_config: {}
+        "#;
+
+        // The admin section is required
+        assert!(parse_server_config_yml(yaml.to_string()).is_none());
+    }
+
+    #[test]
+    fn test_validate_config() {
+        let mut config = ServerConfig::default();
+        assert!(config.validate().is_ok());
+
+        config.room.max_room_count = 0;
+        assert!(config.validate().is_err());
+
+        let mut config = ServerConfig::default();
+        config.rate_limit.chat.refill_per_sec = 0.0;
+        assert_eq!(
+            config.validate().unwrap_err(),
+            "rate_limit.chat.refill_per_sec must be more than 0"
+        );
+    }
+
+    #[test]
+    fn test_changed_settings_on_reload() {
+        let running = ServerConfig::default();
+        let mut reloaded = ServerConfig::default();
+        reloaded.general.welcome_message = Some("Hello".to_string());
+        reloaded.rate_limit.chat.capacity = 10.0;
+        reloaded.admin.token = "new token".to_string();
+        reloaded.network.port = 9090;
+
+        // The address is only read at start, so the running one is kept
+        assert_eq!(reloaded.keep_restart_settings(&running), vec!["network"]);
+        assert_eq!(reloaded.get_network().port, 8080);
+
+        // The cluster secret is not written out, but a new one still needs a restart
+        let cluster = |secret: &str| ClusterConfig {
+            node_id: None,
+            listen: "127.0.0.1:9000".to_string(),
+            peers: Vec::new(),
+            secret: secret.to_string(),
+        };
+        let clustered = ServerConfig {
+            cluster: Some(cluster("old secret")),
+            ..ServerConfig::default()
+        };
+        let mut new_secret = clustered.clone();
+        new_secret.cluster = Some(cluster("new secret"));
+        assert_eq!(
+            new_secret.keep_restart_settings(&clustered),
+            vec!["cluster"]
+        );
+
+        // Secrets are named, but their values are never shown
+        assert_eq!(
+            reloaded.changed_settings(&running),
+            vec![
+                "general.welcome_message",
+                "rate_limit.chat.capacity",
+                "admin.token"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_repository_config() {
+        let file_path = concat!(env!("CARGO_MANIFEST_DIR"), "/server.yml");
+        assert!(parse_server_config(file_path).is_some());
+    }
 }
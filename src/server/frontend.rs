@@ -0,0 +1,236 @@
+use std::path::Path;
+
+use actix_files::Files;
+use actix_web::{
+    http::header::{self, CacheControl, CacheDirective, EntityTag, IfNoneMatch},
+    middleware::DefaultHeaders,
+    web, HttpMessage, HttpRequest, HttpResponse,
+};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::hash::hash_str;
+
+fn default_enabled() -> bool {
+    return true;
+}
+
+/**
+ * Frontend config defined in the server config file
+ *
+ * The web frontend is built into the server binary. A directory can be served instead, e.g. for a custom frontend.
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FrontendConfig {
+    /// Serve the web frontend at `/`
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+
+    /// Directory with an `index.html` served instead of the built in frontend
+    #[serde(default)]
+    pub dir: Option<String>,
+}
+
+impl Default for FrontendConfig {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            dir: None,
+        }
+    }
+}
+
+impl FrontendConfig {
+    /// Checks that the directory to serve exists
+    pub fn validate(&self) -> Result<(), String> {
+        match &self.dir {
+            Some(dir) if self.enabled && !Path::new(dir).is_dir() => {
+                Err(format!("frontend.dir {} is not a directory", dir))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// File of the frontend built into the binary
+struct Asset {
+    path: &'static str,
+    content_type: &'static str,
+    content: &'static str,
+    etag: EntityTag,
+}
+
+impl Asset {
+    fn new(path: &'static str, content_type: &'static str, content: &'static str) -> Self {
+        // The tag changes with the content, so browsers revalidate after the server is updated
+        let etag = EntityTag::new_strong(hash_str(content)[..16].to_string());
+        Self {
+            path,
+            content_type,
+            content,
+            etag,
+        }
+    }
+}
+
+static ASSETS: Lazy<[Asset; 3]> = Lazy::new(|| {
+    [
+        Asset::new(
+            "index.html",
+            "text/html; charset=utf-8",
+            include_str!("../frontend/index.html"),
+        ),
+        Asset::new(
+            "script.js",
+            "text/javascript; charset=utf-8",
+            include_str!("../frontend/script.js"),
+        ),
+        Asset::new(
+            "style.css",
+            "text/css; charset=utf-8",
+            include_str!("../frontend/style.css"),
+        ),
+    ]
+});
+
+/// The file names are the same for every version, so browsers have to check with the server before using their copy
+fn cache_control() -> CacheControl {
+    CacheControl(vec![CacheDirective::NoCache])
+}
+
+/// Serves a file of the built in frontend, or 304 when the browser has the same version
+async fn embedded_asset(req: HttpRequest, file: web::Path<String>) -> HttpResponse {
+    let file = file.into_inner();
+    let file = if file.is_empty() { "index.html" } else { &file };
+    let Some(asset) = ASSETS.iter().find(|asset| asset.path == file) else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    let not_modified = match req.get_header::<IfNoneMatch>() {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&asset.etag)),
+        None => false,
+    };
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .insert_header(header::ETag(asset.etag.clone()))
+        .insert_header(cache_control());
+    if not_modified {
+        return response.finish();
+    }
+    response
+        .content_type(asset.content_type)
+        .body(asset.content)
+}
+
+/// Registers the web frontend at `/`, must be configured after every other route
+pub fn configure_frontend(config: &FrontendConfig) -> impl FnOnce(&mut web::ServiceConfig) {
+    let config = config.clone();
+    move |cfg| {
+        if !config.enabled {
+            return;
+        }
+        match &config.dir {
+            // Files sets the ETag and Last-Modified headers, browsers are told to check them on every use
+            Some(dir) => {
+                cfg.service(
+                    web::scope("")
+                        .wrap(DefaultHeaders::new().add(cache_control()))
+                        .service(Files::new("/", dir).index_file("index.html")),
+                );
+            }
+            None => {
+                cfg.route("/{file:.*}", web::get().to(embedded_asset));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, test, App};
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_embedded_frontend() {
+        let app = test::init_service(
+            App::new().configure(configure_frontend(&FrontendConfig::default())),
+        )
+        .await;
+
+        let response =
+            test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(
+            response.headers().get(header::CACHE_CONTROL).unwrap(),
+            "no-cache"
+        );
+        let etag = response.headers().get(header::ETAG).unwrap().clone();
+
+        // The browser has the same version
+        let request = test::TestRequest::get()
+            .uri("/index.html")
+            .insert_header((header::IF_NONE_MATCH, etag))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        let request = test::TestRequest::get().uri("/style.css").to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(
+            response.headers().get(header::CONTENT_TYPE).unwrap(),
+            "text/css; charset=utf-8"
+        );
+
+        let request = test::TestRequest::get().uri("/server.yml").to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_disabled_frontend() {
+        let config = FrontendConfig {
+            enabled: false,
+            dir: None,
+        };
+        let app = test::init_service(App::new().configure(configure_frontend(&config))).await;
+
+        let response =
+            test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_frontend_from_directory() {
+        let dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(dir.path().join("index.html"), "<h1>Custom</h1>").unwrap();
+        let config = FrontendConfig {
+            enabled: true,
+            dir: Some(dir.path().to_str().unwrap().to_string()),
+        };
+        assert!(config.validate().is_ok());
+        let app = test::init_service(App::new().configure(configure_frontend(&config))).await;
+
+        let response =
+            test::call_service(&app, test::TestRequest::get().uri("/").to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(header::CACHE_CONTROL).unwrap(),
+            "no-cache"
+        );
+        assert!(response.headers().contains_key(header::ETAG));
+        assert_eq!(test::read_body(response).await, "<h1>Custom</h1>");
+
+        dir.close().unwrap();
+        assert!(config.validate().is_err());
+    }
+}
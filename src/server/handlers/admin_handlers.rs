@@ -0,0 +1,549 @@
+use std::{
+    collections::HashMap,
+    future::{ready, Ready},
+};
+
+use actix_web::{
+    dev::Payload, error::InternalError, http::header::AUTHORIZATION, web, FromRequest, HttpRequest,
+    HttpResponse, Scope,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::info;
+
+use crate::{
+    core::{
+        messages::ServerMessage,
+        outbox::broadcast_frame,
+        protocol::PROTOCOL_VERSION,
+        room::room::{SharedRoom, WebRoom},
+    },
+    server::{
+        cluster::{ClusterBus, RoomEvent},
+        reload::{reload_config, LiveConfig},
+        roster::send_room_list,
+        sessions::Sessions,
+        shutdown::{Shutdown, ShutdownNotice},
+    },
+    utils::traits::JsonSerializing,
+};
+
+/**
+ * The admin of the server, extracted from the bearer token of a request
+ *
+ * Requests without the token of the admin config are rejected with 401.
+ * When the admin token is empty, the admin API can not be used at all.
+ */
+pub struct Admin;
+
+impl FromRequest for Admin {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let token = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|header| header.to_str().ok())
+            .and_then(|header| header.strip_prefix("Bearer "));
+        let config = req.app_data::<web::Data<LiveConfig>>();
+
+        let result = match (config, token) {
+            (Some(config), Some(token)) if config.get().is_admin_token(token) => Ok(Admin),
+            _ => {
+                let response = HttpResponse::Unauthorized()
+                    .insert_header(("WWW-Authenticate", "Bearer"))
+                    .json(json!({ "error": "Invalid admin token" }));
+                Err(InternalError::from_response("Invalid admin token", response).into())
+            }
+        };
+        ready(result)
+    }
+}
+
+/// Room as listed by the admin API
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RoomSummary {
+    pub name: String,
+    pub id: String,
+    pub capacity: usize,
+    pub members: usize,
+}
+
+/// Body of a request creating a room, the capacity of the room config is used when not given
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewRoom {
+    pub name: String,
+
+    #[serde(default)]
+    pub capacity: Option<usize>,
+}
+
+/// Session as listed by the admin API
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserSummary {
+    pub session_id: String,
+    pub user_name: String,
+    pub rooms: Vec<String>,
+
+    /// Names used in single rooms instead of the user name, by room
+    #[serde(default)]
+    pub nicknames: HashMap<String, String>,
+    pub authenticated: bool,
+
+    /// Id of the authenticated user, can be added to the banned users of the config
+    pub user_id: Option<String>,
+}
+
+/// Optional body of a kick or ban, the reason is shown to the user
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Moderation {
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// Body of a request posting an announcement
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewAnnouncement {
+    pub message: String,
+}
+
+/// Optional body of a shutdown, the shutdown config is used for what is not given
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ShutdownRequest {
+    #[serde(default)]
+    pub reason: Option<String>,
+
+    #[serde(default)]
+    pub reconnect_after: Option<u64>,
+}
+
+fn error_response(mut response: actix_web::HttpResponseBuilder, message: &str) -> HttpResponse {
+    response.json(json!({ "error": message }))
+}
+
+/// Lets the members of a deleted room know that the room is gone
+pub async fn notify_room_deleted(room: SharedRoom) {
+    let room = room.lock().await;
+    let message = ServerMessage::RoomActionError(format!("Room {} was deleted", room.name()));
+    if let Some(frame) = message.frame() {
+        broadcast_frame(&frame, &room.outboxes());
+    }
+
+    // The clients drop the room from the rooms the user is in
+    ServerMessage::LeftRoom(room.name()).broadcast_msg(&room.outboxes());
+}
+
+async fn server_info(
+    _: Admin,
+    config: web::Data<LiveConfig>,
+    server_rooms: WebRoom,
+    sessions: web::Data<Sessions>,
+    bus: web::Data<dyn ClusterBus>,
+) -> HttpResponse {
+    let users = sessions.users();
+    let authenticated = users.iter().filter(|user| user.get_id().is_some()).count();
+
+    HttpResponse::Ok().json(json!({
+        "server": config.get().get_general(),
+        "protocol_version": PROTOCOL_VERSION,
+        "node_id": bus.node_id(),
+        "sessions": users.len(),
+        "users_authenticated": authenticated,
+        "rooms": server_rooms.room_count(),
+    }))
+}
+
+/// The config the server is running with, without the secrets of the admin
+async fn server_config(_: Admin, config: web::Data<LiveConfig>) -> HttpResponse {
+    HttpResponse::Ok().json(&*config.get())
+}
+
+async fn list_rooms(_: Admin, server_rooms: WebRoom) -> HttpResponse {
+    let mut rooms = Vec::new();
+    for (name, room) in server_rooms.rooms() {
+        let room = room.lock().await;
+        rooms.push(RoomSummary {
+            name,
+            id: room.id().to_string(),
+            capacity: room.capacity(),
+            members: room.joined_user_count(),
+        });
+    }
+    rooms.sort_by(|a, b| a.name.cmp(&b.name));
+
+    HttpResponse::Ok().json(rooms)
+}
+
+async fn create_room(
+    _: Admin,
+    body: web::Json<NewRoom>,
+    config: web::Data<LiveConfig>,
+    server_rooms: WebRoom,
+    sessions: web::Data<Sessions>,
+    bus: web::Data<dyn ClusterBus>,
+) -> HttpResponse {
+    let NewRoom { name, capacity } = body.into_inner();
+    if name.trim().is_empty() {
+        return error_response(HttpResponse::BadRequest(), "Room name can not be empty");
+    }
+
+    // Rooms created without a capacity follow the room config, also when it is reloaded
+    let own_capacity = capacity.is_some();
+    let capacity = capacity.unwrap_or_else(|| config.get().get_room_capacity());
+    let owner_id = format!("admin:{}", config.get().get_admin_name());
+    match server_rooms.create_room_with_owner_id(
+        name.clone(),
+        capacity,
+        own_capacity,
+        owner_id.clone(),
+    ) {
+        Ok(_) => {
+            info!(room = %name, capacity, "Admin created a room");
+
+            // Let the other nodes of the cluster know about the room
+            bus.publish(RoomEvent::RoomCreated {
+                room: name.clone(),
+                owner_id,
+                capacity,
+                own_capacity,
+            });
+            send_room_list(&server_rooms, &sessions).await;
+            HttpResponse::Created().json(json!({ "name": name, "capacity": capacity }))
+        }
+        Err(err) => error_response(HttpResponse::Conflict(), &err.message()),
+    }
+}
+
+async fn delete_room(
+    _: Admin,
+    path: web::Path<String>,
+    server_rooms: WebRoom,
+    sessions: web::Data<Sessions>,
+    bus: web::Data<dyn ClusterBus>,
+) -> HttpResponse {
+    let room_name = path.into_inner();
+    match server_rooms.delete_room(&room_name) {
+        Some(room) => {
+            info!(room = %room_name, "Admin deleted a room");
+            sessions.remove_room(&room_name);
+            bus.publish(RoomEvent::RoomDeleted { room: room_name });
+            notify_room_deleted(room).await;
+            send_room_list(&server_rooms, &sessions).await;
+            HttpResponse::NoContent().finish()
+        }
+        None => error_response(HttpResponse::NotFound(), "Room does not exists"),
+    }
+}
+
+async fn list_users(_: Admin, sessions: web::Data<Sessions>) -> HttpResponse {
+    let mut users: Vec<UserSummary> = sessions
+        .users()
+        .iter()
+        .map(|user| UserSummary {
+            session_id: user.get_session_id().to_string(),
+            user_name: user.get_user_name().to_string(),
+            rooms: user.get_rooms().to_vec(),
+            nicknames: user.get_nicknames().clone(),
+            authenticated: user.get_id().is_some(),
+            user_id: user.get_id().map(str::to_string),
+        })
+        .collect();
+    users.sort_by(|a, b| a.user_name.cmp(&b.user_name));
+
+    HttpResponse::Ok().json(users)
+}
+
+async fn kick_user(
+    _: Admin,
+    path: web::Path<String>,
+    body: Option<web::Json<Moderation>>,
+    server_rooms: WebRoom,
+    sessions: web::Data<Sessions>,
+) -> HttpResponse {
+    let Some(user) = sessions.get(&path) else {
+        return error_response(HttpResponse::NotFound(), "Session does not exists");
+    };
+    let reason = body
+        .and_then(|body| body.into_inner().reason)
+        .unwrap_or_else(|| "Kicked by the admin".to_string());
+
+    info!(user = user.get_user_name(), reason = %reason, "Admin kicked a user");
+    sessions.disconnect(&user, &server_rooms, reason).await;
+    HttpResponse::Ok().json(json!({ "disconnected": 1 }))
+}
+
+/// Bans the user of the session, and disconnects every session of the user
+async fn ban_user(
+    _: Admin,
+    path: web::Path<String>,
+    body: Option<web::Json<Moderation>>,
+    server_rooms: WebRoom,
+    sessions: web::Data<Sessions>,
+) -> HttpResponse {
+    let Some(user) = sessions.get(&path) else {
+        return error_response(HttpResponse::NotFound(), "Session does not exists");
+    };
+    let Some(user_id) = user.get_id() else {
+        return error_response(
+            HttpResponse::Conflict(),
+            "User has not authenticated, kick the session instead",
+        );
+    };
+    let reason = body
+        .and_then(|body| body.into_inner().reason)
+        .unwrap_or_else(|| "Banned by the admin".to_string());
+
+    info!(user = user.get_user_name(), reason = %reason, "Admin banned a user");
+    sessions.ban(user_id);
+    let banned_sessions = sessions.sessions_of(user_id);
+    for session in &banned_sessions {
+        sessions
+            .disconnect(session, &server_rooms, reason.clone())
+            .await;
+    }
+    HttpResponse::Ok().json(json!({ "disconnected": banned_sessions.len() }))
+}
+
+/// Sends an announcement to every user connected to this server
+async fn announce(
+    _: Admin,
+    body: web::Json<NewAnnouncement>,
+    config: web::Data<LiveConfig>,
+    sessions: web::Data<Sessions>,
+) -> HttpResponse {
+    let message = body.into_inner().message;
+    if message.trim().is_empty() {
+        return error_response(HttpResponse::BadRequest(), "Announcement can not be empty");
+    }
+    if message.chars().count() > config.get().get_max_message_length() {
+        return error_response(HttpResponse::BadRequest(), "Announcement is too long");
+    }
+
+    let outboxes = sessions.outboxes();
+    if let Some(frame) = ServerMessage::Announcement(message).frame() {
+        broadcast_frame(&frame, &outboxes);
+    }
+    info!(recipients = outboxes.len(), "Admin posted an announcement");
+    HttpResponse::Ok().json(json!({ "recipients": outboxes.len() }))
+}
+
+/// Shuts the server down, the users are let know before their websockets are closed
+async fn shut_down(
+    _: Admin,
+    body: Option<web::Json<ShutdownRequest>>,
+    config: web::Data<LiveConfig>,
+    shutdown: web::Data<Shutdown>,
+) -> HttpResponse {
+    let request = body.map(|body| body.into_inner()).unwrap_or_default();
+    let notice = ShutdownNotice {
+        reason: request
+            .reason
+            .unwrap_or_else(|| "Server is shutting down".to_string()),
+        reconnect_after: request
+            .reconnect_after
+            .or(config.get().get_shutdown().reconnect_after_secs),
+    };
+
+    if shutdown.trigger(notice) {
+        info!("Admin requested a shutdown");
+        HttpResponse::Accepted().json(json!({ "shutting_down": true }))
+    } else {
+        error_response(HttpResponse::Conflict(), "Server is already shutting down")
+    }
+}
+
+/// Reloads the server config file, the current config is kept when the new one is invalid
+async fn reload(
+    _: Admin,
+    config: web::Data<LiveConfig>,
+    server_rooms: WebRoom,
+    sessions: web::Data<Sessions>,
+) -> HttpResponse {
+    if config.get_path().is_none() {
+        return error_response(
+            HttpResponse::Conflict(),
+            "The server was started without a config file",
+        );
+    }
+    match reload_config(&config, &server_rooms, &sessions).await {
+        Ok(changed) => HttpResponse::Ok().json(json!({ "changed": changed })),
+        Err(err) => error_response(HttpResponse::BadRequest(), &err),
+    }
+}
+
+/// Routes of the admin API, every route needs the admin token as bearer token
+pub fn admin_scope() -> Scope {
+    web::scope("/admin")
+        .route("/info", web::get().to(server_info))
+        .route("/config", web::get().to(server_config))
+        .route("/rooms", web::get().to(list_rooms))
+        .route("/rooms", web::post().to(create_room))
+        .route("/rooms/{room}", web::delete().to(delete_room))
+        .route("/users", web::get().to(list_users))
+        .route("/users/{session_id}/kick", web::post().to(kick_user))
+        .route("/users/{session_id}/ban", web::post().to(ban_user))
+        .route("/announcements", web::post().to(announce))
+        .route("/reload", web::post().to(reload))
+        .route("/shutdown", web::post().to(shut_down))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use actix_web::{http::StatusCode, test, App};
+
+    use super::*;
+    use crate::server::{
+        cluster::local::LocalBus,
+        config::{parse_server_config_yml, ServerConfig},
+    };
+
+    fn config() -> ServerConfig {
+        let yaml = r#"
+            server:
+              admin:
+                token: "secret"
+                password_hash: "hash"
+              general: {}
+              room_config:
+                room_capacity: 7
+            "#;
+        parse_server_config_yml(yaml.to_string()).unwrap()
+    }
+
+    macro_rules! admin_app {
+        ($config:expr) => {{
+            let bus: Arc<dyn ClusterBus> = Arc::new(LocalBus::new());
+            test::init_service(
+                App::new()
+                    .app_data(web::Data::new(Arc::new(
+                        crate::core::room::room::ServerRooms::with_max_room_count(3),
+                    )))
+                    .app_data(web::Data::new(LiveConfig::from($config)))
+                    .app_data(web::Data::new(Sessions::new()))
+                    .app_data(web::Data::new(Shutdown::new()))
+                    .app_data(web::Data::from(bus))
+                    .service(admin_scope()),
+            )
+            .await
+        }};
+    }
+
+    fn authorized(request: test::TestRequest) -> test::TestRequest {
+        request.insert_header((AUTHORIZATION, "Bearer secret"))
+    }
+
+    #[actix_web::test]
+    async fn test_requests_need_the_admin_token() {
+        let app = admin_app!(config());
+
+        let request = test::TestRequest::get().uri("/admin/rooms").to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let request = test::TestRequest::get()
+            .uri("/admin/rooms")
+            .insert_header((AUTHORIZATION, "Bearer wrong"))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let request = authorized(test::TestRequest::get().uri("/admin/rooms")).to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn test_empty_admin_token_disables_the_api() {
+        let app = admin_app!(ServerConfig::default());
+
+        let request = test::TestRequest::get()
+            .uri("/admin/info")
+            .insert_header((AUTHORIZATION, "Bearer "))
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[actix_web::test]
+    async fn test_create_list_and_delete_rooms() {
+        let app = admin_app!(config());
+
+        let request = authorized(test::TestRequest::post().uri("/admin/rooms"))
+            .set_json(NewRoom {
+                name: "lobby".to_string(),
+                capacity: None,
+            })
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        // The name is taken now
+        let request = authorized(test::TestRequest::post().uri("/admin/rooms"))
+            .set_json(NewRoom {
+                name: "lobby".to_string(),
+                capacity: Some(2),
+            })
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let request = authorized(test::TestRequest::get().uri("/admin/rooms")).to_request();
+        let rooms: Vec<RoomSummary> = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(rooms.len(), 1);
+        assert_eq!(rooms[0].name, "lobby");
+        assert_eq!(rooms[0].capacity, 7);
+        assert_eq!(rooms[0].members, 0);
+
+        let request =
+            authorized(test::TestRequest::delete().uri("/admin/rooms/lobby")).to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+        let request =
+            authorized(test::TestRequest::delete().uri("/admin/rooms/lobby")).to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_config_hides_the_admin_secrets() {
+        let app = admin_app!(config());
+
+        let request = authorized(test::TestRequest::get().uri("/admin/config")).to_request();
+        let body = test::call_and_read_body(&app, request).await;
+        let body = String::from_utf8_lossy(&body);
+        assert!(body.contains("room_capacity"));
+        assert!(!body.contains("secret"));
+        assert!(!body.contains("password_hash"));
+    }
+
+    #[actix_web::test]
+    async fn test_reload_needs_a_config_file() {
+        let app = admin_app!(config());
+
+        let request = authorized(test::TestRequest::post().uri("/admin/reload")).to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[actix_web::test]
+    async fn test_unknown_session() {
+        let app = admin_app!(config());
+
+        let request =
+            authorized(test::TestRequest::post().uri("/admin/users/unknown/kick")).to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let request = authorized(test::TestRequest::post().uri("/admin/announcements"))
+            .set_json(NewAnnouncement {
+                message: " ".to_string(),
+            })
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
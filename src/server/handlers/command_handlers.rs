@@ -1,46 +1,80 @@
+use actix_ws::{CloseCode, CloseReason};
+
 use crate::{
     core::{
+        frame::Frame,
         messages::{Command, ServerMessage},
-        room::room::WebRoom,
+        room::room::{RoomError, WebRoom},
         user::user::User,
     },
-    utils::{hash::hash_str, traits::SendServerReply},
+    server::{
+        cluster::{ClusterBus, RoomEvent},
+        config::ServerConfig,
+        metrics::METRICS,
+        roster::{room_list, send_room_list, send_roster},
+        sessions::Sessions,
+    },
+    utils::{constants::MAX_NAME_LENGTH, hash::hash_str, traits::SendServerReply},
 };
 
+/// Reply to commands that need an authenticated user
+const NOT_AUTHENTICATED: &str = "Authenticate before sending commands";
+
+/// Checks the name of a user or a room, and returns it without surrounding whitespace
+///
+/// Names are printed by the clients of other users, so they are checked like the content of chat messages
+fn validate_name(name: &str, max_length: usize) -> Result<&str, String> {
+    let name = name.trim();
+    let length = name.chars().count();
+    if length == 0 {
+        return Err("Names can not be empty".to_string());
+    }
+    if length > max_length {
+        return Err(format!(
+            "Names can not be longer than {} characters",
+            max_length
+        ));
+    }
+    if name.chars().any(char::is_control) {
+        return Err("Names can not contain control characters".to_string());
+    }
+    Ok(name)
+}
+
 async fn join_public_room(
     room_name: String,
     current_user: &mut User,
     server_rooms: &WebRoom,
 ) -> ServerMessage {
-    if let Ok(mut server_rooms_lock) = server_rooms.lock() {
-        if server_rooms_lock.is_room_name_taken(room_name.to_string()) {
-            // Can join the room only if the room is not password protected
-            if let Some(room) = server_rooms_lock.get_room_mut_with_name(room_name.to_string()) {
-                if !room.has_password() {
-                    if !room.contains_user(&current_user) {
-                        // Add the user to the the room
-                        match room.add_user(&current_user) {
-                            Ok(_) => {
-                                // Mutate the state of the user
-                                current_user.set_room(room_name);
-
-                                // Send success message
-                                return ServerMessage::successful_command("Joined room!");
-                            }
-                            Err(err) => {
-                                // Send the error message of the room error
-                                return ServerMessage::room_error_msg(err);
-                            }
+    if let Some(room) = server_rooms.get_room_with_name(&room_name) {
+        let mut room = room.lock().await;
+
+        // Can join the room only if the room is not password protected
+        if !room.has_password() {
+            if !room.contains_user(current_user) {
+                // Add the user to the the room
+                match room.add_user(current_user) {
+                    Ok(_) => {
+                        // Mutate the state of the user, the other rooms of the user are kept
+                        current_user.join_room(room_name.clone());
+                        send_roster(&room);
+
+                        // Send success message, with the latest message of the room for session resume
+                        return ServerMessage::JoinedRoom {
+                            room: room_name,
+                            last_seq: room.last_seq(),
                         };
-                    } else {
-                        // User is already in the room
-                        return ServerMessage::room_error_msg(
-                            crate::core::room::room::RoomError::UserExists(
-                                current_user.get_user_name().to_owned(),
-                            ),
-                        );
                     }
-                }
+                    Err(err) => {
+                        // Send the error message of the room error
+                        return ServerMessage::room_error_msg(err);
+                    }
+                };
+            } else {
+                // User is already in the room
+                return ServerMessage::room_error_msg(RoomError::UserExists(
+                    current_user.get_user_name().to_owned(),
+                ));
             }
         }
     }
@@ -48,70 +82,258 @@ async fn join_public_room(
     return ServerMessage::room_not_found();
 }
 
+/// Puts a reconnected user back into the room it was in, and returns the messages it missed
+///
+/// Adding the user and reading the history happens under the lock of the room,
+/// so every message is either part of the history or broadcasted to the new session.
+/// The history is built from the messages serialized when they were recorded.
+async fn rejoin_room(
+    room_name: String,
+    after: u64,
+    current_user: &mut User,
+    server_rooms: &WebRoom,
+) -> Result<Frame, ServerMessage> {
+    if let Some(room) = server_rooms.get_room_with_name(&room_name) {
+        let mut room = room.lock().await;
+        if !room.has_password() {
+            return match room.add_user(current_user) {
+                Ok(_) => {
+                    current_user.join_room(room_name.clone());
+                    send_roster(&room);
+                    Ok(Frame::history(&room_name, &room.history_after(after)))
+                }
+                Err(err) => Err(ServerMessage::room_error_msg(err)),
+            };
+        }
+    }
+
+    return Err(ServerMessage::room_not_found());
+}
+
+/// Takes the user out of the given room, or out of the room joined last
+async fn leave_room(
+    room_name: Option<String>,
+    current_user: &mut User,
+    server_rooms: &WebRoom,
+) -> ServerMessage {
+    let room_name = match room_name.or_else(|| current_user.get_latest_room().map(str::to_string)) {
+        Some(room_name) => room_name,
+        None => return ServerMessage::failed_command("You are not in any room"),
+    };
+    if !current_user.leave_room(&room_name) {
+        return ServerMessage::room_error_msg(RoomError::NotJoined(room_name));
+    }
+
+    // The room may have been deleted since the user joined it
+    if let Some(room) = server_rooms.get_room_with_name(&room_name) {
+        let mut room = room.lock().await;
+        room.remove_session(current_user);
+        send_roster(&room);
+    }
+
+    return ServerMessage::LeftRoom(room_name);
+}
+
+/// Sets the name of the user in a joined room, the members of the room see the new name
+async fn set_nickname(
+    room_name: String,
+    nickname: String,
+    current_user: &mut User,
+    server_rooms: &WebRoom,
+) -> ServerMessage {
+    if !current_user.is_in_room(&room_name) {
+        return ServerMessage::room_error_msg(RoomError::NotJoined(room_name));
+    }
+    let nickname = match validate_name(&nickname, MAX_NAME_LENGTH) {
+        Ok(nickname) => nickname.to_string(),
+        Err(reason) => return ServerMessage::failed_command(&reason),
+    };
+    current_user.set_nickname(room_name.clone(), nickname.clone());
+
+    // The room keeps a copy of the user, the members see the new name
+    if let Some(room) = server_rooms.get_room_with_name(&room_name) {
+        let mut room = room.lock().await;
+        if room.contains_user(current_user) && room.add_user(current_user).is_ok() {
+            send_roster(&room);
+        }
+    }
+
+    return ServerMessage::state_update(
+        current_user,
+        &format!("Your name in {} is {}", room_name, nickname),
+    );
+}
+
 pub async fn handle_client_command(
     command: &Command,
     current_user: &mut User,
     server_rooms: &WebRoom,
+    bus: &dyn ClusterBus,
+    sessions: &Sessions,
+    config: &ServerConfig,
 ) {
-    // Info log about message
-    match command {
-        Command::SetName(new_name) => {
-            // Changing the name
-            current_user.set_user_name(new_name.to_string());
-
-            // Send update message
-            let msg = ServerMessage::state_update(&current_user, "New user name set");
-            msg.send(&mut current_user.get_session()).await;
+    // Rooms keep their members by id, so every command but authenticating needs an authenticated user
+    let needs_auth = !matches!(command, Command::AuthUser(_) | Command::Help);
+
+    // Every arm tells if the command succeeded, for the metrics
+    let success = match command {
+        _ if needs_auth && current_user.get_id().is_none() => {
+            let msg = ServerMessage::failed_command(NOT_AUTHENTICATED);
+            msg.send(current_user.get_outbox()).await;
+            false
         }
+        Command::SetName(new_name) => match validate_name(new_name, MAX_NAME_LENGTH) {
+            Ok(new_name) => {
+                // Changing the name
+                current_user.set_user_name(new_name.to_string());
+
+                // Every room keeps a copy of the user, the members see the new name
+                for room in server_rooms.joined_rooms(current_user) {
+                    let mut room = room.lock().await;
+                    if room.contains_user(current_user) && room.add_user(current_user).is_ok() {
+                        send_roster(&room);
+                    }
+                }
+
+                // Send update message
+                let msg = ServerMessage::state_update(current_user, "New user name set");
+                msg.send(current_user.get_outbox()).await;
+                true
+            }
+            Err(reason) => {
+                let msg = ServerMessage::failed_command(&reason);
+                msg.send(current_user.get_outbox()).await;
+                false
+            }
+        },
         Command::JoinPublicRoom(given_room_name) => {
             // Handles joining a public room, and then sends the server message from the action
-            join_public_room(given_room_name.to_string(), current_user, server_rooms)
-                .await
-                .send(&mut current_user.get_session())
-                .await;
+            let reply =
+                join_public_room(given_room_name.to_string(), current_user, server_rooms).await;
+            let joined = matches!(reply, ServerMessage::JoinedRoom { .. });
+            reply.send(current_user.get_outbox()).await;
+            joined
         }
-        Command::LeaveRoom => {
-            //TODO: Leave room
-
-            // Send update message
-            let msg = ServerMessage::state_update(&current_user, "Left room");
-            msg.send(&mut current_user.get_session()).await;
+        Command::LeaveRoom(room_name) => {
+            let reply = leave_room(room_name.clone(), current_user, server_rooms).await;
+            let left = matches!(reply, ServerMessage::LeftRoom(_));
+            reply.send(current_user.get_outbox()).await;
+            left
         }
         Command::CreatePublicRoom(room_name) => {
-            // Created a room with the given name
-            if let Ok(mut rooms) = server_rooms.lock() {
-                // Creates a public room
-                let res = rooms.create_public_room(room_name.to_string(), 5, &current_user);
-                match res {
-                    Ok(_) => {
-                        // Send OK message back
-                        let msg = ServerMessage::created_room(room_name.to_string());
-                        msg.send(&mut current_user.get_session()).await;
-                    }
-                    Err(err) => {
-                        // Failed to create the public room
-                        let server_msg = ServerMessage::room_error_msg(err);
-                        server_msg.send(&mut current_user.get_session()).await;
+            match validate_name(room_name, config.get_room_name_max_length()) {
+                Ok(room_name) => {
+                    // Created a room with the given name
+                    match server_rooms.create_public_room(
+                        room_name.to_string(),
+                        config.get_room_capacity(),
+                        current_user,
+                    ) {
+                        Ok(_) => {
+                            // Let the other nodes of the cluster know about the room
+                            if let Some(owner_id) = current_user.get_id() {
+                                bus.publish(RoomEvent::RoomCreated {
+                                    room: room_name.to_string(),
+                                    owner_id: owner_id.to_string(),
+                                    capacity: config.get_room_capacity(),
+                                    own_capacity: false,
+                                });
+                            }
+
+                            // Send OK message back
+                            let msg = ServerMessage::created_room(room_name.to_string());
+                            msg.send(current_user.get_outbox()).await;
+                            send_room_list(server_rooms, sessions).await;
+                            true
+                        }
+                        Err(err) => {
+                            // Failed to create the public room
+                            let server_msg = ServerMessage::room_error_msg(err);
+                            server_msg.send(current_user.get_outbox()).await;
+                            false
+                        }
                     }
                 }
+                Err(reason) => {
+                    let msg = ServerMessage::failed_command(&reason);
+                    msg.send(current_user.get_outbox()).await;
+                    false
+                }
             }
         }
         Command::RoomInfo => {
-            if let Some(room_name) = current_user.get_room_name() {
+            if let Some(_room_name) = current_user.get_latest_room() {
                 // Find information about the current room
                 //TODO: make room info available as a command
             }
+            false
         }
         Command::AuthUser(user_id) => {
-            // Set the user id of the user with the given user
-            current_user.set_id(hash_str(&user_id));
+            let user_id = hash_str(user_id);
+            if sessions.is_banned(&user_id) {
+                // Banned users are let know why, and disconnected
+                let msg = ServerMessage::failed_command("You are banned from this server");
+                msg.send(current_user.get_outbox()).await;
+                current_user.get_outbox().close(Some(CloseReason {
+                    code: CloseCode::Policy,
+                    description: Some("Banned".to_string()),
+                }));
+                false
+            } else {
+                // Set the user id of the user with the given user, the id can only be set once
+                if current_user.get_id().is_none() {
+                    METRICS.users_authenticated.inc();
+                }
+                current_user.set_id(user_id);
+
+                // Send auth message back to user
+                let msg = ServerMessage::Authenticated;
+                msg.send(current_user.get_outbox()).await;
+
+                // Greet the user with the welcome message of the config
+                if let Some(welcome_message) = config.get_welcome_message() {
+                    let msg = ServerMessage::state_update(current_user, welcome_message);
+                    msg.send(current_user.get_outbox()).await;
+                }
+
+                // Rooms the user can join
+                room_list(server_rooms)
+                    .await
+                    .send(current_user.get_outbox())
+                    .await;
+                true
+            }
+        }
 
-            // Send auth message back to user
-            let msg = ServerMessage::Authenticated;
-            msg.send(&mut current_user.get_session()).await;
+        Command::RejoinRoom { room, after } => {
+            match rejoin_room(room.to_string(), *after, current_user, server_rooms).await {
+                Ok(history) => {
+                    current_user.get_outbox().push(history);
+                    true
+                }
+                Err(msg) => {
+                    msg.send(current_user.get_outbox()).await;
+                    false
+                }
+            }
+        }
+
+        Command::SetNickname { room, name } => {
+            let reply = set_nickname(
+                room.to_string(),
+                name.to_string(),
+                current_user,
+                server_rooms,
+            )
+            .await;
+            let set = matches!(reply, ServerMessage::StateUpdate { .. });
+            reply.send(current_user.get_outbox()).await;
+            set
         }
 
         // Help command should not be sent to the server
-        Command::Help => (),
-    }
+        Command::Help => false,
+    };
+
+    METRICS.command(command.name(), success);
 }
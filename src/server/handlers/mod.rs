@@ -1,2 +1,3 @@
+pub mod admin_handlers;
 pub mod command_handlers;
 pub mod ws_handlers;
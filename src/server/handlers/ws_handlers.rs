@@ -1,9 +1,7 @@
 use actix_ws::Session;
+use tracing::info;
 
-use crate::{
-    core::{room::room::Room, user::user::User},
-    utils::constants::INFO_LOG,
-};
+use crate::core::{room::room::Room, user::user::User};
 
 pub async fn handle_join(room_name: String, user: &mut User, room: &mut Room) {
     // Leave the current room if necessary
@@ -12,11 +10,10 @@ pub async fn handle_join(room_name: String, user: &mut User, room: &mut Room) {
     }
 
     // Log join message
-    println!(
-        "{} User {} is joining in room: {}",
-        *INFO_LOG,
-        &user.get_user_name(),
-        room_name
+    info!(
+        user = user.get_user_name(),
+        room = %room_name,
+        "User is joining a room"
     );
 
     // Join the new room
@@ -31,17 +28,15 @@ pub async fn handle_leave(user: &mut User, room: &mut Room) {
     // Leave the current room
     if room.contains_user(user) {
         room.remove_user(user);
-        user.get_session().text("Left the room").await.unwrap();
+        user.get_outbox().push_text("Left the room".to_string());
     } else {
-        user.get_session()
-            .text("You are not in any room")
-            .await
-            .unwrap();
+        user.get_outbox()
+            .push_text("You are not in any room".to_string());
     }
 }
 
 pub async fn handle_name(session: &mut Session, text: String, user: &mut User) {
-    let input: Vec<&str> = text.split_ascii_whitespace().into_iter().collect();
+    let input: Vec<&str> = text.split_ascii_whitespace().collect();
     if input.len() == 2 {
         let user_name = input[1];
         if user_name.is_empty() || user_name.len() < 3 {
@@ -52,7 +47,11 @@ pub async fn handle_name(session: &mut Session, text: String, user: &mut User) {
                 .unwrap();
         } else {
             // Set the user name
-            println!("Renamed user: {} to {}", user.get_user_name(), user_name);
+            info!(
+                user = user.get_user_name(),
+                new_name = user_name,
+                "Renamed user"
+            );
             user.set_user_name(user_name.to_owned());
         }
     } else {
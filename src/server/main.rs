@@ -1,123 +1,82 @@
-use actix_files::Files;
-use actix_web::{web, App, HttpRequest, HttpServer, Responder};
-use actix_ws::Message;
-use futures_util::StreamExt as _;
+use actix_web::web;
 use rustic_reach::{
-    core::{
-        messages::{ClientMessage, ServerMessage},
-        room::room::{ServerRooms, WebRoom},
-        user::user::User,
+    core::protocol::protocol_schema_string,
+    server::{
+        config::{load_server_config, ServerConfig},
+        reload::LiveConfig,
+        runtime::start_server,
+        shutdown::{trigger_on_signals, Shutdown},
+    },
+    utils::{
+        args::validate_server_args,
+        constants::{ERROR_LOG, INFO_LOG},
+        logging::init_logging,
     },
-    server::handlers::command_handlers::handle_client_command,
-    utils::constants::{ERROR_LOG, INFO_LOG, WARNING_LOG},
 };
-use std::sync::{Arc, Mutex};
-
-async fn ws(
-    req: HttpRequest,
-    body: web::Payload,
-    rooms: WebRoom,
-) -> actix_web::Result<impl Responder> {
-    let (response, mut session, mut msg_stream) = actix_ws::handle(&req, body)?;
-
-    // Create the new user
-    let mut current_user = User::new(session.clone());
-
-    // Create a new thread for handling the websocket session
-    actix_web::rt::spawn({
-        async move {
-            while let Some(Ok(msg)) = msg_stream.next().await {
-                match msg {
-                    Message::Text(text) => {
-                        let chat_msg: ClientMessage = match serde_json::from_str(&text) {
-                            Ok(chat) => chat,
-                            Err(_) => {
-                                println!("{} Ignored: {}", *WARNING_LOG, text);
-                                continue;
-                            }
-                        };
+use std::{fs, process::exit};
+use tracing::{info, warn};
 
-                        // Handle the message differently based on command or not
-                        match chat_msg {
-                            // Handle the command
-                            ClientMessage::Command(command) => {
-                                handle_client_command(&command, &mut current_user, &rooms).await;
-                            }
-
-                            // The message is not a command, but a chat message to the room
-                            ClientMessage::Chat(chat_message) => {
-                                // Log that a chat message has been received
-                                println!(
-                                    "{} {} wrote a message in {}",
-                                    *INFO_LOG, chat_message.sender, chat_message.room
-                                );
-
-                                // Acquire lock and broadcast the message for the room
-                                if let Ok(server_rooms) = rooms.lock() {
-                                    match server_rooms.get_room(&current_user) {
-                                        Some(r) => {
-                                            let chat_server_message =
-                                                ServerMessage::Chat(chat_message);
-                                            chat_server_message
-                                                .broadcast_msg(r, &current_user)
-                                                .await;
-                                        }
-                                        None => println!(
-                                            "{} User was not in a room, could not send message",
-                                            *ERROR_LOG
-                                        ),
-                                    };
-                                }
-                            }
-                        }
-                    }
-
-                    // Servers responds to ping messages
-                    Message::Ping(bytes) => {
-                        if session.pong(&bytes).await.is_err() {
-                            break;
-                        }
-                    }
+#[tokio::main]
+async fn main() -> std::io::Result<()> {
+    let server_args = validate_server_args().unwrap_or_else(|message| {
+        println!("{}", message);
+        exit(1);
+    });
 
-                    // Maybe here do user cleanup?
-                    Message::Close(_) => break,
+    // Write the protocol schema and exit, used for publishing the schema
+    if let Some(file_path) = server_args.dump_schema {
+        fs::write(&file_path, protocol_schema_string())?;
+        println!("{} Protocol schema written to {}", *INFO_LOG, file_path);
+        return Ok(());
+    }
 
-                    _ => {}
-                }
-            }
-            // TODO: Clean up when the user disconnects
-        }
+    // Parse the server config, or use the default config
+    let server_config = match &server_args.config {
+        Some(file_path) => load_server_config(file_path).unwrap_or_else(|message| {
+            println!(
+                "{} Provided server config {} could not be parsed: {}",
+                *ERROR_LOG, file_path, message
+            );
+            exit(1);
+        }),
+        None => ServerConfig::default(),
+    };
+    server_config.validate().unwrap_or_else(|message| {
+        println!("{} Invalid server config: {}", *ERROR_LOG, message);
+        exit(1);
     });
 
-    Ok(response)
-}
-
-#[tokio::main]
-async fn main() -> std::io::Result<()> {
-    // TODO: parse server config file
+    // Logging is configured by the server config, so nothing is logged before this
+    init_logging(server_config.get_logging()).unwrap_or_else(|message| {
+        println!("{} {}", *ERROR_LOG, message);
+        exit(1);
+    });
+    if server_args.config.is_none() {
+        warn!("No server config given, using the default config");
+    }
+    let scheme = match server_config.get_network().tls {
+        Some(_) => "https",
+        None => "http",
+    };
 
-    // Creating the rooms for the users
-    let rooms_mutex = Arc::new(Mutex::new(ServerRooms::with_max_room_count(3)));
+    // Ctrl-C and SIGTERM let the clients know before the server stops
+    let shutdown = web::Data::new(Shutdown::new());
+    tokio::spawn(trigger_on_signals(
+        shutdown.clone(),
+        server_config.get_shutdown().clone(),
+    ));
 
-    // Server IP and port
-    let server_ip = "127.0.0.1";
-    let server_port = 8080;
+    // Creating the HTTP server
+    // The config file is reloaded when it changes, or on SIGHUP
+    let server_config = LiveConfig::new(server_config, server_args.config.clone());
+    let (server, addresses) = start_server(server_config, shutdown)?;
 
     // Logging
-    println!(
-        "[INFO] Chat Server running on {}:{}",
-        server_ip, server_port
-    );
+    for address in addresses {
+        info!(url = %format!("{}://{}", scheme, address), "Chat server running");
+    }
 
-    // Creating and running the HTTP server
-    HttpServer::new(move || {
-        App::new()
-            .app_data(web::Data::new(rooms_mutex.clone())) // Serving websocket
-            .route("/ws", web::get().to(ws))
-            // Serving main page
-            .service(Files::new("/", "./src/frontend/").index_file("index.html"))
-    })
-    .bind((server_ip, server_port))?
-    .run()
-    .await
+    server.await?;
+    info!("Server stopped");
+    Ok(())
 }
@@ -0,0 +1,219 @@
+use std::time::Instant;
+
+use actix_web::{HttpResponse, Responder};
+use once_cell::sync::Lazy;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+
+use crate::core::{outbox::OutboxMetrics, room::room::WebRoom};
+
+/// Metrics of the server, served in the Prometheus text format at `/metrics`
+///
+/// Counters only go up, rates like messages per second are calculated by Prometheus with `rate()`.
+pub static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+pub struct Metrics {
+    registry: Registry,
+
+    /// Open websocket sessions
+    pub sessions_connected: IntGauge,
+
+    /// Open websocket sessions that have authenticated
+    pub users_authenticated: IntGauge,
+
+    /// Rooms on the server, updated when the metrics are scraped
+    pub rooms: IntGauge,
+
+    /// Members of all rooms on this server, updated when the metrics are scraped
+    ///
+    /// Not labeled by room, rooms are created by users so the amount of series would have no bound
+    pub room_members: IntGauge,
+
+    /// Chat messages received from clients, before any check
+    pub chat_received: IntCounter,
+
+    /// Chat messages broadcast to a room, by where the message came from
+    pub chat_broadcast: IntCounterVec,
+
+    /// Copies of chat messages queued for the members of rooms
+    pub chat_deliveries: IntCounter,
+
+    /// Commands handled, by command and outcome
+    pub commands: IntCounterVec,
+
+    /// Time from receiving a chat message until it is queued for every member of the room
+    pub broadcast_latency: HistogramVec,
+
+    /// Messages waiting in the outbound queues of all sessions
+    pub outbound_queued: IntGauge,
+
+    /// Messages dropped because an outbound queue was full
+    pub outbound_dropped: IntCounter,
+
+    /// Sessions disconnected because their outbound queue was full
+    pub outbound_disconnects: IntCounter,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let metrics = Self {
+            registry: Registry::new_custom(Some("rustic_reach".to_string()), None)
+                .expect("Metrics registry could not be created"),
+            sessions_connected: IntGauge::new("sessions_connected", "Open websocket sessions")
+                .unwrap(),
+            users_authenticated: IntGauge::new(
+                "users_authenticated",
+                "Open websocket sessions that have authenticated",
+            )
+            .unwrap(),
+            rooms: IntGauge::new("rooms", "Rooms on the server").unwrap(),
+            room_members: IntGauge::new("room_members", "Members of all rooms on this server")
+                .unwrap(),
+            chat_received: IntCounter::new(
+                "chat_messages_received_total",
+                "Chat messages received from clients",
+            )
+            .unwrap(),
+            chat_broadcast: IntCounterVec::new(
+                Opts::new(
+                    "chat_messages_broadcast_total",
+                    "Chat messages broadcast to a room",
+                ),
+                &["origin"],
+            )
+            .unwrap(),
+            chat_deliveries: IntCounter::new(
+                "chat_deliveries_total",
+                "Copies of chat messages queued for the members of rooms",
+            )
+            .unwrap(),
+            commands: IntCounterVec::new(
+                Opts::new("commands_total", "Commands handled"),
+                &["command", "outcome"],
+            )
+            .unwrap(),
+            broadcast_latency: HistogramVec::new(
+                HistogramOpts::new(
+                    "broadcast_latency_seconds",
+                    "Time from receiving a chat message until it is queued for every member of the room",
+                )
+                .buckets(vec![
+                    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25,
+                ]),
+                &["origin"],
+            )
+            .unwrap(),
+            outbound_queued: IntGauge::new(
+                "outbound_queued_messages",
+                "Messages waiting in the outbound queues of all sessions",
+            )
+            .unwrap(),
+            outbound_dropped: IntCounter::new(
+                "outbound_dropped_total",
+                "Messages dropped because an outbound queue was full",
+            )
+            .unwrap(),
+            outbound_disconnects: IntCounter::new(
+                "outbound_disconnects_total",
+                "Sessions disconnected because their outbound queue was full",
+            )
+            .unwrap(),
+        };
+
+        let collectors: Vec<Box<dyn prometheus::core::Collector>> = vec![
+            Box::new(metrics.sessions_connected.clone()),
+            Box::new(metrics.users_authenticated.clone()),
+            Box::new(metrics.rooms.clone()),
+            Box::new(metrics.room_members.clone()),
+            Box::new(metrics.chat_received.clone()),
+            Box::new(metrics.chat_broadcast.clone()),
+            Box::new(metrics.chat_deliveries.clone()),
+            Box::new(metrics.commands.clone()),
+            Box::new(metrics.broadcast_latency.clone()),
+            Box::new(metrics.outbound_queued.clone()),
+            Box::new(metrics.outbound_dropped.clone()),
+            Box::new(metrics.outbound_disconnects.clone()),
+        ];
+        for collector in collectors {
+            metrics
+                .registry
+                .register(collector)
+                .expect("Metric could not be registered");
+        }
+
+        metrics
+    }
+
+    /// Counts a handled command
+    pub fn command(&self, command: &str, success: bool) {
+        let outcome = if success { "ok" } else { "error" };
+        self.commands.with_label_values(&[command, outcome]).inc();
+    }
+
+    /// Counts a chat message broadcast to the given amount of members, received at the given time
+    pub fn broadcast(&self, origin: &str, recipients: usize, received_at: Instant) {
+        self.chat_broadcast.with_label_values(&[origin]).inc();
+        self.chat_deliveries.inc_by(recipients as u64);
+        self.broadcast_latency
+            .with_label_values(&[origin])
+            .observe(received_at.elapsed().as_secs_f64());
+    }
+
+    /// Encodes every metric in the Prometheus text format
+    pub fn encode(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("Metrics could not be encoded");
+        String::from_utf8(buffer).expect("Metrics were not valid UTF-8")
+    }
+}
+
+impl OutboxMetrics for Metrics {
+    fn queued(&self, change: i64) {
+        self.outbound_queued.add(change);
+    }
+
+    fn dropped(&self) {
+        self.outbound_dropped.inc();
+    }
+
+    fn disconnected(&self) {
+        self.outbound_disconnects.inc();
+    }
+}
+
+/// Serves the metrics of the server for Prometheus
+pub async fn metrics(rooms: WebRoom) -> impl Responder {
+    // The rooms are counted when scraped, so nothing has to be tracked while chatting
+    let server_rooms = rooms.rooms();
+    METRICS.rooms.set(server_rooms.len() as i64);
+    let mut members = 0;
+    for (_, room) in server_rooms {
+        members += room.lock().await.joined_user_count();
+    }
+    METRICS.room_members.set(members as i64);
+
+    HttpResponse::Ok()
+        .content_type(TextEncoder::new().format_type())
+        .body(METRICS.encode())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_metrics() {
+        METRICS.command("JoinPublicRoom", false);
+        METRICS.chat_received.inc();
+
+        let text = METRICS.encode();
+        assert!(text.contains("rustic_reach_sessions_connected"));
+        assert!(text
+            .contains(r#"rustic_reach_commands_total{command="JoinPublicRoom",outcome="error"}"#));
+        assert!(text.contains("rustic_reach_chat_messages_received_total"));
+    }
+}
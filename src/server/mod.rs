@@ -1,2 +1,12 @@
+pub mod cluster;
 pub mod config;
+pub mod frontend;
 pub mod handlers;
+pub mod metrics;
+pub mod rate_limit;
+pub mod reload;
+pub mod roster;
+pub mod runtime;
+pub mod sessions;
+pub mod shutdown;
+pub mod tls;
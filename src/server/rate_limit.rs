@@ -0,0 +1,310 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::token_bucket::{BucketConfig, TokenBucket};
+
+fn default_chat_limit() -> BucketConfig {
+    return BucketConfig {
+        capacity: 5.0,
+        refill_per_sec: 1.0,
+    };
+}
+
+fn default_command_limit() -> BucketConfig {
+    return BucketConfig {
+        capacity: 10.0,
+        refill_per_sec: 2.0,
+    };
+}
+
+fn default_room_chat_limit() -> BucketConfig {
+    return BucketConfig {
+        capacity: 30.0,
+        refill_per_sec: 10.0,
+    };
+}
+
+fn default_warnings_before_mute() -> u32 {
+    return 3;
+}
+
+fn default_mute_secs() -> u64 {
+    return 30;
+}
+
+fn default_mutes_before_disconnect() -> u32 {
+    return 2;
+}
+
+fn default_forgive_after_secs() -> u64 {
+    return 300;
+}
+
+/**
+ * Rate limit config defined in the server config file
+ *
+ * Every session has its own budget for chat messages and commands, and every room has a budget for chat messages
+ * shared by all of its members. Sessions going over their budget are warned, then muted, and at last disconnected.
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RateLimitConfig {
+    #[serde(default = "default_chat_limit")]
+    pub chat: BucketConfig,
+
+    #[serde(default = "default_command_limit")]
+    pub commands: BucketConfig,
+
+    #[serde(default = "default_room_chat_limit")]
+    pub room_chat: BucketConfig,
+
+    #[serde(default = "default_warnings_before_mute")]
+    pub warnings_before_mute: u32,
+
+    #[serde(default = "default_mute_secs")]
+    pub mute_secs: u64,
+
+    #[serde(default = "default_mutes_before_disconnect")]
+    pub mutes_before_disconnect: u32,
+
+    /// Warnings and mutes are forgotten when the session has behaved for this long
+    #[serde(default = "default_forgive_after_secs")]
+    pub forgive_after_secs: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            chat: default_chat_limit(),
+            commands: default_command_limit(),
+            room_chat: default_room_chat_limit(),
+            warnings_before_mute: default_warnings_before_mute(),
+            mute_secs: default_mute_secs(),
+            mutes_before_disconnect: default_mutes_before_disconnect(),
+            forgive_after_secs: default_forgive_after_secs(),
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Checks that every budget allows at least one message
+    pub fn validate(&self) -> Result<(), String> {
+        self.chat.validate("chat")?;
+        self.commands.validate("commands")?;
+        self.room_chat.validate("room_chat")
+    }
+}
+
+/// The kind of frame a session sent, each kind has its own budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Chat,
+    Command,
+}
+
+/// What should happen with a frame sent by a session
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    /// The frame is within the budget
+    Allow,
+
+    /// The frame is dropped, and the session is told why
+    Warn(String),
+
+    /// The frame is dropped without a reply, the session is muted
+    Drop,
+
+    /// The session has been flooding for too long and should be disconnected with the given reason
+    Disconnect(String),
+}
+
+/// Flood protection for a single session
+///
+/// Every frame over the budget is a violation. The first violations give a warning,
+/// then the session is muted for a while, and after too many mutes it is disconnected.
+#[derive(Debug, Clone)]
+pub struct FloodGuard {
+    chat: TokenBucket,
+    commands: TokenBucket,
+    warnings: u32,
+    mutes: u32,
+    muted_until: Option<Instant>,
+    last_violation: Option<Instant>,
+}
+
+impl FloodGuard {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            chat: TokenBucket::new(now),
+            commands: TokenBucket::new(now),
+            warnings: 0,
+            mutes: 0,
+            muted_until: None,
+            last_violation: None,
+        }
+    }
+
+    /// Checks a frame against the budget of the session
+    pub fn check(&mut self, kind: FrameKind, config: &RateLimitConfig, now: Instant) -> Verdict {
+        // Muted sessions are ignored until the mute is over
+        if let Some(muted_until) = self.muted_until {
+            if now < muted_until {
+                return Verdict::Drop;
+            }
+            self.muted_until = None;
+        }
+
+        // Forget old violations when the session has behaved for a while
+        if let Some(last_violation) = self.last_violation {
+            if now.saturating_duration_since(last_violation)
+                >= Duration::from_secs(config.forgive_after_secs)
+            {
+                self.warnings = 0;
+                self.mutes = 0;
+                self.last_violation = None;
+            }
+        }
+
+        let allowed = match kind {
+            FrameKind::Chat => self.chat.try_take(&config.chat, now),
+            FrameKind::Command => self.commands.try_take(&config.commands, now),
+        };
+        if allowed {
+            return Verdict::Allow;
+        }
+
+        // The frame is a violation
+        self.last_violation = Some(now);
+        self.warnings += 1;
+        if self.warnings <= config.warnings_before_mute {
+            return Verdict::Warn(format!(
+                "Slow down, you are sending messages too fast (warning {}/{})",
+                self.warnings, config.warnings_before_mute
+            ));
+        }
+
+        self.warnings = 0;
+        self.mutes += 1;
+        if self.mutes > config.mutes_before_disconnect {
+            return Verdict::Disconnect("Disconnected for flooding".to_string());
+        }
+
+        self.muted_until = Some(now + Duration::from_secs(config.mute_secs));
+        return Verdict::Warn(format!(
+            "You have been muted for {} seconds for flooding",
+            config.mute_secs
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> RateLimitConfig {
+        RateLimitConfig {
+            chat: BucketConfig {
+                capacity: 2.0,
+                refill_per_sec: 1.0,
+            },
+            warnings_before_mute: 2,
+            mute_secs: 10,
+            mutes_before_disconnect: 1,
+            ..RateLimitConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_warn_then_mute_then_disconnect() {
+        let config = config();
+        let now = Instant::now();
+        let mut guard = FloodGuard::new(now);
+
+        // Burst is allowed
+        assert_eq!(guard.check(FrameKind::Chat, &config, now), Verdict::Allow);
+        assert_eq!(guard.check(FrameKind::Chat, &config, now), Verdict::Allow);
+
+        // Two warnings, then a mute
+        assert!(matches!(
+            guard.check(FrameKind::Chat, &config, now),
+            Verdict::Warn(_)
+        ));
+        assert!(matches!(
+            guard.check(FrameKind::Chat, &config, now),
+            Verdict::Warn(_)
+        ));
+        assert!(
+            matches!(guard.check(FrameKind::Chat, &config, now), Verdict::Warn(msg) if msg.contains("muted"))
+        );
+
+        // Everything is dropped while muted, also commands
+        assert_eq!(guard.check(FrameKind::Chat, &config, now), Verdict::Drop);
+        assert_eq!(guard.check(FrameKind::Command, &config, now), Verdict::Drop);
+
+        // After the mute, the bucket has refilled
+        let after_mute = now + Duration::from_secs(11);
+        assert_eq!(
+            guard.check(FrameKind::Chat, &config, after_mute),
+            Verdict::Allow
+        );
+        assert_eq!(
+            guard.check(FrameKind::Chat, &config, after_mute),
+            Verdict::Allow
+        );
+
+        // Flooding again leads to a disconnect
+        assert!(matches!(
+            guard.check(FrameKind::Chat, &config, after_mute),
+            Verdict::Warn(_)
+        ));
+        assert!(matches!(
+            guard.check(FrameKind::Chat, &config, after_mute),
+            Verdict::Warn(_)
+        ));
+        assert!(matches!(
+            guard.check(FrameKind::Chat, &config, after_mute),
+            Verdict::Disconnect(_)
+        ));
+    }
+
+    #[test]
+    fn test_chat_and_commands_have_separate_budgets() {
+        let config = config();
+        let now = Instant::now();
+        let mut guard = FloodGuard::new(now);
+
+        assert_eq!(guard.check(FrameKind::Chat, &config, now), Verdict::Allow);
+        assert_eq!(guard.check(FrameKind::Chat, &config, now), Verdict::Allow);
+        assert!(matches!(
+            guard.check(FrameKind::Chat, &config, now),
+            Verdict::Warn(_)
+        ));
+
+        // Commands still have their own budget
+        assert_eq!(
+            guard.check(FrameKind::Command, &config, now),
+            Verdict::Allow
+        );
+    }
+
+    #[test]
+    fn test_violations_are_forgiven() {
+        let config = config();
+        let now = Instant::now();
+        let mut guard = FloodGuard::new(now);
+
+        assert_eq!(guard.check(FrameKind::Chat, &config, now), Verdict::Allow);
+        assert_eq!(guard.check(FrameKind::Chat, &config, now), Verdict::Allow);
+        assert!(
+            matches!(guard.check(FrameKind::Chat, &config, now), Verdict::Warn(msg) if msg.contains("1/2"))
+        );
+
+        // Much later, the count of warnings has started over
+        let later = now + Duration::from_secs(config.forgive_after_secs + 1);
+        assert_eq!(guard.check(FrameKind::Chat, &config, later), Verdict::Allow);
+        assert_eq!(guard.check(FrameKind::Chat, &config, later), Verdict::Allow);
+        assert!(
+            matches!(guard.check(FrameKind::Chat, &config, later), Verdict::Warn(msg) if msg.contains("1/2"))
+        );
+    }
+}
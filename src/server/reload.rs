@@ -0,0 +1,300 @@
+use std::{
+    fs,
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+
+use actix_web::web;
+use tracing::{error, info, warn};
+
+use crate::{
+    core::room::room::ServerRooms,
+    server::{
+        config::{load_server_config, ServerConfig},
+        sessions::Sessions,
+    },
+    utils::{constants::CONFIG_WATCH_INTERVAL, lock},
+};
+
+/**
+ * The config the server is running with, replaced when the config file is reloaded
+ *
+ * Handlers take the current config once per request or message, so every request sees one whole config.
+ * Settings that are only read at start, like the address or the logging, keep their values until a restart.
+ */
+#[derive(Debug)]
+pub struct LiveConfig {
+    path: Option<String>,
+    current: RwLock<Arc<ServerConfig>>,
+}
+
+impl From<ServerConfig> for LiveConfig {
+    /// Config that was not read from a file, so it can not be reloaded
+    fn from(config: ServerConfig) -> Self {
+        Self::new(config, None)
+    }
+}
+
+impl LiveConfig {
+    pub fn new(config: ServerConfig, path: Option<String>) -> Self {
+        Self {
+            path,
+            current: RwLock::new(Arc::new(config)),
+        }
+    }
+
+    /// Returns the current config
+    pub fn get(&self) -> Arc<ServerConfig> {
+        lock::read(&self.current).clone()
+    }
+
+    /// The file the config was read from
+    pub fn get_path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    /// Reads the config file again, and replaces the current config when the new one is valid
+    ///
+    /// Returns the settings that changed. On error the current config is kept.
+    pub fn reload(&self) -> Result<Vec<String>, String> {
+        let path = self
+            .path
+            .as_deref()
+            .ok_or("The server was started without a config file")?;
+        let mut config = load_server_config(path)?;
+        config.validate()?;
+
+        let mut current = lock::write(&self.current);
+        let ignored = config.keep_restart_settings(&current);
+        if !ignored.is_empty() {
+            warn!(
+                sections = ?ignored,
+                "Changes in these sections of the config need a restart"
+            );
+        }
+        let changed = config.changed_settings(&current);
+        *current = Arc::new(config);
+        Ok(changed)
+    }
+}
+
+/// Applies the settings of the config that live outside of it, in the rooms and the sessions
+///
+/// Users that are banned by the config are disconnected.
+pub async fn apply_config(config: &ServerConfig, server_rooms: &ServerRooms, sessions: &Sessions) {
+    server_rooms.set_max_room_count(config.get_max_room_count());
+    sessions.set_config_bans(config.get_banned_users());
+
+    for user in sessions.users() {
+        if user.get_id().is_some_and(|id| sessions.is_banned(id)) {
+            sessions
+                .disconnect(&user, server_rooms, "Banned".to_string())
+                .await;
+        }
+    }
+}
+
+/// Reloads the config file and applies it, logs what changed or why the config was kept
+pub async fn reload_config(
+    live_config: &LiveConfig,
+    server_rooms: &ServerRooms,
+    sessions: &Sessions,
+) -> Result<Vec<String>, String> {
+    match live_config.reload() {
+        Ok(changed) => {
+            let config = live_config.get();
+            apply_config(&config, server_rooms, sessions).await;
+
+            // Rooms get their capacity when they are created, rooms without their own capacity get the new one
+            if changed
+                .iter()
+                .any(|setting| setting == "room_config.room_capacity")
+            {
+                server_rooms
+                    .set_default_room_capacity(config.get_room_capacity())
+                    .await;
+            }
+            info!(changed = ?changed, "Server config reloaded");
+            Ok(changed)
+        }
+        Err(err) => {
+            error!(error = %err, "Server config could not be reloaded, keeping the current config");
+            Err(err)
+        }
+    }
+}
+
+fn modified_at(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// Reloads the config file when it changes, and on SIGHUP on unix
+pub async fn watch_config(
+    live_config: web::Data<LiveConfig>,
+    server_rooms: Arc<ServerRooms>,
+    sessions: web::Data<Sessions>,
+) {
+    let Some(path) = live_config.get_path().map(str::to_string) else {
+        return;
+    };
+
+    #[cfg(unix)]
+    let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(signal) => Some(signal),
+        Err(err) => {
+            warn!(error = %err, "SIGHUP handler could not be installed");
+            None
+        }
+    };
+
+    let mut last_modified = modified_at(&path);
+    let mut interval = tokio::time::interval(CONFIG_WATCH_INTERVAL);
+    loop {
+        #[cfg(unix)]
+        let hangup_received = async {
+            match hangup.as_mut() {
+                Some(signal) => signal.recv().await,
+                None => std::future::pending().await,
+            }
+        };
+        #[cfg(not(unix))]
+        let hangup_received = std::future::pending::<Option<()>>();
+
+        tokio::select! {
+            _ = interval.tick() => {
+                // Editors can replace the file, so the time is compared instead of watching the file itself
+                let modified = modified_at(&path);
+                if modified == last_modified {
+                    continue;
+                }
+                last_modified = modified;
+            }
+            _ = hangup_received => info!("SIGHUP received"),
+        }
+
+        // Errors are logged, the server keeps running with the current config
+        let _ = reload_config(&live_config, &server_rooms, &sessions).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    /// Path of a config file in a new temporary directory, which is removed when dropped
+    fn config_file() -> (TempDir, PathBuf) {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("config.yml");
+        (dir, path)
+    }
+
+    fn write_config(path: &Path, welcome_message: &str, port: u16) {
+        let yaml = format!(
+            r#"
+            server:
+              admin:
+                token: "secret"
+                password_hash: "hash"
+              general:
+                welcome_message: "{}"
+              room_config:
+                max_room_count: 2
+              network:
+                port: {}
+              banned_users: ["troll"]
+            "#,
+            welcome_message, port
+        );
+        fs::write(path, yaml).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reload_keeps_the_old_config_when_invalid() {
+        let (_dir, file) = config_file();
+        write_config(&file, "Hello", 8080);
+        let path = file.to_str().unwrap().to_string();
+        let live_config = LiveConfig::new(load_server_config(&path).unwrap(), Some(path.clone()));
+
+        // A broken file is not applied
+        fs::write(&path, "server: [").unwrap();
+        assert!(live_config.reload().is_err());
+        assert_eq!(live_config.get().get_welcome_message(), Some("Hello"));
+
+        // Neither is a file with values the server can not run with
+        write_config(&file, "Hi", 8080);
+        let invalid = fs::read_to_string(&path)
+            .unwrap()
+            .replace("max_room_count: 2", "max_room_count: 0");
+        fs::write(&path, invalid).unwrap();
+        assert!(live_config.reload().is_err());
+        assert_eq!(live_config.get().get_welcome_message(), Some("Hello"));
+
+        // The port can only change with a restart
+        write_config(&file, "Hi", 9090);
+        let changed = live_config.reload().unwrap();
+        assert_eq!(changed, vec!["general.welcome_message".to_string()]);
+        assert_eq!(live_config.get().get_welcome_message(), Some("Hi"));
+        assert_eq!(live_config.get().get_network().port, 8080);
+    }
+
+    #[tokio::test]
+    async fn test_reload_gives_rooms_the_new_capacity() {
+        let (_dir, file) = config_file();
+        write_config(&file, "Hello", 8080);
+        let path = file.to_str().unwrap().to_string();
+        let live_config = LiveConfig::new(load_server_config(&path).unwrap(), Some(path.clone()));
+
+        let server_rooms = ServerRooms::with_max_room_count(2);
+        server_rooms
+            .create_room_with_owner_id("lobby".to_string(), 20, false, "owner".to_string())
+            .unwrap();
+        server_rooms
+            .create_room_with_owner_id("vip".to_string(), 50, true, "owner".to_string())
+            .unwrap();
+
+        let config = fs::read_to_string(&path).unwrap().replace(
+            "max_room_count: 2",
+            "max_room_count: 2\n                room_capacity: 3",
+        );
+        fs::write(&path, config).unwrap();
+        let changed = reload_config(&live_config, &server_rooms, &Sessions::new())
+            .await
+            .unwrap();
+
+        assert_eq!(changed, vec!["room_config.room_capacity".to_string()]);
+        let room = server_rooms.get_room_with_name("lobby").unwrap();
+        assert_eq!(room.lock().await.capacity(), 3);
+
+        // The capacity given by the admin is kept
+        let room = server_rooms.get_room_with_name("vip").unwrap();
+        assert_eq!(room.lock().await.capacity(), 50);
+    }
+
+    #[tokio::test]
+    async fn test_apply_config() {
+        let (_dir, path) = config_file();
+        write_config(&path, "Hello", 8080);
+        let config = load_server_config(path.to_str().unwrap()).unwrap();
+
+        let server_rooms = ServerRooms::with_max_room_count(5);
+        let sessions = Sessions::new();
+        apply_config(&config, &server_rooms, &sessions).await;
+        assert!(sessions.is_banned("troll"));
+
+        // The new max applies to the rooms created from now on
+        let mut owner = crate::core::user::user::User::detached();
+        owner.set_id("owner".to_string());
+        for room in ["a", "b"] {
+            assert!(server_rooms
+                .create_public_room(room.to_string(), 5, &owner)
+                .is_ok());
+        }
+        assert!(server_rooms
+            .create_public_room("c".to_string(), 5, &owner)
+            .is_err());
+    }
+}
@@ -0,0 +1,42 @@
+use crate::{
+    core::{
+        messages::ServerMessage,
+        outbox::Outbox,
+        room::room::{Room, ServerRooms},
+    },
+    server::sessions::Sessions,
+};
+
+/// Lets every member of the room know who is in the room, after someone joined, left or changed name
+///
+/// Only the members connected to this node of the cluster are listed.
+pub fn send_roster(room: &Room) {
+    let roster = ServerMessage::Roster {
+        room: room.name(),
+        members: room.member_names(),
+    };
+    roster.broadcast_msg(&room.outboxes());
+}
+
+/// Rooms of the server that can be joined without a password
+pub async fn room_list(server_rooms: &ServerRooms) -> ServerMessage {
+    let mut rooms = Vec::new();
+    for (name, room) in server_rooms.rooms() {
+        if !room.lock().await.has_password() {
+            rooms.push(name);
+        }
+    }
+    rooms.sort();
+    ServerMessage::RoomList(rooms)
+}
+
+/// Lets every authenticated user know about the rooms, after a room was created or deleted
+pub async fn send_room_list(server_rooms: &ServerRooms, sessions: &Sessions) {
+    let outboxes: Vec<Outbox> = sessions
+        .users()
+        .iter()
+        .filter(|user| user.get_id().is_some())
+        .map(|user| user.get_outbox().clone())
+        .collect();
+    room_list(server_rooms).await.broadcast_msg(&outboxes);
+}
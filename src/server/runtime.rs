@@ -0,0 +1,1305 @@
+use std::{
+    io,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use actix_web::{dev::Server, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
+use actix_ws::{CloseCode, CloseReason, Message};
+use futures_util::StreamExt as _;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, info, warn};
+
+use crate::{
+    core::{
+        frame::Frame,
+        messages::{ChatMessage, ClientMessage, MessageError, ServerMessage},
+        outbox::{broadcast_frame, Outbox},
+        protocol::protocol_schema,
+        room::room::{ServerRooms, WebRoom},
+        user::user::User,
+    },
+    server::{
+        cluster::{local::LocalBus, tcp::TcpBus, ClusterBus, ClusterMessage, RoomEvent},
+        frontend::configure_frontend,
+        handlers::{
+            admin_handlers::{admin_scope, notify_room_deleted},
+            command_handlers::handle_client_command,
+        },
+        metrics::{metrics, METRICS},
+        rate_limit::{FloodGuard, FrameKind, Verdict},
+        reload::{watch_config, LiveConfig},
+        roster::{send_room_list, send_roster},
+        sessions::Sessions,
+        shutdown::{shut_down_when_triggered, Shutdown},
+        tls::load_rustls_config,
+    },
+    utils::{
+        constants::{LOGGED_FRAME_PREFIX, MAX_FRAME_OVERHEAD},
+        traits::SendServerReply,
+    },
+};
+
+async fn ws(
+    req: HttpRequest,
+    body: web::Payload,
+    rooms: WebRoom,
+    live_config: web::Data<LiveConfig>,
+    bus: web::Data<dyn ClusterBus>,
+    sessions: web::Data<Sessions>,
+    shutdown: web::Data<Shutdown>,
+) -> actix_web::Result<HttpResponse> {
+    // Connections that were open before the shutdown can still try to upgrade
+    if shutdown.is_triggered() {
+        return Ok(HttpResponse::ServiceUnavailable().body("Server is shutting down"));
+    }
+
+    let (response, mut session, msg_stream) = actix_ws::handle(&req, body)?;
+    let config = live_config.get();
+
+    // Frames can not be much larger than the longest chat message allowed
+    let mut msg_stream =
+        msg_stream.max_frame_size(config.get_max_message_length() * 4 + MAX_FRAME_OVERHEAD);
+
+    // Create the new user, messages to the user are queued and written by a separate task
+    let mut current_user = User::new(Outbox::spawn(
+        session.clone(),
+        config.get_outbound(),
+        &*METRICS,
+    ));
+
+    // The admin API can list the session, and kick it
+    sessions.update(&current_user);
+
+    // Flood protection of the session
+    let mut flood_guard = FloodGuard::new(Instant::now());
+
+    // Create a new thread for handling the websocket session
+    actix_web::rt::spawn({
+        async move {
+            METRICS.sessions_connected.inc();
+
+            while let Some(Ok(msg)) = msg_stream.next().await {
+                // The session was closed by the server, e.g. kicked by the admin
+                if current_user.get_outbox().is_closing() {
+                    break;
+                }
+                sessions.leave_deleted_rooms(&mut current_user);
+
+                match msg {
+                    Message::Text(text) => {
+                        let received_at = Instant::now();
+
+                        // The config can be reloaded, every message uses the config of the moment it arrived
+                        let config = live_config.get();
+                        let parsed = serde_json::from_str::<ClientMessage>(&text);
+
+                        // Check that the session is within its rate limits, frames that could not be parsed
+                        // use the budget of commands so that they can not be sent without limit
+                        let frame_kind = match parsed {
+                            Ok(ClientMessage::Chat(_)) => FrameKind::Chat,
+                            Ok(ClientMessage::Command(_)) | Err(_) => FrameKind::Command,
+                        };
+                        let rate_limit = config.get_rate_limit();
+                        match flood_guard.check(frame_kind, rate_limit, Instant::now()) {
+                            Verdict::Allow => (),
+                            Verdict::Warn(reason) => {
+                                ServerMessage::RateLimited(reason)
+                                    .send(current_user.get_outbox())
+                                    .await;
+                                continue;
+                            }
+                            Verdict::Drop => continue,
+                            Verdict::Disconnect(reason) => {
+                                warn!(
+                                    user = current_user.get_user_name(),
+                                    "Disconnected for flooding"
+                                );
+                                let close_reason = CloseReason {
+                                    code: CloseCode::Policy,
+                                    description: Some(reason),
+                                };
+                                current_user.get_outbox().close(Some(close_reason));
+                                break;
+                            }
+                        }
+
+                        let chat_msg = match parsed {
+                            Ok(chat) => chat,
+                            Err(_) => {
+                                let prefix: String =
+                                    text.chars().take(LOGGED_FRAME_PREFIX).collect();
+                                debug!(
+                                    frame = %prefix,
+                                    length = text.len(),
+                                    "Ignored a message that could not be parsed"
+                                );
+                                continue;
+                            }
+                        };
+
+                        // Handle the message differently based on command or not
+                        match chat_msg {
+                            // Handle the command
+                            ClientMessage::Command(command) => {
+                                handle_client_command(
+                                    &command,
+                                    &mut current_user,
+                                    &rooms,
+                                    &**bus,
+                                    &sessions,
+                                    &config,
+                                )
+                                .await;
+
+                                // Keep the copy of the admin API up to date, unless the session was closed
+                                if current_user.get_outbox().is_closing() {
+                                    break;
+                                }
+                                sessions.update(&current_user);
+                            }
+
+                            // The message is not a command, but a chat message to the room
+                            ClientMessage::Chat(chat_message) => {
+                                METRICS.chat_received.inc();
+
+                                // Reject messages that are too long or could mess with the terminal of others
+                                if let Err(err) =
+                                    chat_message.validate(config.get_max_message_length())
+                                {
+                                    ServerMessage::ChatRejected(err.message())
+                                        .send(current_user.get_outbox())
+                                        .await;
+                                    continue;
+                                }
+
+                                // Never trust the sender and room given by the client, build the message from the user
+                                let chat_message =
+                                    match ChatMessage::from_user(&current_user, chat_message) {
+                                        Ok(chat_message) => chat_message,
+                                        Err(err) => {
+                                            ServerMessage::ChatRejected(err.message())
+                                                .send(current_user.get_outbox())
+                                                .await;
+                                            continue;
+                                        }
+                                    };
+
+                                // Log that a chat message has been received
+                                debug!(
+                                    user = %chat_message.sender,
+                                    room = %chat_message.room,
+                                    "Chat message received"
+                                );
+
+                                match rooms.get_room_with_name(&chat_message.room) {
+                                    Some(room) => {
+                                        // The room stays locked while the message is sent, so every member
+                                        // gets the messages of the room in order. Other rooms are not blocked.
+                                        let mut room = room.lock().await;
+
+                                        // The room may have been deleted and created again since the user joined it
+                                        if !room.contains_user(&current_user) {
+                                            drop(room);
+                                            ServerMessage::ChatRejected(
+                                                MessageError::NotInRoom.message(),
+                                            )
+                                            .send(current_user.get_outbox())
+                                            .await;
+                                            continue;
+                                        }
+
+                                        // The budget of the room is shared by all of its members
+                                        if !room.try_take_chat(&config.get_rate_limit().room_chat) {
+                                            drop(room);
+                                            ServerMessage::RateLimited(
+                                                "The room is busy, try again in a moment"
+                                                    .to_string(),
+                                            )
+                                            .send(current_user.get_outbox())
+                                            .await;
+                                            continue;
+                                        }
+
+                                        // Let the other nodes of the cluster deliver the message to their members
+                                        bus.publish(RoomEvent::Chat(chat_message.clone()));
+
+                                        // Store the message in the room history, it is serialized once for everyone
+                                        let recorded = room.record_message(chat_message);
+                                        let outboxes = room.outboxes_except(&current_user);
+
+                                        // Let the author know the sequence number of the message
+                                        let ack = ServerMessage::ChatAck {
+                                            room: room.name(),
+                                            seq: recorded.seq(),
+                                        };
+                                        ack.send(current_user.get_outbox()).await;
+
+                                        broadcast_frame(&Frame::chat(&recorded), &outboxes);
+                                        METRICS.broadcast("local", outboxes.len(), received_at);
+                                    }
+                                    None => {
+                                        // The room may have been deleted since the user joined it
+                                        warn!(
+                                            user = current_user.get_user_name(),
+                                            "User was not in a room, could not send message"
+                                        );
+                                        ServerMessage::room_not_found()
+                                            .send(current_user.get_outbox())
+                                            .await;
+                                    }
+                                };
+                            }
+                        }
+                    }
+
+                    // Servers responds to ping messages
+                    Message::Ping(bytes) => {
+                        let pong = session.pong(&bytes).await;
+                        if pong.is_err() {
+                            break;
+                        }
+                    }
+
+                    // Maybe here do user cleanup?
+                    Message::Close(_) => break,
+
+                    _ => {}
+                }
+            }
+
+            // Remove the user from its rooms, unless it has already reconnected with a new session
+            for room in rooms.joined_rooms(&current_user) {
+                let mut room = room.lock().await;
+                room.remove_session(&current_user);
+                send_roster(&room);
+            }
+
+            // Stop the writer of the session
+            current_user.get_outbox().close(None);
+            sessions.remove(&current_user);
+
+            METRICS.sessions_connected.dec();
+            if current_user.get_id().is_some() {
+                METRICS.users_authenticated.dec();
+            }
+        }
+    });
+
+    Ok(response)
+}
+
+/// Applies the room events of the other nodes in the cluster to the rooms of this node
+async fn apply_cluster_events(
+    mut events: broadcast::Receiver<ClusterMessage>,
+    node_id: String,
+    server_rooms: Arc<ServerRooms>,
+    sessions: web::Data<Sessions>,
+) {
+    loop {
+        let message = match events.recv().await {
+            Ok(message) => message,
+            Err(RecvError::Lagged(missed)) => {
+                warn!(missed, "Missed cluster events");
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+        if message.origin == node_id {
+            continue;
+        }
+
+        match message.event {
+            RoomEvent::RoomCreated {
+                room,
+                owner_id,
+                capacity,
+                own_capacity,
+            } => {
+                match server_rooms.create_room_with_owner_id(room, capacity, own_capacity, owner_id)
+                {
+                    Ok(_) => send_room_list(&server_rooms, &sessions).await,
+                    Err(err) => warn!(
+                        error = %err.message(),
+                        "Room from the cluster could not be created"
+                    ),
+                }
+            }
+            RoomEvent::RoomDeleted { room: room_name } => {
+                if let Some(room) = server_rooms.delete_room(&room_name) {
+                    sessions.remove_room(&room_name);
+                    notify_room_deleted(room).await;
+                    send_room_list(&server_rooms, &sessions).await;
+                }
+            }
+            RoomEvent::Chat(chat_message) => {
+                let received_at = Instant::now();
+                match server_rooms.get_room_with_name(&chat_message.room) {
+                    Some(room) => {
+                        // Deliver the message to the members of the room on this node
+                        let mut room = room.lock().await;
+                        let recorded = room.record_message(chat_message);
+                        let outboxes = room.outboxes();
+                        broadcast_frame(&Frame::chat(&recorded), &outboxes);
+                        METRICS.broadcast("cluster", outboxes.len(), received_at);
+                    }
+                    None => warn!(
+                        room = %chat_message.room,
+                        "Chat message from the cluster for unknown room"
+                    ),
+                }
+            }
+        }
+    }
+}
+
+/// Serves the JSON Schema of the websocket protocol, for third party clients
+async fn schema() -> impl Responder {
+    HttpResponse::Ok().json(protocol_schema())
+}
+
+/// Creates the HTTP server and binds it to the address in the config
+///
+/// Serves over TLS when a certificate is given in the config.
+/// Returns the server, which has to be awaited to run, together with the addresses it is bound to.
+/// The server shuts down gracefully once the given shutdown is triggered.
+pub fn start_server(
+    server_config: LiveConfig,
+    shutdown: web::Data<Shutdown>,
+) -> io::Result<(Server, Vec<SocketAddr>)> {
+    // Join the cluster when configured, otherwise the server is a single node
+    let bus: Arc<dyn ClusterBus> = match server_config.get().get_cluster() {
+        Some(cluster) => {
+            let bus = TcpBus::start(cluster)?;
+            info!(
+                node_id = bus.node_id(),
+                address = %bus.local_addr(),
+                "Cluster node listening"
+            );
+            Arc::new(bus)
+        }
+        None => Arc::new(LocalBus::new()),
+    };
+
+    start_server_with_bus(server_config, bus, shutdown)
+}
+
+/// Creates the HTTP server as a node of the cluster connected by the given bus
+///
+/// Must be called from within a tokio runtime, the events of the other nodes are applied on a separate task
+pub fn start_server_with_bus(
+    server_config: LiveConfig,
+    bus: Arc<dyn ClusterBus>,
+    shutdown: web::Data<Shutdown>,
+) -> io::Result<(Server, Vec<SocketAddr>)> {
+    let config = server_config.get();
+    let network = config.get_network().clone();
+    let frontend = config.get_frontend().clone();
+    let shutdown_timeout = Duration::from_secs(config.get_shutdown().timeout_secs);
+    let server_config = web::Data::new(server_config);
+
+    // Creating the rooms for the users
+    let server_rooms = Arc::new(ServerRooms::with_max_room_count(
+        config.get_max_room_count(),
+    ));
+
+    // Sessions connected to this server, for the admin API
+    let sessions = web::Data::new(Sessions::new());
+    sessions.set_config_bans(config.get_banned_users());
+
+    // Keep the rooms in sync with the other nodes
+    tokio::spawn(apply_cluster_events(
+        bus.subscribe(),
+        bus.node_id().to_string(),
+        server_rooms.clone(),
+        sessions.clone(),
+    ));
+    let bus = web::Data::from(bus);
+
+    // Apply the changes to the config file while running
+    tokio::spawn(watch_config(
+        server_config.clone(),
+        server_rooms.clone(),
+        sessions.clone(),
+    ));
+
+    // Creating the HTTP server
+    let http_server = HttpServer::new({
+        let sessions = sessions.clone();
+        let shutdown = shutdown.clone();
+        move || {
+            App::new()
+                .app_data(web::Data::new(server_rooms.clone()))
+                .app_data(server_config.clone())
+                .app_data(bus.clone())
+                .app_data(sessions.clone())
+                .app_data(shutdown.clone())
+                // Serving websocket
+                .route("/ws", web::get().to(ws))
+                // Serving the protocol schema
+                .route("/protocol/schema.json", web::get().to(schema))
+                // Serving the metrics for Prometheus
+                .route("/metrics", web::get().to(metrics))
+                // Serving the admin API, protected by the admin token
+                .service(admin_scope())
+                // Serving the web frontend, built in or from the directory in the config
+                .configure(configure_frontend(&frontend))
+        }
+    })
+    // Signals are handled by the shutdown, which lets the clients know before stopping
+    .disable_signals();
+
+    let address = (network.host.as_str(), network.port);
+    let http_server = match &network.tls {
+        Some(tls) => http_server.bind_rustls_0_23(address, load_rustls_config(tls)?)?,
+        None => http_server.bind(address)?,
+    };
+
+    let addresses = http_server.addrs();
+    let server = http_server.run();
+    tokio::spawn(shut_down_when_triggered(
+        shutdown,
+        server.handle(),
+        sessions,
+        shutdown_timeout,
+    ));
+    Ok((server, addresses))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use actix_web::rt::time::timeout;
+    use awc::ws::{Frame, Message as WsMessage};
+    use futures_util::SinkExt as _;
+    use sha2::{Digest, Sha256};
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{
+        client::{state::ClientState, tls::client_tls_config},
+        core::messages::Command,
+        server::config::{parse_server_config_yml, TlsConfig},
+        utils::{constants::MAX_NAME_LENGTH, traits::JsonSerializing},
+    };
+
+    /// Writes a self signed certificate for localhost to a temporary directory, removed when it is dropped
+    fn write_self_signed_cert() -> (TempDir, TlsConfig, PathBuf, String) {
+        let cert = rcgen::generate_simple_self_signed(vec![
+            "localhost".to_string(),
+            "127.0.0.1".to_string(),
+        ])
+        .unwrap();
+
+        let dir = TempDir::new().unwrap();
+        let cert_file = dir.path().join("cert.pem");
+        let key_file = dir.path().join("key.pem");
+        fs::write(&cert_file, cert.cert.pem()).unwrap();
+        fs::write(&key_file, cert.key_pair.serialize_pem()).unwrap();
+
+        let fingerprint = hex::encode(Sha256::digest(cert.cert.der()));
+        let tls = TlsConfig {
+            cert_file: cert_file.to_string_lossy().to_string(),
+            key_file: key_file.to_string_lossy().to_string(),
+        };
+        (dir, tls, cert_file, fingerprint)
+    }
+
+    /// Starts a TLS server on a random port and returns its port
+    fn start_tls_server(tls: TlsConfig) -> u16 {
+        let yaml = format!(
+            r#"
+            server:
+              admin:
+                token: "admin"
+                password_hash: ""
+              general: {{}}
+              room_config: {{}}
+              network:
+                port: 0
+                tls:
+                  cert_file: "{}"
+                  key_file: "{}"
+            "#,
+            tls.cert_file, tls.key_file
+        );
+        let config = parse_server_config_yml(yaml).unwrap();
+
+        let (server, addresses) =
+            start_server(config.into(), web::Data::new(Shutdown::new())).unwrap();
+        actix_web::rt::spawn(server);
+        addresses[0].port()
+    }
+
+    /// Connects over wss and authenticates, returns true if the server answered
+    async fn authenticate(port: u16, tls_config: rustls::ClientConfig) -> bool {
+        let client = awc::Client::builder()
+            .connector(awc::Connector::new().rustls_0_23(Arc::new(tls_config)))
+            .finish();
+        let url = format!("wss://localhost:{}/ws", port);
+        let Ok((_, mut connection)) = client.ws(url).connect().await else {
+            return false;
+        };
+
+        let auth = ClientMessage::Command(Command::AuthUser("token".to_string()));
+        connection
+            .send(WsMessage::Text(auth.serialized().await.unwrap().into()))
+            .await
+            .unwrap();
+
+        while let Ok(Some(Ok(frame))) = timeout(Duration::from_secs(5), connection.next()).await {
+            if let Frame::Text(text) = frame {
+                if let Ok(ServerMessage::Authenticated) = serde_json::from_slice(&text) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    #[actix_web::test]
+    async fn test_wss_with_pinned_certificate() {
+        let (_dir, tls, _, fingerprint) = write_self_signed_cert();
+        let port = start_tls_server(tls);
+
+        let tls_config = client_tls_config(None, Some(&fingerprint)).unwrap();
+        assert!(authenticate(port, tls_config).await);
+
+        // A different certificate is rejected
+        let wrong_fingerprint = "00".repeat(32);
+        let tls_config = client_tls_config(None, Some(&wrong_fingerprint)).unwrap();
+        assert!(!authenticate(port, tls_config).await);
+    }
+
+    #[actix_web::test]
+    async fn test_wss_with_ca_file() {
+        let (_dir, tls, cert_file, _) = write_self_signed_cert();
+        let port = start_tls_server(tls);
+
+        let tls_config = client_tls_config(Some(cert_file.to_str().unwrap()), None).unwrap();
+        assert!(authenticate(port, tls_config).await);
+
+        // Self signed certificates are not trusted by the public CAs
+        let tls_config = client_tls_config(None, None).unwrap();
+        assert!(!authenticate(port, tls_config).await);
+    }
+
+    type WsConnection = actix_codec::Framed<awc::BoxedSocket, awc::ws::Codec>;
+
+    /// Starts a server on a random port as a node of the cluster connected by the bus, returns its port
+    fn start_cluster_node(bus: Arc<dyn ClusterBus>) -> u16 {
+        let yaml = r#"
+            server:
+              admin:
+                token: "admin"
+                password_hash: ""
+              general: {}
+              room_config: {}
+              network:
+                port: 0
+            "#;
+        let config = parse_server_config_yml(yaml.to_string()).unwrap();
+
+        let (server, addresses) =
+            start_server_with_bus(config.into(), bus, web::Data::new(Shutdown::new())).unwrap();
+        actix_web::rt::spawn(server);
+        addresses[0].port()
+    }
+
+    async fn send(connection: &mut WsConnection, message: ClientMessage) {
+        let text = message.serialized().await.unwrap();
+        connection.send(WsMessage::Text(text.into())).await.unwrap();
+    }
+
+    /// Waits for a message from the server that matches, other messages are skipped
+    async fn expect_message(
+        connection: &mut WsConnection,
+        matches: impl Fn(&ServerMessage) -> bool,
+    ) -> Option<ServerMessage> {
+        while let Ok(Some(Ok(frame))) = timeout(Duration::from_secs(5), connection.next()).await {
+            if let Frame::Text(text) = frame {
+                if let Ok(message) = serde_json::from_slice::<ServerMessage>(&text) {
+                    if matches(&message) {
+                        return Some(message);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// Connects to the server and authenticates with the token
+    async fn connect_user(port: u16, token: &str) -> WsConnection {
+        let url = format!("ws://127.0.0.1:{}/ws", port);
+        let (_, mut connection) = awc::Client::new().ws(url).connect().await.unwrap();
+        send(
+            &mut connection,
+            ClientMessage::Command(Command::AuthUser(token.to_string())),
+        )
+        .await;
+        expect_message(&mut connection, |message| {
+            matches!(message, ServerMessage::Authenticated)
+        })
+        .await
+        .unwrap();
+        connection
+    }
+
+    fn chat(room: &str, content: &str) -> ClientMessage {
+        let state = ClientState::new(String::new(), "user".to_string(), Some(room.to_string()));
+        ClientMessage::Chat(ChatMessage::create(&state, content.to_string()).unwrap())
+    }
+
+    /// Waits for a chat message with the given content
+    async fn expect_chat(connection: &mut WsConnection, content: &str) {
+        let message = expect_message(connection, |message| {
+            matches!(message, ServerMessage::Chat(chat)
+                if serde_json::to_value(chat).unwrap()["content"] == content)
+        })
+        .await;
+        assert!(
+            message.is_some(),
+            "Chat message '{}' was not received",
+            content
+        );
+    }
+
+    /// Two users in the same room, connected to different nodes, chat with each other
+    async fn chat_across_nodes(bus_a: Arc<dyn ClusterBus>, bus_b: Arc<dyn ClusterBus>) {
+        let port_a = start_cluster_node(bus_a);
+        let port_b = start_cluster_node(bus_b);
+        let mut alice = connect_user(port_a, "alice").await;
+        let mut bob = connect_user(port_b, "bob").await;
+
+        let room = "lobby".to_string();
+        send(
+            &mut alice,
+            ClientMessage::Command(Command::CreatePublicRoom(room.clone())),
+        )
+        .await;
+        send(
+            &mut alice,
+            ClientMessage::Command(Command::JoinPublicRoom(room.clone())),
+        )
+        .await;
+        expect_message(&mut alice, |message| {
+            matches!(message, ServerMessage::JoinedRoom { .. })
+        })
+        .await
+        .unwrap();
+
+        // The room exists on the other node once the event has arrived
+        let mut joined = false;
+        for _ in 0..20 {
+            send(
+                &mut bob,
+                ClientMessage::Command(Command::JoinPublicRoom(room.clone())),
+            )
+            .await;
+            let reply = expect_message(&mut bob, |message| {
+                matches!(
+                    message,
+                    ServerMessage::JoinedRoom { .. }
+                        | ServerMessage::RoomActionError(_)
+                        | ServerMessage::RateLimited(_)
+                )
+            })
+            .await;
+            if let Some(ServerMessage::JoinedRoom { .. }) = reply {
+                joined = true;
+                break;
+            }
+            actix_web::rt::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert!(joined, "Room was not created on the other node");
+
+        send(&mut alice, chat(&room, "Hello from node a")).await;
+        expect_chat(&mut bob, "Hello from node a").await;
+
+        send(&mut bob, chat(&room, "Hello from node b")).await;
+        expect_chat(&mut alice, "Hello from node b").await;
+
+        // The metrics of node a count the room and its member
+        let url = format!("http://127.0.0.1:{}/metrics", port_a);
+        let mut response = awc::Client::new().get(url).send().await.unwrap();
+        assert!(response.status().is_success());
+        let body = response.body().await.unwrap();
+        let metrics = String::from_utf8_lossy(&body);
+        assert!(metrics.contains("rustic_reach_room_members 1"));
+        assert!(metrics
+            .contains(r#"rustic_reach_commands_total{command="CreatePublicRoom",outcome="ok"}"#));
+        assert!(metrics.contains("rustic_reach_broadcast_latency_seconds_bucket"));
+    }
+
+    /// Calls the admin API of the server with the admin token
+    async fn admin_request(
+        port: u16,
+        method: awc::http::Method,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> (awc::http::StatusCode, serde_json::Value) {
+        let url = format!("http://127.0.0.1:{}/admin{}", port, path);
+        let request = awc::Client::new().request(method, url).bearer_auth("admin");
+        let mut response = match body {
+            Some(body) => request.send_json(&body).await.unwrap(),
+            None => request.send().await.unwrap(),
+        };
+        let json = response.json().await.unwrap_or(serde_json::Value::Null);
+        (response.status(), json)
+    }
+
+    /// Waits until the server closes the connection, returns the reason
+    async fn expect_close(connection: &mut WsConnection) -> Option<CloseReason> {
+        while let Ok(Some(Ok(frame))) = timeout(Duration::from_secs(5), connection.next()).await {
+            if let Frame::Close(reason) = frame {
+                return reason;
+            }
+        }
+        None
+    }
+
+    /// Waits until the server closes the connection, returns the description of the reason
+    async fn expect_close_description(connection: &mut WsConnection) -> Option<String> {
+        expect_close(connection)
+            .await
+            .and_then(|reason| reason.description)
+    }
+
+    #[actix_web::test]
+    async fn test_admin_api() {
+        use awc::http::{Method, StatusCode};
+
+        let port = start_cluster_node(Arc::new(LocalBus::new()));
+        let mut alice = connect_user(port, "alice").await;
+
+        // Requests without the token are rejected
+        let url = format!("http://127.0.0.1:{}/admin/users", port);
+        let response = awc::Client::new().get(url).send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let (status, users) = admin_request(port, Method::GET, "/users", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(users.as_array().unwrap().len(), 1);
+        assert_eq!(users[0]["authenticated"], true);
+        let session_id = users[0]["session_id"].as_str().unwrap().to_string();
+
+        let body = serde_json::json!({ "message": "Maintenance at noon" });
+        let (status, _) = admin_request(port, Method::POST, "/announcements", Some(body)).await;
+        assert_eq!(status, StatusCode::OK);
+        let announcement = expect_message(&mut alice, |message| {
+            matches!(message, ServerMessage::Announcement(_))
+        })
+        .await;
+        assert!(
+            matches!(announcement, Some(ServerMessage::Announcement(text)) if text == "Maintenance at noon")
+        );
+
+        // A banned user is disconnected, and can not authenticate again
+        let body = serde_json::json!({ "reason": "Spam" });
+        let path = format!("/users/{}/ban", session_id);
+        let (status, result) = admin_request(port, Method::POST, &path, Some(body)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(result["disconnected"], 1);
+        assert_eq!(
+            expect_close_description(&mut alice).await.as_deref(),
+            Some("Spam")
+        );
+
+        let url = format!("ws://127.0.0.1:{}/ws", port);
+        let (_, mut alice) = awc::Client::new().ws(url).connect().await.unwrap();
+        send(
+            &mut alice,
+            ClientMessage::Command(Command::AuthUser("alice".to_string())),
+        )
+        .await;
+        let reply = expect_message(&mut alice, |message| {
+            matches!(
+                message,
+                ServerMessage::Authenticated | ServerMessage::CommandResult { .. }
+            )
+        })
+        .await;
+        assert!(matches!(
+            reply,
+            Some(ServerMessage::CommandResult { success: false, .. })
+        ));
+        assert_eq!(
+            expect_close_description(&mut alice).await.as_deref(),
+            Some("Banned")
+        );
+
+        // Other users can still connect, and be kicked
+        let mut bob = connect_user(port, "bob").await;
+        let (_, users) = admin_request(port, Method::GET, "/users", None).await;
+        let bob_session = users
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|user| user["authenticated"] == true)
+            .unwrap()["session_id"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let path = format!("/users/{}/kick", bob_session);
+        let (status, _) = admin_request(port, Method::POST, &path, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            expect_close_description(&mut bob).await.as_deref(),
+            Some("Kicked by the admin")
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_former_members_can_not_write_in_a_recreated_room() {
+        use awc::http::{Method, StatusCode};
+
+        let port = start_cluster_node(Arc::new(LocalBus::new()));
+        let mut alice = connect_user(port, "alice").await;
+        let mut bob = connect_user(port, "bob").await;
+        let joined = |message: &ServerMessage| matches!(message, ServerMessage::JoinedRoom { .. });
+        for command in [
+            Command::CreatePublicRoom("lobby".to_string()),
+            Command::JoinPublicRoom("lobby".to_string()),
+        ] {
+            send(&mut alice, ClientMessage::Command(command)).await;
+        }
+        assert!(expect_message(&mut alice, joined).await.is_some());
+
+        let (status, _) = admin_request(port, Method::DELETE, "/rooms/lobby", None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        for command in [
+            Command::CreatePublicRoom("lobby".to_string()),
+            Command::JoinPublicRoom("lobby".to_string()),
+        ] {
+            send(&mut bob, ClientMessage::Command(command)).await;
+        }
+        assert!(expect_message(&mut bob, joined).await.is_some());
+
+        // Alice was in the deleted room, not in the new one
+        send(&mut alice, chat("lobby", "Still here")).await;
+        let rejected = expect_message(&mut alice, |message| {
+            matches!(message, ServerMessage::ChatRejected(_))
+        })
+        .await;
+        assert!(rejected.is_some());
+
+        // Once alice has joined the new room the messages reach bob, the rejected one never did
+        send(
+            &mut alice,
+            ClientMessage::Command(Command::JoinPublicRoom("lobby".to_string())),
+        )
+        .await;
+        assert!(expect_message(&mut alice, joined).await.is_some());
+        send(&mut alice, chat("lobby", "Hello again")).await;
+        let received = expect_message(&mut bob, |message| {
+            matches!(message, ServerMessage::Chat(_))
+        })
+        .await;
+        assert!(matches!(
+            received,
+            Some(ServerMessage::Chat(chat)) if chat.get_content() == "Hello again"
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_graceful_shutdown() {
+        use awc::http::{Method, StatusCode};
+
+        let yaml = r#"
+            server:
+              admin:
+                token: "admin"
+                password_hash: ""
+              general: {}
+              room_config: {}
+              network:
+                port: 0
+              shutdown:
+                timeout_secs: 2
+            "#;
+        let config = parse_server_config_yml(yaml.to_string()).unwrap();
+        let (server, addresses) = start_server_with_bus(
+            config.into(),
+            Arc::new(LocalBus::new()),
+            web::Data::new(Shutdown::new()),
+        )
+        .unwrap();
+        let stopped = actix_web::rt::spawn(server);
+        let port = addresses[0].port();
+        let mut alice = connect_user(port, "alice").await;
+
+        let body = serde_json::json!({ "reason": "Update", "reconnect_after": 5 });
+        let (status, _) = admin_request(port, Method::POST, "/shutdown", Some(body)).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+
+        // The users are let know before the websocket is closed
+        let notice = expect_message(&mut alice, |message| {
+            matches!(message, ServerMessage::ServerShutdown { .. })
+        })
+        .await;
+        assert!(matches!(
+            notice,
+            Some(ServerMessage::ServerShutdown { reason, reconnect_after: Some(5) }) if reason == "Update"
+        ));
+        let reason = expect_close(&mut alice).await.unwrap();
+        assert_eq!(reason.code, CloseCode::Restart);
+
+        // Once the client has closed its side, the server stops without waiting for the timeout
+        alice.send(WsMessage::Close(None)).await.unwrap();
+        let result = timeout(Duration::from_secs(1), stopped).await;
+        assert!(matches!(result, Ok(Ok(Ok(())))), "Server did not stop");
+    }
+
+    #[actix_web::test]
+    async fn test_malformed_frames_are_rate_limited() {
+        let port = start_cluster_node(Arc::new(LocalBus::new()));
+        let mut alice = connect_user(port, "alice").await;
+
+        // The default budget of commands allows a burst of 10
+        for _ in 0..11 {
+            alice
+                .send(WsMessage::Text("not a message".into()))
+                .await
+                .unwrap();
+        }
+        let limited = expect_message(&mut alice, |message| {
+            matches!(message, ServerMessage::RateLimited(_))
+        })
+        .await;
+        assert!(limited.is_some(), "Malformed frames were not rate limited");
+    }
+
+    #[actix_web::test]
+    async fn test_commands_before_auth() {
+        let port = start_cluster_node(Arc::new(LocalBus::new()));
+        let url = format!("ws://127.0.0.1:{}/ws", port);
+        let (_, mut connection) = awc::Client::new().ws(url).connect().await.unwrap();
+
+        let failed = |message: &ServerMessage| {
+            matches!(message, ServerMessage::CommandResult { success: false, .. })
+        };
+        for command in [
+            Command::CreatePublicRoom("lobby".to_string()),
+            Command::JoinPublicRoom("lobby".to_string()),
+            Command::RejoinRoom {
+                room: "lobby".to_string(),
+                after: 0,
+            },
+            Command::SetName("bob".to_string()),
+        ] {
+            send(&mut connection, ClientMessage::Command(command)).await;
+            assert!(expect_message(&mut connection, failed).await.is_some());
+        }
+
+        // The session is still handled, and can create the room once authenticated
+        for command in [
+            Command::AuthUser("bob".to_string()),
+            Command::CreatePublicRoom("lobby".to_string()),
+        ] {
+            send(&mut connection, ClientMessage::Command(command)).await;
+        }
+        let created = expect_message(&mut connection, |message| {
+            matches!(message, ServerMessage::CreatedRoom(_))
+        })
+        .await;
+        assert!(created.is_some(), "Session stopped after the commands");
+    }
+
+    #[actix_web::test]
+    async fn test_invalid_names() {
+        let port = start_cluster_node(Arc::new(LocalBus::new()));
+        let mut alice = connect_user(port, "alice").await;
+        let spoof = "lobby\r\n[SERVER] Send your token".to_string();
+        send(
+            &mut alice,
+            ClientMessage::Command(Command::SetName("alice".to_string())),
+        )
+        .await;
+
+        let failed = |message: &ServerMessage| {
+            matches!(message, ServerMessage::CommandResult { success: false, .. })
+        };
+        for command in [
+            Command::CreatePublicRoom(spoof.clone()),
+            Command::SetName(spoof.clone()),
+        ] {
+            send(&mut alice, ClientMessage::Command(command)).await;
+            assert!(expect_message(&mut alice, failed).await.is_some());
+        }
+
+        send(
+            &mut alice,
+            ClientMessage::Command(Command::CreatePublicRoom("lobby".to_string())),
+        )
+        .await;
+        send(
+            &mut alice,
+            ClientMessage::Command(Command::JoinPublicRoom("lobby".to_string())),
+        )
+        .await;
+        send(
+            &mut alice,
+            ClientMessage::Command(Command::SetNickname {
+                room: "lobby".to_string(),
+                name: "\u{1b}[2Jadmin".to_string(),
+            }),
+        )
+        .await;
+        assert!(expect_message(&mut alice, failed).await.is_some());
+
+        // Names can not be empty or too long, sent by bob so that alice stays within the budget of commands
+        let mut bob = connect_user(port, "bob").await;
+        for command in [
+            Command::CreatePublicRoom(" ".to_string()),
+            Command::CreatePublicRoom("a".repeat(100)),
+            Command::SetName(String::new()),
+            Command::SetName("a".repeat(MAX_NAME_LENGTH + 1)),
+        ] {
+            send(&mut bob, ClientMessage::Command(command)).await;
+            assert!(expect_message(&mut bob, failed).await.is_some());
+        }
+
+        // The rejected names were not used, and names are trimmed
+        for command in [
+            Command::SetName("  bob ".to_string()),
+            Command::JoinPublicRoom("lobby".to_string()),
+        ] {
+            send(&mut bob, ClientMessage::Command(command)).await;
+        }
+        let roster = expect_message(&mut alice, |message| {
+            matches!(message, ServerMessage::Roster { members, .. } if members.len() == 2)
+        })
+        .await;
+        assert!(matches!(
+            roster,
+            Some(ServerMessage::Roster { room, members }) if room == "lobby" && members == ["alice", "bob"]
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_roster_and_room_list() {
+        let port = start_cluster_node(Arc::new(LocalBus::new()));
+        let mut alice = connect_user(port, "alice").await;
+        let mut bob = connect_user(port, "bob").await;
+
+        // Every user gets the new list of rooms
+        send(
+            &mut alice,
+            ClientMessage::Command(Command::CreatePublicRoom("lobby".to_string())),
+        )
+        .await;
+        let rooms = expect_message(&mut bob, |message| {
+            matches!(message, ServerMessage::RoomList(rooms) if rooms.contains(&"lobby".to_string()))
+        })
+        .await;
+        assert!(rooms.is_some(), "Room list was not sent");
+
+        // The members of the room get the roster when someone joins or leaves
+        for (connection, name) in [(&mut alice, "alice"), (&mut bob, "bob")] {
+            send(
+                connection,
+                ClientMessage::Command(Command::SetName(name.to_string())),
+            )
+            .await;
+            send(
+                connection,
+                ClientMessage::Command(Command::JoinPublicRoom("lobby".to_string())),
+            )
+            .await;
+        }
+        let roster = expect_message(&mut alice, |message| {
+            matches!(message, ServerMessage::Roster { members, .. } if members.len() == 2)
+        })
+        .await;
+        assert!(matches!(
+            roster,
+            Some(ServerMessage::Roster { room, members }) if room == "lobby" && members == ["alice", "bob"]
+        ));
+
+        bob.close().await.unwrap();
+        let roster = expect_message(&mut alice, |message| {
+            matches!(message, ServerMessage::Roster { members, .. } if members.len() == 1)
+        })
+        .await;
+        assert!(matches!(
+            roster,
+            Some(ServerMessage::Roster { members, .. }) if members == ["alice"]
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_several_rooms() {
+        let port = start_cluster_node(Arc::new(LocalBus::new()));
+        let mut alice = connect_user(port, "alice").await;
+        let mut bob = connect_user(port, "bob").await;
+
+        for room in ["lobby", "dev"] {
+            send(
+                &mut alice,
+                ClientMessage::Command(Command::CreatePublicRoom(room.to_string())),
+            )
+            .await;
+            send(
+                &mut alice,
+                ClientMessage::Command(Command::JoinPublicRoom(room.to_string())),
+            )
+            .await;
+            expect_message(&mut alice, |message| {
+                matches!(message, ServerMessage::JoinedRoom { room: joined, .. } if joined == room)
+            })
+            .await
+            .unwrap();
+        }
+        send(
+            &mut bob,
+            ClientMessage::Command(Command::JoinPublicRoom("dev".to_string())),
+        )
+        .await;
+        expect_message(&mut bob, |message| {
+            matches!(message, ServerMessage::JoinedRoom { .. })
+        })
+        .await
+        .unwrap();
+
+        // Every chat message goes to the room it was written for
+        send(&mut alice, chat("lobby", "Hello lobby")).await;
+        send(&mut alice, chat("dev", "Hello dev")).await;
+        expect_chat(&mut bob, "Hello dev").await;
+        send(&mut bob, chat("dev", "Hi alice")).await;
+        expect_chat(&mut alice, "Hi alice").await;
+
+        // Leaving a room keeps the other rooms
+        send(
+            &mut alice,
+            ClientMessage::Command(Command::LeaveRoom(Some("lobby".to_string()))),
+        )
+        .await;
+        let left = expect_message(&mut alice, |message| {
+            matches!(message, ServerMessage::LeftRoom(_))
+        })
+        .await;
+        assert!(matches!(left, Some(ServerMessage::LeftRoom(room)) if room == "lobby"));
+
+        send(&mut alice, chat("lobby", "Still here?")).await;
+        let rejected = expect_message(&mut alice, |message| {
+            matches!(message, ServerMessage::ChatRejected(_))
+        })
+        .await;
+        assert!(
+            matches!(rejected, Some(ServerMessage::ChatRejected(reason)) if reason.contains("lobby"))
+        );
+        send(&mut alice, chat("dev", "Still in dev")).await;
+        expect_chat(&mut bob, "Still in dev").await;
+    }
+
+    #[actix_web::test]
+    async fn test_nickname_per_room() {
+        let port = start_cluster_node(Arc::new(LocalBus::new()));
+        let mut alice = connect_user(port, "alice").await;
+        let mut bob = connect_user(port, "bob").await;
+        send(
+            &mut alice,
+            ClientMessage::Command(Command::SetName("zebra123".to_string())),
+        )
+        .await;
+
+        for room in ["lobby", "friends"] {
+            send(
+                &mut alice,
+                ClientMessage::Command(Command::CreatePublicRoom(room.to_string())),
+            )
+            .await;
+            for connection in [&mut alice, &mut bob] {
+                send(
+                    connection,
+                    ClientMessage::Command(Command::JoinPublicRoom(room.to_string())),
+                )
+                .await;
+                expect_message(connection, |message| {
+                    matches!(message, ServerMessage::JoinedRoom { room: joined, .. } if joined == room)
+                })
+                .await
+                .unwrap();
+            }
+        }
+
+        // The nickname is only used in its room
+        send(
+            &mut alice,
+            ClientMessage::Command(Command::SetNickname {
+                room: "friends".to_string(),
+                name: "elephant321".to_string(),
+            }),
+        )
+        .await;
+        let roster = expect_message(&mut bob, |message| {
+            matches!(message, ServerMessage::Roster { members, .. } if members.contains(&"elephant321".to_string()))
+        })
+        .await;
+        assert!(matches!(roster, Some(ServerMessage::Roster { room, .. }) if room == "friends"));
+
+        let sender_of = |message: Option<ServerMessage>| match message {
+            Some(ServerMessage::Chat(chat)) => chat.sender,
+            _ => panic!("Chat message was not received"),
+        };
+        send(&mut alice, chat("friends", "Hi friends")).await;
+        let message = expect_message(&mut bob, |message| {
+            matches!(message, ServerMessage::Chat(_))
+        })
+        .await;
+        assert_eq!(sender_of(message), "elephant321");
+        send(&mut alice, chat("lobby", "Hi lobby")).await;
+        let message = expect_message(&mut bob, |message| {
+            matches!(message, ServerMessage::Chat(_))
+        })
+        .await;
+        assert_eq!(sender_of(message), "zebra123");
+
+        // A nickname can only be set in a joined room
+        send(
+            &mut bob,
+            ClientMessage::Command(Command::SetNickname {
+                room: "work".to_string(),
+                name: "anon".to_string(),
+            }),
+        )
+        .await;
+        let error = expect_message(&mut bob, |message| {
+            matches!(message, ServerMessage::RoomActionError(_))
+        })
+        .await;
+        assert!(error.is_some(), "Nickname was set outside of a joined room");
+    }
+
+    #[actix_web::test]
+    async fn test_cluster_with_local_bus() {
+        let bus_a = LocalBus::new();
+        let bus_b = bus_a.join();
+        chat_across_nodes(Arc::new(bus_a), Arc::new(bus_b)).await;
+    }
+
+    #[actix_web::test]
+    async fn test_cluster_with_tcp_bus() {
+        let cluster_config = |peers: Vec<String>| crate::server::cluster::ClusterConfig {
+            node_id: None,
+            listen: "127.0.0.1:0".to_string(),
+            peers,
+            secret: "cluster-secret".to_string(),
+        };
+        let bus_a = TcpBus::start(&cluster_config(Vec::new())).unwrap();
+        let bus_b = TcpBus::start(&cluster_config(vec![bus_a.local_addr().to_string()])).unwrap();
+
+        // Wait for the nodes to connect, events published before are lost
+        for _ in 0..100 {
+            if bus_a.connected_peers() == 1 && bus_b.connected_peers() == 1 {
+                break;
+            }
+            actix_web::rt::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(bus_b.connected_peers(), 1);
+
+        chat_across_nodes(Arc::new(bus_a), Arc::new(bus_b)).await;
+    }
+}
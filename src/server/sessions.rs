@@ -0,0 +1,222 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::RwLock,
+};
+
+use actix_ws::{CloseCode, CloseReason};
+
+use crate::{
+    core::{outbox::Outbox, room::room::ServerRooms, user::user::User},
+    server::roster::send_roster,
+    utils::lock,
+};
+
+/**
+ * Every websocket session connected to this server, and the users that are banned from it
+ *
+ * The session keeps the real state of its user, the registry holds a copy that is updated after every command.
+ * Only used for looking up sessions, the locks are never held across an `.await`.
+ */
+#[derive(Default)]
+pub struct Sessions {
+    users: RwLock<HashMap<String, User>>,
+
+    // Rooms deleted while the session was in them, by session id, left by the session before its next frame
+    deleted_rooms: RwLock<HashMap<String, Vec<String>>>,
+
+    // Banned with the admin API, and banned in the server config
+    banned: RwLock<HashSet<String>>,
+    banned_by_config: RwLock<HashSet<String>>,
+}
+
+impl Sessions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the session of the user, or updates the copy of the user when it is already registered
+    ///
+    /// Rooms deleted since the session last left its deleted rooms are left out of the copy.
+    pub fn update(&self, user: &User) {
+        let mut users = lock::write(&self.users);
+        let mut user = user.clone();
+        if let Some(rooms) = lock::read(&self.deleted_rooms).get(user.get_session_id()) {
+            for room_name in rooms {
+                user.leave_room(room_name);
+            }
+        }
+        users.insert(user.get_session_id().to_string(), user);
+    }
+
+    /// Removes the session of the user, when its connection is closed
+    pub fn remove(&self, user: &User) {
+        lock::write(&self.users).remove(user.get_session_id());
+        lock::write(&self.deleted_rooms).remove(user.get_session_id());
+    }
+
+    /// Takes every session out of the deleted room
+    ///
+    /// The copies are changed right away, the sessions leave the room before handling their next frame.
+    /// Otherwise the members could still write in a new room with the same name.
+    pub fn remove_room(&self, room_name: &str) {
+        let mut users = lock::write(&self.users);
+        let mut deleted_rooms = lock::write(&self.deleted_rooms);
+        for (session_id, user) in users.iter_mut() {
+            if user.leave_room(room_name) {
+                deleted_rooms
+                    .entry(session_id.clone())
+                    .or_default()
+                    .push(room_name.to_string());
+            }
+        }
+    }
+
+    /// Takes the user of a session out of the rooms that were deleted since it was last called
+    pub fn leave_deleted_rooms(&self, user: &mut User) {
+        let deleted_rooms = lock::write(&self.deleted_rooms).remove(user.get_session_id());
+        for room_name in deleted_rooms.unwrap_or_default() {
+            user.leave_room(&room_name);
+        }
+    }
+
+    /// Returns the user of the session with the given id
+    pub fn get(&self, session_id: &str) -> Option<User> {
+        lock::read(&self.users).get(session_id).cloned()
+    }
+
+    /// Returns the users of every session
+    pub fn users(&self) -> Vec<User> {
+        lock::read(&self.users).values().cloned().collect()
+    }
+
+    /// Returns the sessions of the user with the given id, a user can be connected more than once
+    pub fn sessions_of(&self, user_id: &str) -> Vec<User> {
+        self.users()
+            .into_iter()
+            .filter(|user| user.get_id() == Some(user_id))
+            .collect()
+    }
+
+    /// Returns the outboxes of every session
+    pub fn outboxes(&self) -> Vec<Outbox> {
+        lock::read(&self.users)
+            .values()
+            .map(|user| user.get_outbox().clone())
+            .collect()
+    }
+
+    /// Amount of connected sessions
+    pub fn count(&self) -> usize {
+        lock::read(&self.users).len()
+    }
+
+    /// Bans the user with the given id, until the server is restarted
+    pub fn ban(&self, user_id: &str) {
+        lock::write(&self.banned).insert(user_id.to_string());
+    }
+
+    /// Replaces the users banned in the server config, the bans of the admin API are kept
+    pub fn set_config_bans(&self, user_ids: &[String]) {
+        *lock::write(&self.banned_by_config) = user_ids.iter().cloned().collect();
+    }
+
+    /// Checks if the user with the given id is banned
+    pub fn is_banned(&self, user_id: &str) -> bool {
+        lock::read(&self.banned).contains(user_id)
+            || lock::read(&self.banned_by_config).contains(user_id)
+    }
+
+    /// Takes the user out of its rooms and closes its session, with the reason shown to the user
+    pub async fn disconnect(&self, user: &User, server_rooms: &ServerRooms, reason: String) {
+        self.remove(user);
+        for room in server_rooms.joined_rooms(user) {
+            let mut room = room.lock().await;
+            room.remove_session(user);
+            send_roster(&room);
+        }
+        user.get_outbox().close(Some(CloseReason {
+            code: CloseCode::Policy,
+            description: Some(reason),
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: &str) -> User {
+        let mut user = User::detached();
+        user.set_id(id.to_string());
+        user
+    }
+
+    #[test]
+    fn test_update_and_remove_sessions() {
+        let sessions = Sessions::new();
+        let mut alice = user("alice");
+        let bob = user("bob");
+        sessions.update(&alice);
+        sessions.update(&bob);
+
+        // The copy of the user is replaced, not added again
+        alice.set_user_name("Alice".to_string());
+        sessions.update(&alice);
+        assert_eq!(sessions.count(), 2);
+        let stored = sessions.get(alice.get_session_id()).unwrap();
+        assert_eq!(stored.get_user_name(), "Alice");
+
+        // A user connected twice has two sessions
+        let alice_again = user("alice");
+        sessions.update(&alice_again);
+        assert_eq!(sessions.sessions_of("alice").len(), 2);
+
+        sessions.remove(&alice);
+        assert_eq!(sessions.count(), 2);
+        assert!(sessions.get(alice.get_session_id()).is_none());
+    }
+
+    #[test]
+    fn test_remove_deleted_room() {
+        let sessions = Sessions::new();
+        let mut alice = user("alice");
+        alice.join_room("lobby".to_string());
+        alice.join_room("games".to_string());
+        sessions.update(&alice);
+
+        // The copy leaves the room right away, and keeps it left when the session sends an older copy
+        sessions.remove_room("lobby");
+        assert_eq!(
+            sessions.get(alice.get_session_id()).unwrap().get_rooms(),
+            ["games"]
+        );
+        sessions.update(&alice);
+        assert_eq!(
+            sessions.get(alice.get_session_id()).unwrap().get_rooms(),
+            ["games"]
+        );
+
+        // The session leaves the room once
+        sessions.leave_deleted_rooms(&mut alice);
+        assert_eq!(alice.get_rooms(), ["games"]);
+        alice.join_room("lobby".to_string());
+        sessions.leave_deleted_rooms(&mut alice);
+        assert_eq!(alice.get_rooms(), ["games", "lobby"]);
+    }
+
+    #[test]
+    fn test_ban() {
+        let sessions = Sessions::new();
+        assert!(!sessions.is_banned("alice"));
+        sessions.ban("alice");
+        assert!(sessions.is_banned("alice"));
+        assert!(!sessions.is_banned("bob"));
+
+        // The bans of the config are replaced on reload, without lifting the other bans
+        sessions.set_config_bans(&["bob".to_string()]);
+        assert!(sessions.is_banned("bob"));
+        sessions.set_config_bans(&[]);
+        assert!(!sessions.is_banned("bob"));
+        assert!(sessions.is_banned("alice"));
+    }
+}
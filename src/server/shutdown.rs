@@ -0,0 +1,222 @@
+use std::time::Duration;
+
+use actix_web::{dev::ServerHandle, web};
+use actix_ws::{CloseCode, CloseReason};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::watch, time::Instant};
+use tracing::{info, warn};
+
+use crate::{
+    core::{messages::ServerMessage, outbox::broadcast_frame},
+    server::sessions::Sessions,
+    utils::traits::JsonSerializing,
+};
+
+fn default_timeout_secs() -> u64 {
+    return 10;
+}
+
+/**
+ * Shutdown config defined in the server config file
+ *
+ * Used when the server is stopped with Ctrl-C or SIGTERM, the admin API can give its own reason and delay.
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ShutdownConfig {
+    /// Max amount of seconds to wait for the clients to close their websockets
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+
+    /// Seconds after which clients can reconnect, for servers that are restarted by a supervisor
+    #[serde(default)]
+    pub reconnect_after_secs: Option<u64>,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: default_timeout_secs(),
+            reconnect_after_secs: None,
+        }
+    }
+}
+
+/// Why the server is shutting down, and when the clients can reconnect
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShutdownNotice {
+    pub reason: String,
+
+    #[serde(default)]
+    pub reconnect_after: Option<u64>,
+}
+
+impl ShutdownNotice {
+    /// Message sent to every user before the websockets are closed
+    pub fn server_message(&self) -> ServerMessage {
+        ServerMessage::ServerShutdown {
+            reason: self.reason.clone(),
+            reconnect_after: self.reconnect_after,
+        }
+    }
+
+    /// Close reason of the websockets, the code tells clients whether the server is coming back
+    pub fn close_reason(&self) -> CloseReason {
+        let code = match self.reconnect_after {
+            Some(_) => CloseCode::Restart,
+            None => CloseCode::Away,
+        };
+        CloseReason {
+            code,
+            description: Some(self.reason.clone()),
+        }
+    }
+}
+
+/// Starts the shutdown of the server once, from a signal or from the admin API
+#[derive(Debug)]
+pub struct Shutdown {
+    notice: watch::Sender<Option<ShutdownNotice>>,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self {
+            notice: watch::Sender::new(None),
+        }
+    }
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts the shutdown, returns false if the server is already shutting down
+    pub fn trigger(&self, notice: ShutdownNotice) -> bool {
+        self.notice.send_if_modified(|current| {
+            if current.is_some() {
+                return false;
+            }
+            *current = Some(notice);
+            true
+        })
+    }
+
+    /// Checks if the server is shutting down, new sessions are refused
+    pub fn is_triggered(&self) -> bool {
+        self.notice.borrow().is_some()
+    }
+
+    /// Waits until the shutdown has been triggered
+    pub async fn triggered(&self) -> ShutdownNotice {
+        let mut receiver = self.notice.subscribe();
+        let notice = receiver
+            .wait_for(Option::is_some)
+            .await
+            .expect("The sender lives as long as the shutdown");
+        notice.clone().unwrap()
+    }
+}
+
+/// Shuts the server down once triggered
+///
+/// New connections are refused, every user gets the notice, and the websockets are closed once the notice has been sent.
+/// Rooms only live in memory, so there is no state to flush. The server is stopped when all sessions are closed,
+/// or when the timeout is reached.
+pub async fn shut_down_when_triggered(
+    shutdown: web::Data<Shutdown>,
+    server: ServerHandle,
+    sessions: web::Data<Sessions>,
+    timeout: Duration,
+) {
+    let notice = shutdown.triggered().await;
+    info!(reason = %notice.reason, reconnect_after = ?notice.reconnect_after, "Shutting down");
+
+    // Stop accepting new connections
+    server.pause().await;
+
+    // The notice is queued before the close, so the clients get it first
+    let outboxes = sessions.outboxes();
+    if let Some(frame) = notice.server_message().frame() {
+        broadcast_frame(&frame, &outboxes);
+    }
+    for outbox in &outboxes {
+        outbox.close(Some(notice.close_reason()));
+    }
+
+    // Sessions are removed once the websocket is closed on both sides
+    let deadline = Instant::now() + timeout;
+    while sessions.count() > 0 && Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    if sessions.count() > 0 {
+        warn!(
+            sessions = sessions.count(),
+            "Sessions were not closed in time, dropping them"
+        );
+    }
+
+    server.stop(false).await;
+}
+
+/// Triggers the shutdown on Ctrl-C, and on SIGTERM on unix
+pub async fn trigger_on_signals(shutdown: web::Data<Shutdown>, config: ShutdownConfig) {
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(err) => {
+                warn!(error = %err, "SIGTERM handler could not be installed");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => (),
+        _ = terminate => (),
+    }
+
+    shutdown.trigger(ShutdownNotice {
+        reason: "Server is shutting down".to_string(),
+        reconnect_after: config.reconnect_after_secs,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notice(reason: &str, reconnect_after: Option<u64>) -> ShutdownNotice {
+        ShutdownNotice {
+            reason: reason.to_string(),
+            reconnect_after,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_trigger_only_once() {
+        let shutdown = Shutdown::new();
+        assert!(!shutdown.is_triggered());
+
+        assert!(shutdown.trigger(notice("Update", Some(30))));
+        assert!(!shutdown.trigger(notice("Again", None)));
+        assert!(shutdown.is_triggered());
+
+        // The first notice is kept, also for waiters that come later
+        assert_eq!(shutdown.triggered().await, notice("Update", Some(30)));
+    }
+
+    #[test]
+    fn test_close_code_tells_if_the_server_comes_back() {
+        assert_eq!(
+            notice("Restart", Some(5)).close_reason().code,
+            CloseCode::Restart
+        );
+        assert_eq!(notice("Gone", None).close_reason().code, CloseCode::Away);
+    }
+}
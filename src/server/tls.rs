@@ -0,0 +1,35 @@
+use std::io;
+
+use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+
+use super::config::TlsConfig;
+
+/// Loads the certificate chain and private key from the PEM files in the config
+pub fn load_rustls_config(tls: &TlsConfig) -> io::Result<rustls::ServerConfig> {
+    let cert_chain = CertificateDer::pem_file_iter(&tls.cert_file)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|err| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Could not read certificate {}: {}", tls.cert_file, err),
+            )
+        })?;
+    if cert_chain.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("No certificate found in {}", tls.cert_file),
+        ));
+    }
+
+    let key = PrivateKeyDer::from_pem_file(&tls.key_file).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Could not read private key {}: {}", tls.key_file, err),
+        )
+    })?;
+
+    rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(cert_chain, key)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+}
@@ -1,32 +1,295 @@
 use std::env;
 
+use clap::{Args, Parser, Subcommand};
+
 use crate::{
-    client::config::{parse_client_config, ClientConfig},
+    client::config::{load_client_config, ClientConfig},
     utils::constants::ERROR_LOG,
 };
 
+/// Command line of the client binary
+#[derive(Debug, Parser)]
+#[command(
+    name = "client",
+    version,
+    about = "Terminal chat client of RusticReach"
+)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct ClientArgs {
+    #[command(subcommand)]
+    pub command: Option<ClientCommand>,
+
+    /// Options used when no subcommand is given, which connects like `connect`
+    #[command(flatten)]
+    pub options: ClientOptions,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ClientCommand {
+    /// Connect to the server given with --server, or to the default server of the config
+    Connect(ClientOptions),
+
+    /// Check that the config file can be used, and print the settings the client would run with
+    CheckConfig(ClientOptions),
+}
+
+/// Options of the client, they override the values of the config file
+#[derive(Debug, Clone, Default, Args)]
+pub struct ClientOptions {
+    /// Path to the client config file
+    #[arg(short, long, value_name = "FILE", default_value = "client.yml")]
+    pub config: String,
+
+    /// Server to connect to, the port is 8080 when not given
+    #[arg(short, long, value_name = "HOST[:PORT]", value_parser = parse_server_address)]
+    pub server: Option<ServerAddress>,
+
+    /// Room to join once connected
+    #[arg(short, long)]
+    pub room: Option<String>,
+
+    /// User name to use instead of the one in the config
+    #[arg(short, long)]
+    pub name: Option<String>,
+
+    /// Print messages as plain lines and read input line by line, for scripts and pipes
+    #[arg(long)]
+    pub no_tui: bool,
+
+    /// File to write the logs to
+    #[arg(long, value_name = "FILE")]
+    pub log_file: Option<String>,
+}
+
+/// Host and optional port given with --server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerAddress {
+    pub host: String,
+    pub port: Option<u16>,
+}
+
+/// Parses `host`, `host:port`, `[ipv6]` and `[ipv6]:port`
+pub fn parse_server_address(address: &str) -> Result<ServerAddress, String> {
+    let invalid_port = |port: &str| format!("{} is not a valid port", port);
+
+    let (host, port) = if let Some(rest) = address.strip_prefix('[') {
+        let (host, rest) = rest
+            .split_once(']')
+            .ok_or("IPv6 addresses must be closed with ]")?;
+        match rest.strip_prefix(':') {
+            Some(port) => (host, Some(port)),
+            None if rest.is_empty() => (host, None),
+            None => return Err(format!("Unexpected {} after the address", rest)),
+        }
+    } else {
+        match address.split_once(':') {
+            // More than one colon is an IPv6 address without a port
+            Some((host, port)) if !port.contains(':') => (host, Some(port)),
+            _ => (address, None),
+        }
+    };
+
+    if host.is_empty() {
+        return Err("The host can not be empty".to_string());
+    }
+    let port = match port {
+        Some(port) => Some(port.parse::<u16>().map_err(|_| invalid_port(port))?),
+        None => None,
+    };
+    Ok(ServerAddress {
+        host: host.to_string(),
+        port,
+    })
+}
+
 /**
- * Validates the input arguments of the
+ * Parses the input arguments of the client
  *
- * Logs error and info
+ * Clap prints the help, the version or the usage error and exits when needed.
+ */
+pub fn parse_client_args() -> ClientArgs {
+    ClientArgs::parse()
+}
+
+impl ClientArgs {
+    /// Options of the subcommand, or of the client itself when no subcommand is given
+    pub fn get_options(&self) -> &ClientOptions {
+        match &self.command {
+            Some(ClientCommand::Connect(options)) | Some(ClientCommand::CheckConfig(options)) => {
+                options
+            }
+            None => &self.options,
+        }
+    }
+
+    /// Checks if the client should connect right away, instead of waiting for the auto connect of the config
+    pub fn should_connect(&self) -> bool {
+        matches!(self.command, Some(ClientCommand::Connect(_)))
+            || self.get_options().server.is_some()
+    }
+}
+
+impl ClientOptions {
+    /// Loads the config file, and applies the options on top of it
+    pub fn load_config(&self) -> Result<ClientConfig, String> {
+        let mut config =
+            load_client_config(&self.config).map_err(|err| format!("{} {}", *ERROR_LOG, err))?;
+        self.apply(&mut config);
+        Ok(config)
+    }
+
+    fn apply(&self, config: &mut ClientConfig) {
+        if let Some(name) = &self.name {
+            config.set_user_name(name.clone());
+        }
+        if let Some(log_file) = &self.log_file {
+            config.set_log_file(log_file.clone());
+        }
+        if let Some(address) = &self.server {
+            let server = config.resolve_server(&address.host, address.port);
+            config.set_default_server(server);
+        }
+        if let Some(room) = &self.room {
+            if let Some(mut server) = config.get_default_server().cloned() {
+                server.set_auto_join(room.clone());
+                config.set_default_server(server);
+            }
+        }
+    }
+}
+
+/// Arguments of the server binary
+#[derive(Debug, Default)]
+pub struct ServerArgs {
+    /// Path to the server config file
+    pub config: Option<String>,
+
+    /// Write the protocol schema to the given file, and exit
+    pub dump_schema: Option<String>,
+}
+
+/**
+ * Validates the input arguments of the server
  *
- * Returns either the parsed client config or an error.
+ * Returns either the parsed arguments or an error message with the usage.
  */
-pub fn validate_args() -> Result<ClientConfig, String> {
-    let args: Vec<String> = env::args().collect();
+pub fn validate_server_args() -> Result<ServerArgs, String> {
+    parse_server_args(env::args().skip(1))
+}
+
+fn parse_server_args(mut args: impl Iterator<Item = String>) -> Result<ServerArgs, String> {
+    let usage = format!(
+        "{} Usage: <program> [-c <server.yml>] [--dump-schema <file>]",
+        *ERROR_LOG
+    );
+    let mut server_args = ServerArgs::default();
 
-    if args.len() != 3 || &args[1] != "-c" || args[2].is_empty() {
-        let message = format!("{} Usage: <program> -c <client.yml>", *ERROR_LOG);
-        return Err(message);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-c" => match args.next() {
+                Some(file_path) if !file_path.is_empty() => server_args.config = Some(file_path),
+                _ => return Err(usage),
+            },
+            "--dump-schema" => match args.next() {
+                Some(file_path) if !file_path.is_empty() => {
+                    server_args.dump_schema = Some(file_path)
+                }
+                _ => return Err(usage),
+            },
+            _ => return Err(usage),
+        }
     }
 
-    let file_path = &args[2];
-    if let Some(config) = parse_client_config(&file_path) {
-        return Ok(config);
-    } else {
-        return Err(format!(
-            "{} Provided client config {} could not be parsed",
-            *ERROR_LOG, file_path
-        ));
+    Ok(server_args)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_server_address() {
+        let address = |host: &str, port: Option<u16>| ServerAddress {
+            host: host.to_string(),
+            port,
+        };
+        assert_eq!(
+            parse_server_address("chat.example.com"),
+            Ok(address("chat.example.com", None))
+        );
+        assert_eq!(
+            parse_server_address("127.0.0.1:9000"),
+            Ok(address("127.0.0.1", Some(9000)))
+        );
+        assert_eq!(
+            parse_server_address("[::1]:9000"),
+            Ok(address("::1", Some(9000)))
+        );
+        assert_eq!(parse_server_address("::1"), Ok(address("::1", None)));
+        assert!(parse_server_address("localhost:http").is_err());
+        assert!(parse_server_address(":9000").is_err());
+        assert!(parse_server_address("[::1").is_err());
+    }
+
+    #[test]
+    fn test_options_of_subcommands() {
+        // Without a subcommand the options are given to the client itself
+        let args = ClientArgs::try_parse_from(["client", "--server", "localhost:9000"]).unwrap();
+        assert!(args.command.is_none());
+        assert!(args.should_connect());
+        assert_eq!(args.get_options().config, "client.yml");
+
+        let args = ClientArgs::try_parse_from(["client", "connect", "-c", "other.yml", "--no-tui"])
+            .unwrap();
+        assert!(args.should_connect());
+        assert_eq!(args.get_options().config, "other.yml");
+        assert!(args.get_options().no_tui);
+
+        let args = ClientArgs::try_parse_from(["client", "check-config"]).unwrap();
+        assert!(matches!(args.command, Some(ClientCommand::CheckConfig(_))));
+        assert!(!args.should_connect());
+
+        assert!(ClientArgs::try_parse_from(["client", "--server", "localhost:x"]).is_err());
+    }
+
+    #[test]
+    fn test_options_override_the_config() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let file_path = dir.path().join("config.yml");
+        std::fs::write(
+            &file_path,
+            r#"
+            client:
+              user_name: "zebra123"
+              hash_pass: "asfdgfhgdQESHZDJXK"
+              user_token: "12345678756432134567"
+              default_server:
+                server_ip: 127.0.0.1
+                auto_join: lobby
+            "#,
+        )
+        .unwrap();
+        let file_path = file_path.to_str().unwrap().to_string();
+
+        let options = ClientOptions {
+            config: file_path.clone(),
+            server: Some(ServerAddress {
+                host: "chat.example.com".to_string(),
+                port: Some(9000),
+            }),
+            room: Some("work".to_string()),
+            name: Some("elephant321".to_string()),
+            no_tui: true,
+            log_file: Some("other.log".to_string()),
+        };
+        let config = options.load_config().unwrap();
+
+        assert_eq!(config.get_user_name(&None), "elephant321");
+        assert_eq!(config.get_logging().file.as_deref(), Some("other.log"));
+        let server = config.get_default_server().unwrap();
+        assert_eq!(server.ws_url(), "ws://chat.example.com:9000/ws");
+        assert_eq!(server.get_auto_join(), Some("work"));
     }
 }
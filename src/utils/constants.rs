@@ -1,6 +1,7 @@
 use colored::Colorize;
 use crossterm::style::Stylize;
 use once_cell::sync::Lazy;
+use std::time::Duration;
 
 pub const COMMAND_LINE_SYMBOL: &str = "$";
 pub const MESSAGE_COMMAND_SYMBOL: &str = "/";
@@ -12,8 +13,35 @@ pub static SERVER_INFO: Lazy<String> =
     Lazy::new(|| Colorize::bold("[SERVER]").yellow().to_string());
 
 pub static MESSAGE_LINE_SYMBOL: Lazy<String> = Lazy::new(|| Colorize::blue(">").to_string());
-pub const DEFAULT_SERVER_PORT: &str = "8080";
 
+/// Bytes allowed in a websocket frame on top of the chat message content, for the rest of the JSON message
+pub const MAX_FRAME_OVERHEAD: usize = 4096;
+
+/// Max amount of characters in the name or a nickname of a user
+pub const MAX_NAME_LENGTH: usize = 32;
+
+/// Amount of characters logged of a frame that could not be parsed, the rest of the frame is left out of the log
+pub const LOGGED_FRAME_PREFIX: usize = 64;
+
+/// Amount of chat messages each room keeps in its history
+pub const ROOM_HISTORY_SIZE: usize = 100;
+
+/// How often the client pings the server to check that the connection is alive
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The client treats the connection as lost when nothing has been received from the server for this long
+pub const CONNECTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Time between attempts to connect to a cluster peer
+pub const CLUSTER_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Time another node has to prove that it knows the secret of the cluster
+pub const CLUSTER_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How often the server checks if its config file has changed
+pub const CONFIG_WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Formats a message from the server
 pub fn server_message(content: &str) -> String {
     return format!("{} {}", *SERVER_INFO, content).yellow().to_string();
 }
@@ -0,0 +1,132 @@
+use std::{env, fs::OpenOptions, sync::Mutex};
+
+use serde::{Deserialize, Serialize};
+use tracing_subscriber::EnvFilter;
+
+fn default_level() -> String {
+    return "info".to_string();
+}
+
+/// Format of the log lines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// One JSON object per line, for log collectors
+    #[default]
+    Json,
+
+    /// Human readable lines
+    Text,
+}
+
+/**
+ * Logging config defined in the server and client config files
+ *
+ * The levels can be set per module, `RUST_LOG` overrides the levels of the config.
+ */
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct LoggingConfig {
+    /// Filter directives, like `info` or `info,rustic_reach::server::cluster=debug`
+    #[serde(default = "default_level")]
+    pub level: String,
+
+    #[serde(default)]
+    pub format: LogFormat,
+
+    /// File the logs are appended to, stdout when not given
+    #[serde(default)]
+    pub file: Option<String>,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: default_level(),
+            format: LogFormat::default(),
+            file: None,
+        }
+    }
+}
+
+/// Builds the filter from `RUST_LOG` when set, and from the levels of the config otherwise
+fn build_filter(env_directives: Option<String>, level: &str) -> Result<EnvFilter, String> {
+    let directives = match env_directives {
+        Some(directives) if !directives.trim().is_empty() => directives,
+        _ => level.to_string(),
+    };
+    return EnvFilter::try_new(&directives)
+        .map_err(|err| format!("Invalid log level '{}': {}", directives, err));
+}
+
+/**
+ * Installs the global logger with the given config
+ *
+ * Can only be called once, the logs of crates using `log` are included too.
+ */
+pub fn init_logging(config: &LoggingConfig) -> Result<(), String> {
+    let filter = build_filter(env::var(EnvFilter::DEFAULT_ENV).ok(), &config.level)?;
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    let result = match &config.file {
+        Some(file_path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(file_path)
+                .map_err(|err| format!("Log file {} could not be opened: {}", file_path, err))?;
+            let builder = builder.with_writer(Mutex::new(file)).with_ansi(false);
+            match config.format {
+                LogFormat::Json => builder.json().try_init(),
+                LogFormat::Text => builder.try_init(),
+            }
+        }
+        None => match config.format {
+            LogFormat::Json => builder.json().try_init(),
+            LogFormat::Text => builder.try_init(),
+        },
+    };
+
+    return result.map_err(|err| format!("Logging could not be initialized: {}", err));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_logging_config() {
+        let config: LoggingConfig = serde_yaml::from_str(
+            r#"
+            level: "warn,rustic_reach::server::cluster=debug"
+            format: text
+            file: "server.log"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.format, LogFormat::Text);
+        assert_eq!(config.file.as_deref(), Some("server.log"));
+
+        // Everything is optional
+        let config: LoggingConfig = serde_yaml::from_str("{}").unwrap();
+        assert_eq!(config.level, "info");
+        assert_eq!(config.format, LogFormat::Json);
+        assert!(config.file.is_none());
+    }
+
+    #[test]
+    fn test_build_filter() {
+        let filter = build_filter(None, "warn,rustic_reach::server::cluster=debug").unwrap();
+        assert_eq!(
+            filter.to_string(),
+            "rustic_reach::server::cluster=debug,warn"
+        );
+
+        // The environment takes precedence, unless it is empty
+        let filter = build_filter(Some("error".to_string()), "info").unwrap();
+        assert_eq!(filter.to_string(), "error");
+        let filter = build_filter(Some(" ".to_string()), "info").unwrap();
+        assert_eq!(filter.to_string(), "info");
+
+        assert!(build_filter(None, "info,rustic_reach=loud").is_err());
+    }
+}
@@ -1,6 +1,10 @@
 pub mod args;
 pub mod constants;
 pub mod hash;
+pub mod lock;
+pub mod logging;
+pub mod sanitize;
 pub mod terminal_ui;
 pub mod time;
+pub mod token_bucket;
 pub mod traits;
@@ -0,0 +1,102 @@
+/// Character shown instead of control characters in remote text
+const REPLACEMENT_CHAR: char = '\u{FFFD}';
+
+const ESC: char = '\u{1b}';
+const BEL: char = '\u{7}';
+const C1_CSI: char = '\u{9b}';
+const C1_OSC: char = '\u{9d}';
+const C1_ST: char = '\u{9c}';
+
+/// Makes text received from the server safe to print in the terminal
+///
+/// Escape sequences (colors, cursor movement, clearing the screen, setting the terminal title, ...) are removed,
+/// and other control characters like newlines are replaced, so remote text can never change the terminal
+/// or spoof other lines.
+pub fn sanitize_remote_text(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ESC => match chars.next() {
+                // Control sequence: ESC [ <parameters> <final byte>
+                Some('[') => skip_control_sequence(&mut chars),
+                // Operating system command, string and device control sequences, ended by BEL or ESC \
+                Some(']') | Some('P') | Some('X') | Some('^') | Some('_') => {
+                    skip_string_sequence(&mut chars)
+                }
+                // Other escape sequences are two characters long
+                _ => (),
+            },
+            C1_CSI => skip_control_sequence(&mut chars),
+            C1_OSC => skip_string_sequence(&mut chars),
+            '\t' => output.push(' '),
+            c if c.is_control() => output.push(REPLACEMENT_CHAR),
+            c => output.push(c),
+        }
+    }
+
+    output
+}
+
+/// Skips the parameters and the final byte of a control sequence
+fn skip_control_sequence(chars: &mut impl Iterator<Item = char>) {
+    for c in chars.by_ref() {
+        if ('\u{40}'..='\u{7e}').contains(&c) {
+            break;
+        }
+    }
+}
+
+/// Skips a string sequence until the string terminator
+fn skip_string_sequence(chars: &mut std::iter::Peekable<impl Iterator<Item = char>>) {
+    while let Some(c) = chars.next() {
+        match c {
+            BEL | C1_ST => break,
+            ESC if chars.peek() == Some(&'\\') => {
+                chars.next();
+                break;
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_text_is_unchanged() {
+        let text = "Hello there, æøå 🦀 [SERVER]";
+        assert_eq!(sanitize_remote_text(text), text);
+    }
+
+    #[test]
+    fn test_removes_colors_and_screen_clear() {
+        assert_eq!(sanitize_remote_text("\u{1b}[31mred\u{1b}[0m"), "red");
+        assert_eq!(sanitize_remote_text("\u{1b}[2J\u{1b}[Hhi"), "hi");
+        assert_eq!(sanitize_remote_text("\u{9b}2Jhi"), "hi");
+    }
+
+    #[test]
+    fn test_removes_terminal_title() {
+        assert_eq!(sanitize_remote_text("\u{1b}]0;pwned\u{7}hi"), "hi");
+        assert_eq!(sanitize_remote_text("\u{1b}]0;pwned\u{1b}\\hi"), "hi");
+        assert_eq!(sanitize_remote_text("\u{9d}0;pwned\u{9c}hi"), "hi");
+
+        // Unterminated sequences swallow the rest of the text
+        assert_eq!(sanitize_remote_text("hi\u{1b}]0;pwned"), "hi");
+    }
+
+    #[test]
+    fn test_replaces_line_breaks() {
+        // A message can not start a new line that looks like it came from the server
+        let spoof = "hi\r\n[SERVER] Please send your token";
+        assert_eq!(
+            sanitize_remote_text(spoof),
+            "hi\u{FFFD}\u{FFFD}[SERVER] Please send your token"
+        );
+        assert_eq!(sanitize_remote_text("a\tb\u{7f}"), "a b\u{FFFD}");
+    }
+}
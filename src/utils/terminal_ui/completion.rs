@@ -0,0 +1,128 @@
+use super::panes::Panes;
+
+/// Commands that take the name of a room, and if the room has to be joined
+const ROOM_COMMANDS: [(&str, bool); 3] = [("/join", false), ("/leave", true), ("/switch", true)];
+
+/// Words the word at the cursor can be completed with
+///
+/// The first word of a command is completed with the commands, the argument of a room command with the rooms,
+/// and a word starting with `@` with the members of the room shown. Letters are matched without case.
+pub fn candidates(before: &str, word: &str, commands: &[String], panes: &Panes) -> Vec<String> {
+    let matches = |candidate: &str| candidate.to_lowercase().starts_with(&word.to_lowercase());
+    let previous_words: Vec<&str> = before.split_whitespace().collect();
+
+    if let Some(name) = word.strip_prefix('@') {
+        return panes
+            .members
+            .iter()
+            .filter(|member| member.to_lowercase().starts_with(&name.to_lowercase()))
+            .map(|member| format!("@{}", member))
+            .collect();
+    }
+
+    match previous_words[..] {
+        [] if word.starts_with('/') => commands
+            .iter()
+            .filter(|command| matches(command))
+            .cloned()
+            .collect(),
+        [command] => match ROOM_COMMANDS.iter().find(|(name, _)| *name == command) {
+            Some((_, joined_only)) => panes
+                .rooms
+                .iter()
+                .filter(|room| room.joined || !joined_only)
+                .filter(|room| matches(&room.name))
+                .map(|room| room.name.clone())
+                .collect(),
+            None => Vec::new(),
+        },
+        _ => Vec::new(),
+    }
+}
+
+/// Completion in progress, repeated Tab goes through the candidates
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    /// Position in the input line of the word that is completed
+    pub start: usize,
+    candidates: Vec<String>,
+    index: usize,
+}
+
+impl Completion {
+    pub fn new(start: usize, candidates: Vec<String>) -> Self {
+        Self {
+            start,
+            candidates,
+            index: 0,
+        }
+    }
+
+    /// Text of the candidate shown, followed by a space
+    pub fn text(&self) -> String {
+        format!("{} ", self.candidates[self.index])
+    }
+
+    /// Moves to the next candidate, or to the one before with Shift-Tab, and starts over at the end
+    pub fn cycle(&mut self, backwards: bool) {
+        let count = self.candidates.len();
+        self.index = match backwards {
+            true => (self.index + count - 1) % count,
+            false => (self.index + 1) % count,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::terminal_ui::panes::RoomEntry;
+
+    fn panes() -> Panes {
+        let room = |name: &str, joined: bool| RoomEntry {
+            name: name.to_string(),
+            joined,
+            unread: 0,
+        };
+        Panes {
+            rooms: vec![room("lobby", true), room("linux", false)],
+            members: vec!["alice".to_string(), "Albert".to_string(), "bob".to_string()],
+            ..Panes::default()
+        }
+    }
+
+    #[test]
+    fn test_candidates() {
+        let commands = ["/join".to_string(), "/leave".to_string()];
+        let panes = panes();
+        assert_eq!(candidates("", "/j", &commands, &panes), ["/join"]);
+        assert_eq!(candidates("", "/", &commands, &panes), commands);
+        assert_eq!(
+            candidates("/join ", "l", &commands, &panes),
+            ["lobby", "linux"]
+        );
+
+        // Only the joined rooms can be left
+        assert_eq!(candidates("/leave ", "", &commands, &panes), ["lobby"]);
+        assert_eq!(
+            candidates("hi ", "@al", &commands, &panes),
+            ["@alice", "@Albert"]
+        );
+
+        // Commands are only completed at the start, and other words are left as they are
+        assert!(candidates("hi ", "/j", &commands, &panes).is_empty());
+        assert!(candidates("", "lo", &commands, &panes).is_empty());
+    }
+
+    #[test]
+    fn test_cycle_through_candidates() {
+        let mut completion = Completion::new(0, vec!["/join".to_string(), "/leave".to_string()]);
+        assert_eq!(completion.text(), "/join ");
+        completion.cycle(false);
+        assert_eq!(completion.text(), "/leave ");
+        completion.cycle(false);
+        assert_eq!(completion.text(), "/join ");
+        completion.cycle(true);
+        assert_eq!(completion.text(), "/leave ");
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::VecDeque;
+
+use unicode_width::UnicodeWidthChar;
+
+/// Number of sent lines kept for going back with Up
+const HISTORY_SIZE: usize = 100;
+
+/// Line the user is typing, with the cursor and the lines sent before
+#[derive(Debug, Default)]
+pub struct InputLine {
+    chars: Vec<char>,
+    cursor: usize,
+    history: VecDeque<String>,
+
+    // Position in the history while going through it with Up and Down, None when typing a new line
+    history_index: Option<usize>,
+
+    // Line that was typed before going through the history, it is back after the newest line
+    draft: Vec<char>,
+}
+
+fn char_width(c: char) -> usize {
+    c.width().unwrap_or(0)
+}
+
+impl InputLine {
+    pub fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.chars.insert(self.cursor, c);
+        self.cursor += 1;
+    }
+
+    pub fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    pub fn delete(&mut self) {
+        if self.cursor < self.chars.len() {
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    pub fn move_left(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    pub fn move_right(&mut self) {
+        self.cursor = (self.cursor + 1).min(self.chars.len());
+    }
+
+    pub fn move_home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn move_end(&mut self) {
+        self.cursor = self.chars.len();
+    }
+
+    /// Start of the word before the cursor, spaces right before the cursor belong to the word
+    fn word_start(&self) -> usize {
+        let mut start = self.cursor;
+        while start > 0 && self.chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        while start > 0 && !self.chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        start
+    }
+
+    pub fn move_word_left(&mut self) {
+        self.cursor = self.word_start();
+    }
+
+    pub fn move_word_right(&mut self) {
+        while self.cursor < self.chars.len() && self.chars[self.cursor].is_whitespace() {
+            self.cursor += 1;
+        }
+        while self.cursor < self.chars.len() && !self.chars[self.cursor].is_whitespace() {
+            self.cursor += 1;
+        }
+    }
+
+    /// Start of the word that ends at the cursor, and the word, empty when the cursor is after a space
+    pub fn word_at_cursor(&self) -> (usize, String) {
+        let mut start = self.cursor;
+        while start > 0 && !self.chars[start - 1].is_whitespace() {
+            start -= 1;
+        }
+        (start, self.chars[start..self.cursor].iter().collect())
+    }
+
+    /// Text before the given position
+    pub fn text_before(&self, position: usize) -> String {
+        self.chars[..position].iter().collect()
+    }
+
+    /// Replaces the text from the given position up to the cursor, the cursor is put after the new text
+    pub fn replace_to_cursor(&mut self, start: usize, text: &str) {
+        let text: Vec<char> = text.chars().collect();
+        let end = start + text.len();
+        self.chars.splice(start..self.cursor, text);
+        self.cursor = end;
+    }
+
+    /// Deletes the word before the cursor, Ctrl-W
+    pub fn delete_word(&mut self) {
+        let start = self.word_start();
+        self.chars.drain(start..self.cursor);
+        self.cursor = start;
+    }
+
+    /// Deletes everything before the cursor, Ctrl-U
+    pub fn delete_to_start(&mut self) {
+        self.chars.drain(..self.cursor);
+        self.cursor = 0;
+    }
+
+    /// Shows the line sent before the one shown, Up
+    pub fn history_previous(&mut self) {
+        let index = match self.history_index {
+            Some(0) => return,
+            Some(index) => index - 1,
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = std::mem::take(&mut self.chars);
+                self.history.len() - 1
+            }
+        };
+        self.show_history(Some(index));
+    }
+
+    /// Shows the line sent after the one shown, or the line that was being typed, Down
+    pub fn history_next(&mut self) {
+        match self.history_index {
+            Some(index) if index + 1 < self.history.len() => self.show_history(Some(index + 1)),
+            Some(_) => self.show_history(None),
+            None => (),
+        }
+    }
+
+    fn show_history(&mut self, index: Option<usize>) {
+        self.chars = match index {
+            Some(index) => self.history[index].chars().collect(),
+            None => std::mem::take(&mut self.draft),
+        };
+        self.history_index = index;
+        self.cursor = self.chars.len();
+    }
+
+    /// Takes the line to send it, and keeps it in the history
+    pub fn submit(&mut self) -> String {
+        let line = self.text();
+        if !line.trim().is_empty() && self.history.back() != Some(&line) {
+            if self.history.len() == HISTORY_SIZE {
+                self.history.pop_front();
+            }
+            self.history.push_back(line.clone());
+        }
+        self.chars.clear();
+        self.draft.clear();
+        self.cursor = 0;
+        self.history_index = None;
+        line
+    }
+
+    /**
+     * Part of the line shown in the given number of columns, and the column of the cursor in it
+     *
+     * The line scrolls sideways so the cursor is always shown, wide characters take two columns.
+     */
+    pub fn view(&self, width: usize) -> (String, usize) {
+        let width = width.max(1);
+
+        // Leave a column for the cursor at the end of the line
+        let mut start = self.cursor;
+        let mut cursor_column = 0;
+        while start > 0 && cursor_column + char_width(self.chars[start - 1]) < width {
+            start -= 1;
+            cursor_column += char_width(self.chars[start]);
+        }
+
+        let mut view = String::new();
+        let mut view_width = 0;
+        for &c in &self.chars[start..] {
+            if view_width + char_width(c) > width {
+                break;
+            }
+            view.push(c);
+            view_width += char_width(c);
+        }
+        (view, cursor_column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn typed(text: &str) -> InputLine {
+        let mut input = InputLine::default();
+        text.chars().for_each(|c| input.insert(c));
+        input
+    }
+
+    #[test]
+    fn test_edit_line() {
+        let mut input = typed("hello world");
+        input.move_word_left();
+        input.insert('>');
+        assert_eq!(input.text(), "hello >world");
+
+        input.move_home();
+        input.delete();
+        input.move_end();
+        input.backspace();
+        assert_eq!(input.text(), "ello >worl");
+
+        // Ctrl-W deletes the word and the spaces after it, Ctrl-U the rest before the cursor
+        input.insert(' ');
+        input.delete_word();
+        assert_eq!(input.text(), "ello ");
+        input.move_left();
+        input.delete_to_start();
+        assert_eq!(input.text(), " ");
+    }
+
+    #[test]
+    fn test_replace_word_at_cursor() {
+        let mut input = typed("/join lo now");
+        (0..4).for_each(|_| input.move_left());
+        assert_eq!(input.word_at_cursor(), (6, "lo".to_string()));
+        assert_eq!(input.text_before(6), "/join ");
+
+        input.replace_to_cursor(6, "lobby ");
+        assert_eq!(input.text(), "/join lobby  now");
+        assert_eq!(input.word_at_cursor(), (12, String::new()));
+    }
+
+    #[test]
+    fn test_input_history() {
+        let mut input = typed("first");
+        assert_eq!(input.submit(), "first");
+        "second".chars().for_each(|c| input.insert(c));
+        input.submit();
+        "draft".chars().for_each(|c| input.insert(c));
+
+        input.history_previous();
+        assert_eq!(input.text(), "second");
+        input.history_previous();
+        input.history_previous();
+        assert_eq!(input.text(), "first");
+        input.history_next();
+        input.history_next();
+        assert_eq!(input.text(), "draft");
+
+        // Blank lines and repeated lines are not kept
+        input.submit();
+        input.submit();
+        "draft".chars().for_each(|c| input.insert(c));
+        input.submit();
+        assert_eq!(input.history, ["first", "second", "draft"]);
+    }
+
+    #[test]
+    fn test_input_view() {
+        let mut input = typed("0123456789");
+        assert_eq!(input.view(20), ("0123456789".to_string(), 10));
+        assert_eq!(input.view(5), ("6789".to_string(), 4));
+
+        input.move_home();
+        assert_eq!(input.view(5), ("01234".to_string(), 0));
+
+        // Wide characters take two columns
+        let input = typed("日本語");
+        assert_eq!(input.view(5), ("本語".to_string(), 4));
+    }
+}
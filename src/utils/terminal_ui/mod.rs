@@ -0,0 +1,352 @@
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    queue,
+    style::{Print, Stylize},
+    terminal::{Clear, ClearType},
+};
+use std::{
+    collections::HashMap,
+    io::{self, stdout, BufRead, Write},
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::Duration,
+};
+
+use completion::Completion;
+use input::InputLine;
+use panes::{Layout, Panes};
+use scrollback::Scrollback;
+
+pub mod completion;
+pub mod input;
+pub mod panes;
+pub mod scrollback;
+
+const PROMPT: &str = "> ";
+
+/// Message for the terminal, sent from the connection to the input thread
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UiMessage {
+    /// Shown together with the messages shown at the moment
+    Info(String),
+
+    /// Kept with the messages of the given room, and shown when the room is shown
+    Room(String, String),
+}
+
+pub struct TerminalUI {
+    input: InputLine,
+
+    // Commands for Tab completion, the rooms and the members are taken from the panes
+    commands: Vec<String>,
+    completion: Option<Completion>,
+
+    // Messages of the room shown, or of the server when no room is shown
+    scrollback: Scrollback,
+    shown_room: Option<String>,
+
+    // Messages of the rooms that are not shown, and of the server while a room is shown
+    hidden_buffers: HashMap<Option<String>, Scrollback>,
+    panes: Panes,
+    terminal_width: u16,
+    terminal_height: u16,
+
+    // Rows drawn by the last render, only the rows that changed are drawn again
+    screen: Vec<String>,
+
+    // Lines read from stdin when running without the full screen UI
+    plain_input: Option<Receiver<String>>,
+}
+
+impl TerminalUI {
+    pub fn new() -> io::Result<Self> {
+        let (terminal_width, terminal_height) = crossterm::terminal::size()?;
+        Ok(Self {
+            input: InputLine::default(),
+            commands: Vec::new(),
+            completion: None,
+            scrollback: Scrollback::default(),
+            shown_room: None,
+            hidden_buffers: HashMap::new(),
+            panes: Panes::default(),
+            terminal_width,
+            terminal_height,
+            screen: Vec::new(),
+            plain_input: None,
+        })
+    }
+
+    /// UI that prints every message as a line and reads the input line by line, for scripts and pipes
+    ///
+    /// Stdin is read on its own thread, so waiting for input never blocks the messages.
+    pub fn plain() -> Self {
+        let (line_sender, line_receiver) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                let Ok(line) = line else { break };
+                if line_sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            input: InputLine::default(),
+            commands: Vec::new(),
+            completion: None,
+            scrollback: Scrollback::default(),
+            shown_room: None,
+            hidden_buffers: HashMap::new(),
+            panes: Panes::default(),
+            terminal_width: 0,
+            terminal_height: 0,
+            screen: Vec::new(),
+            plain_input: Some(line_receiver),
+        }
+    }
+
+    /// Columns used by the UI
+    ///
+    /// The last column stays empty, a character written there would make some terminals wrap or scroll.
+    fn usable_width(&self) -> usize {
+        (self.terminal_width as usize).saturating_sub(1).max(1)
+    }
+
+    fn layout(&self) -> Layout {
+        Layout::for_width(self.usable_width())
+    }
+
+    /// Columns the messages are wrapped to
+    fn message_width(&self) -> usize {
+        self.layout().messages_width.max(1)
+    }
+
+    /// Rows for the messages, above the status bar and the input line
+    fn message_height(&self) -> usize {
+        (self.terminal_height as usize).saturating_sub(1 + self.status_bar_height())
+    }
+
+    /// Very low terminals only show the messages and the input line
+    fn status_bar_height(&self) -> usize {
+        (self.terminal_height >= 4) as usize
+    }
+
+    /// Rows of the whole screen, the panes at the top, then the status bar and the input line at the bottom
+    fn frame(&self) -> (Vec<String>, usize) {
+        let mut messages = self
+            .scrollback
+            .rows(self.message_width(), self.message_height());
+        if self.scrollback.is_scrolled() {
+            if let Some(last_row) = messages.last_mut() {
+                *last_row = "-- PageDown for newer messages --".dark_grey().to_string();
+            }
+        }
+        messages.resize(self.message_height(), String::new());
+
+        let mut frame = self.panes.body(&self.layout(), &messages);
+        if self.status_bar_height() > 0 {
+            frame.push(self.panes.status_bar(self.usable_width()));
+        }
+        let (input, cursor_column) = self
+            .input
+            .view(self.usable_width().saturating_sub(PROMPT.len()));
+        frame.push(format!("{}{}", PROMPT, input));
+        (frame, PROMPT.len() + cursor_column)
+    }
+
+    /// Sets the commands that Tab completes at the start of the input line
+    pub fn set_commands(&mut self, commands: Vec<String>) {
+        self.commands = commands;
+    }
+
+    /// Completes the word at the cursor, repeated Tab goes through the candidates
+    fn complete(&mut self, backwards: bool) {
+        if let Some(completion) = &mut self.completion {
+            completion.cycle(backwards);
+        } else {
+            let (start, word) = self.input.word_at_cursor();
+            let before = self.input.text_before(start);
+            let candidates = completion::candidates(&before, &word, &self.commands, &self.panes);
+            if candidates.is_empty() {
+                return;
+            }
+            self.completion = Some(Completion::new(start, candidates));
+        }
+        if let Some(completion) = &self.completion {
+            self.input
+                .replace_to_cursor(completion.start, &completion.text());
+        }
+    }
+
+    /// Shows the rooms, the members and the status around the messages
+    ///
+    /// The messages of the room of the panes are shown, the messages of the rooms that were left are dropped.
+    pub fn set_panes(&mut self, panes: Panes) {
+        if self.panes != panes {
+            if self.shown_room != panes.room {
+                self.show_room(panes.room.clone());
+            }
+            self.hidden_buffers.retain(|room, _| match room {
+                Some(room) => panes
+                    .rooms
+                    .iter()
+                    .any(|entry| entry.joined && entry.name == *room),
+                None => true,
+            });
+            self.panes = panes;
+            self.render().unwrap();
+        }
+    }
+
+    /// Swaps the messages shown for the messages of the given room
+    fn show_room(&mut self, room: Option<String>) {
+        let buffer = self.hidden_buffers.remove(&room).unwrap_or_default();
+        let shown = std::mem::replace(&mut self.scrollback, buffer);
+        self.hidden_buffers
+            .insert(std::mem::replace(&mut self.shown_room, room), shown);
+        self.screen.clear();
+    }
+
+    /// Draws the rows that changed since the last render, and puts the cursor in the input line
+    pub fn render(&mut self) -> io::Result<()> {
+        if self.plain_input.is_some() || self.terminal_height == 0 {
+            return Ok(());
+        }
+        let mut stdout = stdout();
+        let (frame, cursor_column) = self.frame();
+
+        queue!(stdout, cursor::Hide)?;
+        for (row, line) in frame.iter().enumerate() {
+            if self.screen.get(row) != Some(line) {
+                queue!(
+                    stdout,
+                    cursor::MoveTo(0, row as u16),
+                    Print(line),
+                    Clear(ClearType::UntilNewLine)
+                )?;
+            }
+        }
+        queue!(
+            stdout,
+            cursor::MoveTo(cursor_column as u16, self.terminal_height - 1),
+            cursor::Show
+        )?;
+        stdout.flush()?;
+
+        self.screen = frame;
+        Ok(())
+    }
+
+    pub fn add_message(&mut self, message: String) {
+        let clean_message = message.trim().to_string();
+        if !clean_message.is_empty() {
+            if self.plain_input.is_some() {
+                println!("{}", clean_message);
+                return;
+            }
+            self.scrollback.push(clean_message, self.message_width());
+            self.render().unwrap();
+        }
+    }
+
+    /// Adds a message of the given room, it is only drawn when the room is shown
+    pub fn add_room_message(&mut self, room: &str, message: String) {
+        if self.plain_input.is_some() || self.shown_room.as_deref() == Some(room) {
+            self.add_message(message);
+            return;
+        }
+        let clean_message = message.trim().to_string();
+        if !clean_message.is_empty() {
+            let width = self.message_width();
+            self.hidden_buffers
+                .entry(Some(room.to_string()))
+                .or_default()
+                .push(clean_message, width);
+        }
+    }
+
+    /// Adds the message to the buffer it belongs to
+    pub fn show_message(&mut self, message: UiMessage) {
+        match message {
+            UiMessage::Info(message) => self.add_message(message),
+            UiMessage::Room(room, message) => self.add_room_message(&room, message),
+        }
+    }
+
+    /// Edits the input line, or scrolls the messages, returns the line when Enter is pressed
+    ///
+    /// Alt+number returns the command to switch to the joined room with that number, the input line is kept.
+    fn handle_key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> Option<String> {
+        let control = modifiers.contains(KeyModifiers::CONTROL);
+        let alt = modifiers.contains(KeyModifiers::ALT);
+        let page = self.message_height().saturating_sub(1).max(1);
+
+        // Any other key keeps the completed word
+        if !matches!(code, KeyCode::Tab | KeyCode::BackTab) {
+            self.completion = None;
+        }
+        match code {
+            KeyCode::Enter => {
+                let line = self.input.submit();
+                if !line.trim().is_empty() {
+                    return Some(line);
+                }
+            }
+            KeyCode::Backspace => self.input.backspace(),
+            KeyCode::Delete => self.input.delete(),
+            KeyCode::Left if control => self.input.move_word_left(),
+            KeyCode::Right if control => self.input.move_word_right(),
+            KeyCode::Left => self.input.move_left(),
+            KeyCode::Right => self.input.move_right(),
+            KeyCode::Home => self.input.move_home(),
+            KeyCode::End => self.input.move_end(),
+            KeyCode::Char('a') if control => self.input.move_home(),
+            KeyCode::Char('e') if control => self.input.move_end(),
+            KeyCode::Char('w') if control => self.input.delete_word(),
+            KeyCode::Char('u') if control => self.input.delete_to_start(),
+            KeyCode::Up => self.input.history_previous(),
+            KeyCode::Down => self.input.history_next(),
+            KeyCode::PageUp => {
+                self.scrollback
+                    .scroll_up(page, self.message_width(), self.message_height())
+            }
+            KeyCode::PageDown => self.scrollback.scroll_down(page),
+            KeyCode::Tab => self.complete(false),
+            KeyCode::BackTab => self.complete(true),
+            KeyCode::Char(c @ '1'..='9') if alt => return Some(format!("/switch {}", c)),
+            KeyCode::Char(c) if !control && !alt => self.input.insert(c),
+            _ => (),
+        }
+        None
+    }
+
+    pub fn handle_input(&mut self) -> io::Result<Option<String>> {
+        if let Some(plain_input) = &self.plain_input {
+            return Ok(plain_input.recv_timeout(Duration::from_millis(100)).ok());
+        }
+        if event::poll(Duration::from_millis(100))? {
+            match event::read()? {
+                Event::Key(KeyEvent {
+                    code,
+                    modifiers,
+                    kind: KeyEventKind::Press | KeyEventKind::Repeat,
+                    ..
+                }) => {
+                    let line = self.handle_key(code, modifiers);
+                    self.render()?;
+                    return Ok(line);
+                }
+                Event::Resize(width, height) => {
+                    // Every row is drawn again, the terminal may have moved the old ones
+                    self.terminal_width = width;
+                    self.terminal_height = height;
+                    self.screen.clear();
+                    self.render()?;
+                }
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+}
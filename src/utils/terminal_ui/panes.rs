@@ -0,0 +1,254 @@
+use std::time::Duration;
+
+use crossterm::style::Stylize;
+use unicode_width::UnicodeWidthChar;
+
+use super::scrollback::visible_width;
+
+/// Columns of the room list and of the member list, without the separator
+const SIDEBAR_WIDTH: usize = 18;
+
+/// Narrowest message pane shown next to a sidebar, narrower terminals leave the sidebars out
+const MIN_MESSAGES_WIDTH: usize = 40;
+
+const SEPARATOR: char = '│';
+
+/// Room in the room list
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoomEntry {
+    pub name: String,
+    pub joined: bool,
+
+    /// Messages received in the room since it was shown last
+    pub unread: usize,
+}
+
+/// What is shown around the messages: the rooms, the members of the room and the status bar
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Panes {
+    /// The joined rooms come first, in the order they were joined, and are numbered for Alt+number
+    pub rooms: Vec<RoomEntry>,
+    pub members: Vec<String>,
+    pub connection: String,
+    pub latency: Option<Duration>,
+    pub room: Option<String>,
+    pub user_name: String,
+}
+
+/// Columns of every pane, 0 for a pane that is left out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    pub rooms_width: usize,
+    pub messages_width: usize,
+    pub members_width: usize,
+}
+
+impl Layout {
+    /// Fits the panes in the given columns, the member list is left out first and then the room list
+    pub fn for_width(width: usize) -> Self {
+        let sidebar = SIDEBAR_WIDTH + 1;
+        let (rooms_width, members_width) = if width >= MIN_MESSAGES_WIDTH + 2 * sidebar {
+            (SIDEBAR_WIDTH, SIDEBAR_WIDTH)
+        } else if width >= MIN_MESSAGES_WIDTH + sidebar {
+            (SIDEBAR_WIDTH, 0)
+        } else {
+            (0, 0)
+        };
+        let separators = (rooms_width > 0) as usize + (members_width > 0) as usize;
+        Self {
+            rooms_width,
+            messages_width: width.saturating_sub(rooms_width + members_width + separators),
+            members_width,
+        }
+    }
+}
+
+/// Cuts the plain text to the given columns, and fills up the rest with spaces
+pub fn fit(text: &str, width: usize) -> String {
+    let mut fitted = String::new();
+    let mut fitted_width = 0;
+    let text_width: usize = text.chars().map(|c| c.width().unwrap_or(0)).sum();
+    for c in text.chars() {
+        let char_width = c.width().unwrap_or(0);
+        // Leave a column for the ellipsis when the text is cut
+        let limit = if text_width > width {
+            width.saturating_sub(1)
+        } else {
+            width
+        };
+        if fitted_width + char_width > limit {
+            fitted.push('…');
+            fitted_width += 1;
+            break;
+        }
+        fitted.push(c);
+        fitted_width += char_width;
+    }
+    fitted.push_str(&" ".repeat(width.saturating_sub(fitted_width)));
+    fitted
+}
+
+/// Fills up a row of the messages to the given columns, the colors of the row take no space
+fn pad(row: &str, width: usize) -> String {
+    format!(
+        "{}{}",
+        row,
+        " ".repeat(width.saturating_sub(visible_width(row)))
+    )
+}
+
+fn separator() -> String {
+    SEPARATOR.dark_grey().to_string()
+}
+
+impl Panes {
+    fn room_rows(&self, width: usize, height: usize) -> Vec<String> {
+        let mut rows = vec![fit("Rooms", width).bold().to_string()];
+        for (index, room) in self.rooms.iter().enumerate() {
+            let label = match room.joined {
+                true => format!("{} {}", index + 1, room.name),
+                false => format!("  {}", room.name),
+            };
+
+            // The unread count is never cut off, the name is cut instead
+            let unread = match room.unread {
+                0 => String::new(),
+                unread => format!(" {}", unread),
+            };
+            let row = format!(
+                "{}{}",
+                fit(&label, width.saturating_sub(unread.len())),
+                unread.bold()
+            );
+            rows.push(if self.room.as_ref() == Some(&room.name) {
+                row.reverse().to_string()
+            } else {
+                row
+            });
+        }
+        rows.resize(height, " ".repeat(width));
+        rows.truncate(height);
+        rows
+    }
+
+    fn member_rows(&self, width: usize, height: usize) -> Vec<String> {
+        let title = format!("Members ({})", self.members.len());
+        let mut rows = vec![fit(&title, width).bold().to_string()];
+        for member in &self.members {
+            let row = fit(&format!(" {}", member), width);
+            rows.push(if *member == self.user_name {
+                row.italic().to_string()
+            } else {
+                row
+            });
+        }
+        rows.resize(height, " ".repeat(width));
+        rows.truncate(height);
+        rows
+    }
+
+    /// Puts the sidebars around the rows of the messages
+    pub fn body(&self, layout: &Layout, messages: &[String]) -> Vec<String> {
+        let height = messages.len();
+        let room_rows = self.room_rows(layout.rooms_width, height);
+        let member_rows = self.member_rows(layout.members_width, height);
+
+        messages
+            .iter()
+            .enumerate()
+            .map(|(index, message)| {
+                let mut row = String::new();
+                if layout.rooms_width > 0 {
+                    row.push_str(&room_rows[index]);
+                    row.push_str(&separator());
+                }
+                if layout.members_width > 0 {
+                    row.push_str(&pad(message, layout.messages_width));
+                    row.push_str(&separator());
+                    row.push_str(&member_rows[index]);
+                } else {
+                    row.push_str(message);
+                }
+                row
+            })
+            .collect()
+    }
+
+    /// Status bar with the connection, the latency, the room and the name of the user
+    pub fn status_bar(&self, width: usize) -> String {
+        let latency = match self.latency {
+            Some(latency) => format!("{} ms", latency.as_millis()),
+            None => "-- ms".to_string(),
+        };
+        let room = self.room.as_deref().unwrap_or("no room");
+        let status = format!(
+            " {} | {} | {} | {}",
+            self.connection, latency, room, self.user_name
+        );
+        fit(&status, width).reverse().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_for_width() {
+        let wide = Layout::for_width(120);
+        assert_eq!((wide.rooms_width, wide.members_width), (18, 18));
+        assert_eq!(wide.messages_width, 120 - 2 * 19);
+
+        // Narrow terminals leave out the member list, and then the room list
+        let medium = Layout::for_width(70);
+        assert_eq!((medium.rooms_width, medium.members_width), (18, 0));
+        assert_eq!(medium.messages_width, 51);
+        let narrow = Layout::for_width(50);
+        assert_eq!((narrow.rooms_width, narrow.members_width), (0, 0));
+        assert_eq!(narrow.messages_width, 50);
+    }
+
+    #[test]
+    fn test_fit() {
+        assert_eq!(fit("lobby", 8), "lobby   ");
+        assert_eq!(fit("a_long_room_name", 8), "a_long_…");
+        assert_eq!(fit("日本語", 4), "日… ");
+        assert_eq!(pad("\x1b[31mred\x1b[0m", 5), "\x1b[31mred\x1b[0m  ");
+    }
+
+    #[test]
+    fn test_panes_body() {
+        let panes = Panes {
+            rooms: vec![
+                RoomEntry {
+                    name: "lobby".to_string(),
+                    joined: true,
+                    unread: 0,
+                },
+                RoomEntry {
+                    name: "a_long_room_name".to_string(),
+                    joined: true,
+                    unread: 12,
+                },
+            ],
+            members: vec!["alice".to_string(), "bob".to_string()],
+            ..Panes::default()
+        };
+        let layout = Layout::for_width(100);
+        let messages = ["hello".to_string(), String::new(), String::new()];
+        let body = panes.body(&layout, &messages);
+        assert_eq!(body.len(), 3);
+        for row in &body {
+            assert_eq!(visible_width(row), 100);
+        }
+        assert!(body[1].contains("1 lobby"));
+
+        // The unread count is kept when the name is cut
+        assert!(body[2].contains("2 a_long_room_…"));
+        assert!(body[2].contains("12"));
+
+        // Without sidebars the rows of the messages are left as they are
+        let body = panes.body(&Layout::for_width(30), &["hello".to_string()]);
+        assert_eq!(body, ["hello"]);
+    }
+}
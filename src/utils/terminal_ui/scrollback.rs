@@ -0,0 +1,203 @@
+use std::collections::VecDeque;
+
+use unicode_width::UnicodeWidthChar;
+
+/// Number of messages kept for scrolling back
+const SCROLLBACK_SIZE: usize = 1000;
+
+const RESET_STYLE: &str = "\x1b[0m";
+
+/**
+ * Splits a message into rows of the given width
+ *
+ * Escape sequences of the colors take no space. A row that ends inside a colored part resets the style,
+ * and the next row starts with the style again, so every row can be drawn on its own.
+ */
+pub fn wrap_message(message: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut rows = Vec::new();
+
+    // Escape sequences in effect since the last reset
+    let mut style = String::new();
+    for line in message.split('\n') {
+        let mut row = style.clone();
+        let mut row_width = 0;
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c == '\x1b' {
+                let mut sequence = String::from(c);
+                if chars.peek() == Some(&'[') {
+                    // Parameters up to the final byte of the sequence
+                    for c in chars.by_ref() {
+                        sequence.push(c);
+                        if c != '[' && ('\x40'..='\x7e').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                if sequence == RESET_STYLE || sequence == "\x1b[m" {
+                    style.clear();
+                } else {
+                    style.push_str(&sequence);
+                }
+                row.push_str(&sequence);
+                continue;
+            }
+            if c.is_control() {
+                continue;
+            }
+
+            let char_width = c.width().unwrap_or(0);
+            if row_width + char_width > width && row_width > 0 {
+                rows.push(end_row(row, &style));
+                row = style.clone();
+                row_width = 0;
+            }
+            row.push(c);
+            row_width += char_width;
+        }
+        rows.push(end_row(row, &style));
+    }
+    rows
+}
+
+/// Columns taken by the text in the terminal, escape sequences of the colors take no space
+pub fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut in_sequence = false;
+    for c in text.chars() {
+        if c == '\x1b' {
+            in_sequence = true;
+        } else if in_sequence {
+            in_sequence = c == '[' || !('\x40'..='\x7e').contains(&c);
+        } else {
+            width += c.width().unwrap_or(0);
+        }
+    }
+    width
+}
+
+fn end_row(mut row: String, style: &str) -> String {
+    if !style.is_empty() {
+        row.push_str(RESET_STYLE);
+    }
+    row
+}
+
+/// Messages shown in the terminal, and how far the user scrolled back
+#[derive(Debug, Default)]
+pub struct Scrollback {
+    messages: VecDeque<String>,
+
+    // Rows scrolled up from the newest message, 0 follows the new messages
+    offset: usize,
+}
+
+impl Scrollback {
+    /// Adds a message, the rows shown stay the same while scrolled back
+    pub fn push(&mut self, message: String, width: usize) {
+        if self.offset > 0 {
+            self.offset += wrap_message(&message, width).len();
+        }
+        if self.messages.len() == SCROLLBACK_SIZE {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(message);
+    }
+
+    pub fn is_scrolled(&self) -> bool {
+        self.offset > 0
+    }
+
+    /// Scrolls back, up to the oldest message
+    pub fn scroll_up(&mut self, rows: usize, width: usize, height: usize) {
+        let total_rows: usize = self
+            .messages
+            .iter()
+            .map(|message| wrap_message(message, width).len())
+            .sum();
+        self.offset = (self.offset + rows).min(total_rows.saturating_sub(height));
+    }
+
+    /// Scrolls forward, up to the newest message
+    pub fn scroll_down(&mut self, rows: usize) {
+        self.offset = self.offset.saturating_sub(rows);
+    }
+
+    /// Rows shown in the given space, the oldest first
+    pub fn rows(&self, width: usize, height: usize) -> Vec<String> {
+        // Only the newest messages are wrapped, as many as needed to fill the space
+        let mut rows = VecDeque::new();
+        for message in self.messages.iter().rev() {
+            if rows.len() >= self.offset + height {
+                break;
+            }
+            for row in wrap_message(message, width).into_iter().rev() {
+                rows.push_front(row);
+            }
+        }
+
+        let end = rows.len().saturating_sub(self.offset);
+        let start = end.saturating_sub(height);
+        rows.range(start..end).cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wrap_message() {
+        assert_eq!(wrap_message("hello world", 5), ["hello", " worl", "d"]);
+        assert_eq!(wrap_message("help\r\n    usage", 20), ["help", "    usage"]);
+        assert_eq!(wrap_message("", 5), [""]);
+        assert_eq!(wrap_message("日本語", 4), ["日本", "語"]);
+        assert_eq!(visible_width("\x1b[1;31m日本\x1b[0m ok"), 7);
+
+        // The color is ended on the first row and set again on the next
+        let red = "\x1b[31m";
+        assert_eq!(
+            wrap_message(&format!("{red}abcdef{RESET_STYLE} o"), 4),
+            [
+                format!("{red}abcd{RESET_STYLE}"),
+                format!("{red}ef{RESET_STYLE} o")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_scroll_back() {
+        let mut scrollback = Scrollback::default();
+        for i in 0..10 {
+            scrollback.push(format!("message {}", i), 20);
+        }
+        assert_eq!(
+            scrollback.rows(20, 3),
+            ["message 7", "message 8", "message 9"]
+        );
+
+        scrollback.scroll_up(5, 20, 3);
+        assert_eq!(
+            scrollback.rows(20, 3),
+            ["message 2", "message 3", "message 4"]
+        );
+
+        // New messages do not move the rows shown
+        scrollback.push("message 10".to_string(), 20);
+        assert_eq!(
+            scrollback.rows(20, 3),
+            ["message 2", "message 3", "message 4"]
+        );
+
+        // Scrolling stops at the oldest and the newest message
+        scrollback.scroll_up(100, 20, 3);
+        assert_eq!(
+            scrollback.rows(20, 3),
+            ["message 0", "message 1", "message 2"]
+        );
+        scrollback.scroll_down(100);
+        assert!(!scrollback.is_scrolled());
+        assert_eq!(scrollback.rows(20, 3)[2], "message 10");
+    }
+}
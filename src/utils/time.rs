@@ -15,3 +15,9 @@ pub fn get_time_string() -> String {
         datetime.format("%S")
     );
 }
+
+/// Local date and time, for what is kept longer than a day
+pub fn get_date_time_string() -> String {
+    let datetime: DateTime<Local> = SystemTime::now().into();
+    return datetime.format("%Y-%m-%d %H:%M:%S").to_string();
+}
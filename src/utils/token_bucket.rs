@@ -0,0 +1,95 @@
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+/// Size and refill rate of a token bucket
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BucketConfig {
+    /// Max amount of messages that can be sent in a burst
+    pub capacity: f64,
+
+    /// Amount of messages per second that can be sent over time
+    pub refill_per_sec: f64,
+}
+
+impl BucketConfig {
+    /// Checks that at least one message can be sent, and that the bucket refills
+    pub fn validate(&self, name: &str) -> Result<(), String> {
+        if !(self.capacity >= 1.0 && self.capacity.is_finite()) {
+            return Err(format!("rate_limit.{}.capacity must be at least 1", name));
+        }
+        if !(self.refill_per_sec > 0.0 && self.refill_per_sec.is_finite()) {
+            return Err(format!(
+                "rate_limit.{}.refill_per_sec must be more than 0",
+                name
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Token bucket that refills over time
+///
+/// The size and rate is given on every call, so that changes in the config are used right away
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    tokens: Option<f64>,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Creates a full bucket
+    pub fn new(now: Instant) -> Self {
+        Self {
+            tokens: None,
+            last_refill: now,
+        }
+    }
+
+    /// Takes a token from the bucket, returns false if the bucket was empty
+    pub fn try_take(&mut self, config: &BucketConfig, now: Instant) -> bool {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        let tokens = self.tokens.unwrap_or(config.capacity) + elapsed * config.refill_per_sec;
+        let tokens = tokens.min(config.capacity);
+        self.last_refill = now;
+
+        if tokens >= 1.0 {
+            self.tokens = Some(tokens - 1.0);
+            true
+        } else {
+            self.tokens = Some(tokens);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_bucket_allows_burst_then_refills() {
+        let bucket_config = BucketConfig {
+            capacity: 2.0,
+            refill_per_sec: 1.0,
+        };
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(now);
+
+        assert!(bucket.try_take(&bucket_config, now));
+        assert!(bucket.try_take(&bucket_config, now));
+        assert!(!bucket.try_take(&bucket_config, now));
+
+        // One token is back after a second, but never more than the capacity
+        assert!(bucket.try_take(&bucket_config, now + Duration::from_secs(1)));
+        assert!(!bucket.try_take(&bucket_config, now + Duration::from_secs(1)));
+        let later = now + Duration::from_secs(60);
+        assert!(bucket.try_take(&bucket_config, later));
+        assert!(bucket.try_take(&bucket_config, later));
+        assert!(!bucket.try_take(&bucket_config, later));
+    }
+}
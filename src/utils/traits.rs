@@ -1,4 +1,4 @@
-use actix_ws::Session;
+use crate::core::{frame::Frame, outbox::Outbox};
 use async_trait::async_trait;
 use serde::Serialize;
 
@@ -7,20 +7,23 @@ pub trait JsonSerializing: Serialize + Send + Sync {
     async fn serialized(&self) -> Option<String> {
         serde_json::to_string(self).ok()
     }
+
+    /// Serializes the message to a frame, that can be queued for many sessions
+    fn frame(&self) -> Option<Frame> {
+        Frame::json(self)
+    }
 }
 
 #[async_trait]
 pub trait SendServerReply: JsonSerializing {
-    async fn send(&self, session: &mut Session) {
-        match self.serialized().await {
+    async fn send(&self, outbox: &Outbox) {
+        match self.frame() {
             Some(reply) => {
-                // Send the serialized message as a WebSocket text message
-                if let Err(err) = session.text(reply).await {
-                    eprintln!("Failed to send message: {}", err);
-                }
+                // Queue the serialized message as a WebSocket text message
+                outbox.push(reply);
             }
             None => {
-                eprintln!("Failed to serialize the struct");
+                tracing::error!("Failed to serialize the server message");
             }
         }
     }